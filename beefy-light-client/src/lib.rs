@@ -0,0 +1,258 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
+//! IBC light client of a chain finalized by BEEFY.
+//!
+//! The client tracks the chain's BEEFY validator set. An update carries a commitment to the
+//! chain's MMR root signed by that set, and the proof of a block's leaf in that MMR: the block is
+//! then known final, and its state root is what the chain's IBC proofs are checked against. The
+//! update also proves the next validator set in the block's state, which the client switches to
+//! once a commitment is signed by it.
+//!
+//! The leaves of the MMR are the ones of `pallet-mmr-leaf`, the leaf of block `n` at index `n`
+//! committing the block's number and hash.
+//!
+//! States and messages are SCALE encoded under the type URLs below, for the IBC pallet of the
+//! counterparty to route to this client. pallet-ibc does not route them yet, so no chain is
+//! tracked by this client so far.
+
+use beefy_primitives::{
+	crypto::{AuthorityId, Signature},
+	MmrRootHash, SignedCommitment, ValidatorSet, ValidatorSetId,
+};
+use codec::{Decode, Encode};
+use pallet_mmr::primitives::{DataOrHash, Proof};
+use pallet_mmr_leaf::MmrLeaf;
+use sp_core::{ecdsa, H256};
+use sp_runtime::{
+	generic,
+	traits::{BlakeTwo256, Hash, Header as _, Keccak256},
+};
+use sp_std::prelude::*;
+use sp_trie::StorageProof;
+
+#[cfg(test)]
+mod tests;
+
+pub const CLIENT_TYPE: &str = "beefy";
+pub const CLIENT_STATE_TYPE_URL: &str = "/ibc.lightclients.beefy.v1.ClientState";
+pub const CONSENSUS_STATE_TYPE_URL: &str = "/ibc.lightclients.beefy.v1.ConsensusState";
+pub const UPDATE_CLIENT_TYPE_URL: &str = "/ibc.lightclients.beefy.v1.MsgUpdateClient";
+
+/// The block number of the chain tracked.
+pub type BlockNumber = u32;
+/// The header of a block of the chain tracked.
+pub type BlockHeader = generic::Header<BlockNumber, BlakeTwo256>;
+
+/// The state of the client.
+#[derive(Clone, Debug, PartialEq, Eq, Encode, Decode)]
+pub struct ClientState {
+	pub chain_id: Vec<u8>,
+	/// The number of the latest block the client has the consensus state of.
+	pub latest_height: BlockNumber,
+	/// The block number of the latest commitment verified.
+	pub latest_commitment: BlockNumber,
+	/// The MMR root of the latest commitment verified.
+	pub mmr_root: MmrRootHash,
+	/// The validator set the commitments are signed by.
+	pub validator_set: ValidatorSet<AuthorityId>,
+	/// The validators of the set after `validator_set`.
+	pub next_validators: Vec<AuthorityId>,
+	/// The height the client was frozen at by misbehaviour, if it was.
+	pub frozen_height: Option<BlockNumber>,
+}
+
+/// The state of the chain at a block the client verified.
+#[derive(Clone, Debug, PartialEq, Eq, Encode, Decode)]
+pub struct ConsensusState {
+	pub block_hash: H256,
+	/// The state root of the block, which proofs of the chain's IBC state are checked against.
+	pub root: H256,
+}
+
+/// An update of the client to a block.
+#[derive(Clone, Debug, PartialEq, Eq, Encode, Decode)]
+pub struct Header {
+	/// A commitment to the chain's MMR root, signed by the current or the next validator set.
+	pub signed_commitment: SignedCommitment<BlockNumber, MmrRootHash>,
+	/// The header of the block.
+	pub block_header: BlockHeader,
+	/// The block's leaf in the MMR committed to.
+	pub mmr_leaf: MmrLeaf<BlockNumber, H256>,
+	/// The proof of `mmr_leaf` in the MMR committed to.
+	pub mmr_proof: Proof<MmrRootHash>,
+	/// The proof of `Beefy::ValidatorSetId` and `Beefy::NextAuthorities` in the block's state.
+	pub authorities_proof: Vec<Vec<u8>>,
+}
+
+/// The message updating client `client_id` with `header`.
+#[derive(Clone, Debug, PartialEq, Eq, Encode, Decode)]
+pub struct MsgUpdateClient {
+	pub client_id: Vec<u8>,
+	pub header: Header,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Error {
+	/// The client is frozen.
+	Frozen,
+	/// The commitment is older than the latest one verified.
+	StaleCommitment,
+	/// The commitment is signed by neither the current nor the next validator set.
+	UnknownValidatorSet(ValidatorSetId),
+	/// The commitment is not signed by more than two thirds of its validator set.
+	NotEnoughSignatures,
+	/// The leaf index of the MMR proof is not the block's number.
+	LeafIndexMismatch,
+	/// The leaf does not commit the block's number and hash.
+	LeafMismatch,
+	/// The block is not in the MMR committed to.
+	InvalidMmrProof,
+	/// The authorities proof does not prove the validator sets in the block's state.
+	InvalidAuthoritiesProof,
+}
+
+impl ClientState {
+	/// The state of a client tracking `chain_id` from block `latest_height`, whose validator set
+	/// is `validator_set` and the next one `next_validators`.
+	pub fn new(
+		chain_id: Vec<u8>,
+		latest_height: BlockNumber,
+		validator_set: ValidatorSet<AuthorityId>,
+		next_validators: Vec<AuthorityId>,
+	) -> Self {
+		ClientState {
+			chain_id,
+			latest_height,
+			latest_commitment: 0,
+			mmr_root: Default::default(),
+			validator_set,
+			next_validators,
+			frozen_height: None,
+		}
+	}
+
+	/// Verify `header` and update the client with it, returning the consensus state of its block.
+	pub fn update(&mut self, header: &Header) -> Result<ConsensusState, Error> {
+		if self.frozen_height.is_some() {
+			return Err(Error::Frozen)
+		}
+		let commitment = &header.signed_commitment.commitment;
+		if commitment.block_number < self.latest_commitment {
+			return Err(Error::StaleCommitment)
+		}
+		let rotates = if commitment.validator_set_id == self.validator_set.id {
+			false
+		} else if commitment.validator_set_id == self.validator_set.id + 1 {
+			true
+		} else {
+			return Err(Error::UnknownValidatorSet(commitment.validator_set_id))
+		};
+		let validators =
+			if rotates { &self.next_validators } else { &self.validator_set.validators };
+		verify_signatures(&header.signed_commitment, validators)?;
+
+		let block_hash = header.block_header.hash();
+		if header.mmr_proof.leaf_index != header.block_header.number as u64 {
+			return Err(Error::LeafIndexMismatch)
+		}
+		if header.mmr_leaf.parent_number_and_hash != (header.block_header.number, block_hash) {
+			return Err(Error::LeafMismatch)
+		}
+		pallet_mmr::verify_leaf_proof::<Keccak256, _>(
+			commitment.payload,
+			DataOrHash::Data(header.mmr_leaf.clone()),
+			header.mmr_proof.clone(),
+		)
+		.map_err(|_| Error::InvalidMmrProof)?;
+
+		let (set_id, next_validators) =
+			read_authorities(&header.block_header.state_root, &header.authorities_proof)?;
+
+		if rotates {
+			self.validator_set = ValidatorSet {
+				validators: self.next_validators.clone(),
+				id: commitment.validator_set_id,
+			};
+		}
+		// Past a change of set, the block states the set after the one now tracked.
+		if set_id == self.validator_set.id {
+			self.next_validators = next_validators;
+		}
+		self.latest_commitment = commitment.block_number;
+		self.mmr_root = commitment.payload;
+		self.latest_height = self.latest_height.max(header.block_header.number);
+
+		Ok(ConsensusState { block_hash, root: header.block_header.state_root })
+	}
+
+	/// Freeze the client at `height`, after misbehaviour of the chain tracked.
+	pub fn freeze(&mut self, height: BlockNumber) {
+		self.frozen_height = Some(height);
+	}
+}
+
+/// The storage key of `Beefy::ValidatorSetId`.
+pub fn validator_set_id_key() -> Vec<u8> {
+	storage_key(b"Beefy", b"ValidatorSetId")
+}
+
+/// The storage key of `Beefy::NextAuthorities`.
+pub fn next_authorities_key() -> Vec<u8> {
+	storage_key(b"Beefy", b"NextAuthorities")
+}
+
+fn storage_key(pallet: &[u8], item: &[u8]) -> Vec<u8> {
+	let mut key = sp_io::hashing::twox_128(pallet).to_vec();
+	key.extend_from_slice(&sp_io::hashing::twox_128(item));
+	key
+}
+
+/// Check that more than two thirds of `validators` signed the commitment, each signature in the
+/// position of its validator.
+fn verify_signatures(
+	signed_commitment: &SignedCommitment<BlockNumber, MmrRootHash>,
+	validators: &[AuthorityId],
+) -> Result<(), Error> {
+	let hash = Keccak256::hash(&signed_commitment.commitment.encode());
+	let signed = validators
+		.iter()
+		.zip(signed_commitment.signatures.iter())
+		.filter(|(validator, signature)| match signature {
+			Some(signature) => signed_by(hash.as_fixed_bytes(), signature, validator),
+			None => false,
+		})
+		.count();
+	if signed * 3 > validators.len() * 2 {
+		Ok(())
+	} else {
+		Err(Error::NotEnoughSignatures)
+	}
+}
+
+fn signed_by(hash: &[u8; 32], signature: &Signature, validator: &AuthorityId) -> bool {
+	let signature: &ecdsa::Signature = signature.as_ref();
+	let validator: &ecdsa::Public = validator.as_ref();
+	sp_io::crypto::secp256k1_ecdsa_recover_compressed(&signature.0, hash)
+		.map(|recovered| recovered == validator.0)
+		.unwrap_or(false)
+}
+
+/// The validator set id and the next validators stated by `proof` in the state of `state_root`.
+fn read_authorities(
+	state_root: &H256,
+	proof: &[Vec<u8>],
+) -> Result<(ValidatorSetId, Vec<AuthorityId>), Error> {
+	let db = StorageProof::new(proof.to_vec()).into_memory_db::<BlakeTwo256>();
+	let read = |key: &[u8]| {
+		sp_trie::read_trie_value::<sp_trie::Layout<BlakeTwo256>, _>(&db, state_root, key)
+			.ok()
+			.flatten()
+	};
+	let set_id = read(&validator_set_id_key())
+		.and_then(|value| ValidatorSetId::decode(&mut &value[..]).ok())
+		.ok_or(Error::InvalidAuthoritiesProof)?;
+	let next_validators = read(&next_authorities_key())
+		.and_then(|value| Vec::<AuthorityId>::decode(&mut &value[..]).ok())
+		.ok_or(Error::InvalidAuthoritiesProof)?;
+	Ok((set_id, next_validators))
+}
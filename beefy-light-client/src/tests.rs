@@ -0,0 +1,187 @@
+use crate::*;
+use beefy_primitives::Commitment;
+use sp_core::Pair;
+use sp_runtime::traits::Header as _;
+use sp_trie::{MemoryDB, TrieMut};
+
+fn validators(seeds: &[&str]) -> Vec<ecdsa::Pair> {
+	seeds.iter().map(|seed| ecdsa::Pair::from_string(seed, None).unwrap()).collect()
+}
+
+fn authority_ids(pairs: &[ecdsa::Pair]) -> Vec<AuthorityId> {
+	pairs.iter().map(|pair| AuthorityId::from(pair.public())).collect()
+}
+
+/// A state stating `set_id` and `next_validators`, with the proof of both.
+fn state(set_id: ValidatorSetId, next_validators: &[AuthorityId]) -> (H256, Vec<Vec<u8>>) {
+	let mut db = MemoryDB::<BlakeTwo256>::default();
+	let mut root = H256::default();
+	{
+		let mut trie = sp_trie::TrieDBMut::<sp_trie::Layout<BlakeTwo256>>::new(&mut db, &mut root);
+		trie.insert(&validator_set_id_key(), &set_id.encode()).unwrap();
+		trie.insert(&next_authorities_key(), &next_validators.encode()).unwrap();
+	}
+	let proof = db.drain().into_iter().map(|(_, (node, _))| node).collect();
+	(root, proof)
+}
+
+/// The header of block 0, proven by its leaf in the single leaf MMR and committed to at block 1 by set
+/// `set_id`, with signatures of `signers` out of `validators`.
+fn header(
+	set_id: ValidatorSetId,
+	validators: &[ecdsa::Pair],
+	signers: usize,
+	next_validators: &[AuthorityId],
+) -> Header {
+	let (state_root, authorities_proof) = state(set_id, next_validators);
+	let block_header =
+		BlockHeader::new(0, Default::default(), state_root, Default::default(), Default::default());
+	let mmr_leaf = MmrLeaf {
+		parent_number_and_hash: (0, block_header.hash()),
+		beefy_next_authority_set: Default::default(),
+		parent_state_root: H256::zero(),
+	};
+	let mmr_root = DataOrHash::<Keccak256, _>::Data(mmr_leaf.clone()).hash();
+	let commitment = Commitment { payload: mmr_root, block_number: 1, validator_set_id: set_id };
+	let hash = Keccak256::hash(&commitment.encode());
+	let signatures = validators
+		.iter()
+		.enumerate()
+		.map(|(i, pair)| {
+			if i < signers {
+				Some(Signature::from(pair.sign_prehashed(hash.as_fixed_bytes())))
+			} else {
+				None
+			}
+		})
+		.collect();
+	Header {
+		signed_commitment: SignedCommitment { commitment, signatures },
+		block_header,
+		mmr_leaf,
+		mmr_proof: Proof { leaf_index: 0, leaf_count: 1, items: vec![] },
+		authorities_proof,
+	}
+}
+
+fn client_state(set: &[ecdsa::Pair], next: &[ecdsa::Pair]) -> ClientState {
+	ClientState::new(
+		b"appia".to_vec(),
+		0,
+		ValidatorSet { validators: authority_ids(set), id: 0 },
+		authority_ids(next),
+	)
+}
+
+#[test]
+fn update_verifies_a_header_under_a_signed_commitment() {
+	let set = validators(&["//Alice", "//Bob", "//Charlie", "//Dave"]);
+	let mut client = client_state(&set, &set);
+	let header = header(0, &set, 3, &authority_ids(&set));
+
+	let consensus_state = client.update(&header).unwrap();
+	assert_eq!(consensus_state.block_hash, header.block_header.hash());
+	assert_eq!(consensus_state.root, header.block_header.state_root);
+	assert_eq!(client.latest_commitment, 1);
+	assert_eq!(client.mmr_root, header.signed_commitment.commitment.payload);
+}
+
+#[test]
+fn update_requires_more_than_two_thirds_of_signatures() {
+	let set = validators(&["//Alice", "//Bob", "//Charlie"]);
+	let mut client = client_state(&set, &set);
+
+	assert_eq!(
+		client.update(&header(0, &set, 2, &authority_ids(&set))),
+		Err(Error::NotEnoughSignatures)
+	);
+	assert!(client.update(&header(0, &set, 3, &authority_ids(&set))).is_ok());
+}
+
+#[test]
+fn update_rejects_signatures_of_other_validators() {
+	let set = validators(&["//Alice", "//Bob", "//Charlie"]);
+	let others = validators(&["//Dave", "//Eve", "//Ferdie"]);
+	let mut client = client_state(&set, &set);
+
+	assert_eq!(
+		client.update(&header(0, &others, 3, &authority_ids(&set))),
+		Err(Error::NotEnoughSignatures)
+	);
+}
+
+#[test]
+fn update_rejects_a_block_not_in_the_mmr() {
+	let set = validators(&["//Alice"]);
+	let mut client = client_state(&set, &set);
+	let mut header = header(0, &set, 1, &authority_ids(&set));
+	header.mmr_leaf.parent_state_root = H256::repeat_byte(1);
+
+	assert_eq!(client.update(&header), Err(Error::InvalidMmrProof));
+
+	header.block_header.number = 1;
+	header.mmr_leaf.parent_number_and_hash = (1, header.block_header.hash());
+	assert_eq!(client.update(&header), Err(Error::LeafIndexMismatch));
+
+	header.mmr_proof.leaf_index = 1;
+	header.mmr_proof.leaf_count = 2;
+	assert_eq!(client.update(&header), Err(Error::InvalidMmrProof));
+}
+
+#[test]
+fn update_rejects_a_leaf_of_another_block() {
+	let set = validators(&["//Alice"]);
+	let mut client = client_state(&set, &set);
+	let mut header = header(0, &set, 1, &authority_ids(&set));
+	header.block_header.digest.push(sp_runtime::generic::DigestItem::Other(vec![1]));
+
+	assert_eq!(client.update(&header), Err(Error::LeafMismatch));
+}
+
+#[test]
+fn update_rejects_an_invalid_authorities_proof() {
+	let set = validators(&["//Alice"]);
+	let mut client = client_state(&set, &set);
+	let mut header = header(0, &set, 1, &authority_ids(&set));
+	header.authorities_proof.clear();
+
+	assert_eq!(client.update(&header), Err(Error::InvalidAuthoritiesProof));
+}
+
+#[test]
+fn update_rejects_stale_commitments_and_frozen_clients() {
+	let set = validators(&["//Alice"]);
+	let mut client = client_state(&set, &set);
+	client.latest_commitment = 2;
+
+	assert_eq!(
+		client.update(&header(0, &set, 1, &authority_ids(&set))),
+		Err(Error::StaleCommitment)
+	);
+
+	client.latest_commitment = 0;
+	client.freeze(1);
+	assert_eq!(client.update(&header(0, &set, 1, &authority_ids(&set))), Err(Error::Frozen));
+}
+
+#[test]
+fn update_switches_to_the_next_validator_set() {
+	let set = validators(&["//Alice", "//Bob"]);
+	let next = validators(&["//Charlie", "//Dave"]);
+	let after = validators(&["//Eve"]);
+	let mut client = client_state(&set, &next);
+
+	assert_eq!(
+		client.update(&header(2, &next, 2, &authority_ids(&after))),
+		Err(Error::UnknownValidatorSet(2))
+	);
+	assert_eq!(
+		client.update(&header(1, &set, 2, &authority_ids(&after))),
+		Err(Error::NotEnoughSignatures)
+	);
+
+	client.update(&header(1, &next, 2, &authority_ids(&after))).unwrap();
+	assert_eq!(client.validator_set.id, 1);
+	assert_eq!(client.validator_set.validators, authority_ids(&next));
+	assert_eq!(client.next_validators, authority_ids(&after));
+}
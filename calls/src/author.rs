@@ -0,0 +1,39 @@
+//! Implements support for the `author_submitAndWatchExtrinsic` RPC method of `sc-rpc`, which
+//! reports every status of an extrinsic in the pool, not only the block that finalizes it.
+use async_trait::async_trait;
+use codec::Encode;
+use jsonrpsee_types::Subscription;
+use serde_json::to_value as to_json_value;
+use sp_core::Bytes;
+use sp_transaction_pool::TransactionStatus;
+use substrate_subxt::{system::System, Client, Error, Runtime};
+
+/// Submission of signed extrinsics to the node's transaction pool.
+#[async_trait]
+pub trait AuthorRpcExt<T: System> {
+    /// Submit `extrinsic` to the pool, and subscribe to its status until it is finalized or
+    /// leaves the pool. Returns once the pool accepted it.
+    async fn submit_and_watch<E: Encode + Send + Sync>(
+        &self,
+        extrinsic: &E,
+    ) -> Result<Subscription<TransactionStatus<T::Hash, T::Hash>>, Error>;
+}
+
+#[async_trait]
+impl<T: Runtime> AuthorRpcExt<T> for Client<T> {
+    async fn submit_and_watch<E: Encode + Send + Sync>(
+        &self,
+        extrinsic: &E,
+    ) -> Result<Subscription<TransactionStatus<T::Hash, T::Hash>>, Error> {
+        let params = &[to_json_value(Bytes(extrinsic.encode()))?];
+        let subscription = self
+            .rpc_client()
+            .subscribe(
+                "author_submitAndWatchExtrinsic",
+                params,
+                "author_unwatchExtrinsic",
+            )
+            .await?;
+        Ok(subscription)
+    }
+}
@@ -0,0 +1,105 @@
+use crate::signer::KeySource;
+use serde::{de, Deserialize, Deserializer};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+/// The type of the GRANDPA light client, the only one the CLI and the relayer create and update.
+pub const GRANDPA_CLIENT_TYPE: &str = "grandpa";
+
+/// A chain the CLI and the relayer can talk to.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ChainConfig {
+    /// Websocket RPC endpoints, tried in order. `endpoint` takes a single one.
+    #[serde(alias = "endpoint", deserialize_with = "one_or_many")]
+    pub endpoints: Vec<String>,
+    /// Identifier of the light client of this chain on its counterparty.
+    pub client_identifier: String,
+    /// Type of the light client tracking this chain.
+    #[serde(default = "default_client_type")]
+    pub client_type: String,
+    /// IBC chain id, e.g. `appia-0`.
+    pub chain_id: String,
+    /// The key messages to this chain are signed with, `//Alice` if not given.
+    #[serde(default)]
+    pub signer: KeySource,
+    /// The `tmp` argument pallet-ibc's `deliver` expects on this chain.
+    #[serde(default)]
+    pub tmp: u8,
+}
+
+fn default_client_type() -> String {
+    GRANDPA_CLIENT_TYPE.to_string()
+}
+
+fn one_or_many<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<String>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(String),
+        Many(Vec<String>),
+    }
+
+    let endpoints = match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(endpoint) => vec![endpoint],
+        OneOrMany::Many(endpoints) => endpoints,
+    };
+    if endpoints.is_empty() {
+        return Err(de::Error::custom("at least one endpoint is required"));
+    }
+    Ok(endpoints)
+}
+
+impl ChainConfig {
+    /// The preferred endpoint.
+    pub fn endpoint(&self) -> &str {
+        &self.endpoints[0]
+    }
+}
+
+/// The registered chains, keyed by name.
+///
+/// This is the `chains` table of the relayer's config file, so the CLI can read the same file;
+/// any other table in it is ignored.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ChainRegistry {
+    pub chains: HashMap<String, ChainConfig>,
+}
+
+impl ChainRegistry {
+    /// Load the registry from a TOML file, or a JSON file if the path ends in `.json`.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Box<dyn Error>> {
+        let path = path.as_ref();
+        let contents = fs::read_to_string(path)
+            .map_err(|e| format!("can not read {}: {}", path.display(), e))?;
+        parse(path, &contents)
+    }
+
+    /// Look up a chain by name.
+    pub fn get(&self, name: &str) -> Result<&ChainConfig, Box<dyn Error>> {
+        self.chains.get(name).ok_or_else(|| {
+            let mut names: Vec<&str> = self.chains.keys().map(String::as_str).collect();
+            names.sort_unstable();
+            format!(
+                "unknown chain {}, registered chains: {}",
+                name,
+                names.join(", ")
+            )
+            .into()
+        })
+    }
+}
+
+/// Parse a config file holding at least a chain registry, by the file's extension.
+pub fn parse<T: serde::de::DeserializeOwned>(
+    path: &Path,
+    contents: &str,
+) -> Result<T, Box<dyn Error>> {
+    let result = if path.extension().map_or(false, |ext| ext == "json") {
+        serde_json::from_str(contents).map_err(|e| e.to_string())
+    } else {
+        toml::from_str(contents).map_err(|e| e.to_string())
+    };
+    result.map_err(|e| format!("can not parse {}: {}", path.display(), e).into())
+}
@@ -0,0 +1,102 @@
+//! Implements support for the GRANDPA finality RPC methods of `sc-finality-grandpa-rpc`.
+use async_trait::async_trait;
+use codec::Decode;
+use serde_json::to_value as to_json_value;
+use sp_core::Bytes;
+use sp_runtime::traits::{AtLeast32BitUnsigned, Header, One};
+use substrate_subxt::{system::System, Client, Error, Runtime};
+
+#[cfg(test)]
+mod tests;
+
+/// A justification of a finalized block, with the headers from the block asked for up to it, as
+/// `sc_finality_grandpa::FinalityProof` encodes it.
+#[derive(Clone, Debug, Decode)]
+pub struct FinalityProof<Hash, Header> {
+    /// The hash of the block the justification finalizes.
+    pub block: Hash,
+    /// The encoded GRANDPA justification of `block`.
+    pub justification: Vec<u8>,
+    /// The headers between the block asked for and `block`, which the asker may not know.
+    pub unknown_headers: Vec<Header>,
+}
+
+/// Queries of the node's GRANDPA finality proofs.
+#[async_trait]
+pub trait GrandpaRpcExt<T: System> {
+    /// Prove the finality of block `block` with the justification of the last block of the
+    /// authority set that finalized it, or of the latest justified block if that set is the
+    /// current one. `None` if the node has no such justification.
+    async fn prove_finality(
+        &self,
+        block: T::BlockNumber,
+    ) -> Result<Option<FinalityProof<T::Hash, T::Header>>, Error>;
+
+    /// The justified blocks a GRANDPA light client at `latest_height` is updated through to
+    /// reach `target`, each with its justification: the last block of every authority set that
+    /// ends after `latest_height`, then the latest justified block. The path stops short of
+    /// `target` if no block at or after it is justified yet, and is empty if the client is
+    /// already at or past `target`.
+    async fn update_path(
+        &self,
+        latest_height: T::BlockNumber,
+        target: T::BlockNumber,
+    ) -> Result<Vec<(T::BlockNumber, Vec<u8>)>, Error>;
+}
+
+#[async_trait]
+impl<T: Runtime> GrandpaRpcExt<T> for Client<T> {
+    async fn prove_finality(
+        &self,
+        block: T::BlockNumber,
+    ) -> Result<Option<FinalityProof<T::Hash, T::Header>>, Error> {
+        let params = &[to_json_value(block)?];
+        let proof: Option<Bytes> = self
+            .rpc_client()
+            .request("grandpa_proveFinality", params)
+            .await?;
+        match proof {
+            Some(proof) => Ok(Some(FinalityProof::decode(&mut &proof[..])?)),
+            None => Ok(None),
+        }
+    }
+
+    async fn update_path(
+        &self,
+        latest_height: T::BlockNumber,
+        target: T::BlockNumber,
+    ) -> Result<Vec<(T::BlockNumber, Vec<u8>)>, Error> {
+        let mut path = vec![];
+        let mut next = match first_unproven(latest_height, target) {
+            Some(next) => next,
+            None => return Ok(path),
+        };
+        while let Some(proof) = self.prove_finality(next).await? {
+            let height = *self
+                .header(Some(proof.block))
+                .await?
+                .ok_or_else(|| Error::Other("justified block not found".into()))?
+                .number();
+            // The current set's latest justification may be one the client already has.
+            if height < next {
+                break;
+            }
+            path.push((height, proof.justification));
+            if height >= target {
+                break;
+            }
+            next = height + One::one();
+        }
+        Ok(path)
+    }
+}
+
+/// The first block a GRANDPA light client at `latest_height` has no header of, `None` if it is
+/// already at or past `target`.
+fn first_unproven<N: AtLeast32BitUnsigned + Copy>(latest_height: N, target: N) -> Option<N> {
+    if latest_height >= target {
+        None
+    } else {
+        Some(latest_height + One::one())
+    }
+}
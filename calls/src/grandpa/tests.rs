@@ -0,0 +1,13 @@
+use super::first_unproven;
+
+#[test]
+fn update_path_starts_after_the_client_height() {
+    assert_eq!(first_unproven(10u32, 20), Some(11));
+    assert_eq!(first_unproven(19u32, 20), Some(20));
+}
+
+#[test]
+fn update_path_is_empty_for_a_client_at_or_past_the_target() {
+    assert_eq!(first_unproven(20u32, 20), None);
+    assert_eq!(first_unproven(25u32, 20), None);
+}
@@ -1,10 +1,15 @@
 //! Implements support for the pallet_ibc module.
+use async_trait::async_trait;
 use codec::Decode;
 use codec::Encode;
 use core::marker::PhantomData;
 use pallet_ibc::event::primitive::{ClientId, ClientType, ConnectionId, Height};
-use sp_core::H256;
-use substrate_subxt::{balances::Balances, module, system::System, Call, Store};
+use serde::Deserialize;
+use serde_json::to_value as to_json_value;
+use sp_core::{Bytes, H256};
+use substrate_subxt::{
+    balances::Balances, module, system::System, Call, Client, Error, Runtime, Store,
+};
 use substrate_subxt_proc_macro::Event;
 
 /// The subset of the `pallet_ibc::Trait` that a client must implement.
@@ -98,3 +103,275 @@ pub struct DeliverCall<T: Ibc> {
     pub messages: Vec<pallet_ibc::Any>,
     pub tmp: u8,
 }
+
+/// A pallet-ibc value with the proof of its inclusion, as returned by the `ibc_query*` RPCs.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StateResponse<Hash> {
+    /// The encoded value, empty if a queried packet receipt does not exist.
+    pub value: Bytes,
+    /// The trie nodes proving `value` against the state root of `block_hash`.
+    pub proof: Vec<Bytes>,
+    /// The number of the block the value was read at.
+    pub height: u32,
+    /// The hash of the block the value was read at.
+    pub block_hash: Hash,
+}
+
+impl<Hash> StateResponse<Hash> {
+    /// The proof in the form pallet-ibc expects it in the proof fields of IBC messages.
+    pub fn encoded_proof(&self) -> Vec<u8> {
+        self.proof
+            .iter()
+            .map(|node| node.to_vec())
+            .collect::<Vec<_>>()
+            .encode()
+    }
+
+    /// The consensus state at the protobuf-encoded `height` in the value of an
+    /// `ibc_queryConsensusState` response, the list of all consensus states of the client.
+    pub fn consensus_state_at(&self, height: &[u8]) -> Result<Option<Vec<u8>>, codec::Error> {
+        let consensus_states = Vec::<(Vec<u8>, Vec<u8>)>::decode(&mut &self.value[..])?;
+        Ok(consensus_states
+            .into_iter()
+            .find(|(consensus_height, _)| consensus_height.as_slice() == height)
+            .map(|(_, consensus_state)| consensus_state))
+    }
+}
+
+/// Queries of the IBC state exposed by the node's `ibc_query*` RPC methods.
+#[async_trait]
+pub trait IbcRpcExt<T: Ibc> {
+    async fn query_client_state(
+        &self,
+        client_id: &str,
+        at: Option<T::Hash>,
+    ) -> Result<Option<StateResponse<T::Hash>>, Error>;
+
+    async fn query_consensus_state(
+        &self,
+        client_id: &str,
+        height: Vec<u8>,
+        at: Option<T::Hash>,
+    ) -> Result<Option<StateResponse<T::Hash>>, Error>;
+
+    async fn query_connection(
+        &self,
+        connection_id: &str,
+        at: Option<T::Hash>,
+    ) -> Result<Option<StateResponse<T::Hash>>, Error>;
+
+    async fn query_connections(
+        &self,
+        at: Option<T::Hash>,
+    ) -> Result<Vec<(String, StateResponse<T::Hash>)>, Error>;
+
+    async fn query_channel(
+        &self,
+        port_id: &str,
+        channel_id: &str,
+        at: Option<T::Hash>,
+    ) -> Result<Option<StateResponse<T::Hash>>, Error>;
+
+    async fn query_channels(
+        &self,
+        at: Option<T::Hash>,
+    ) -> Result<Vec<(String, String, StateResponse<T::Hash>)>, Error>;
+
+    async fn query_packet_commitment(
+        &self,
+        port_id: &str,
+        channel_id: &str,
+        sequence: u64,
+        at: Option<T::Hash>,
+    ) -> Result<Option<StateResponse<T::Hash>>, Error>;
+
+    async fn query_packet_commitments(
+        &self,
+        port_id: &str,
+        channel_id: &str,
+        at: Option<T::Hash>,
+    ) -> Result<Vec<(u64, StateResponse<T::Hash>)>, Error>;
+
+    async fn query_packet_acknowledgement(
+        &self,
+        port_id: &str,
+        channel_id: &str,
+        sequence: u64,
+        at: Option<T::Hash>,
+    ) -> Result<Option<StateResponse<T::Hash>>, Error>;
+
+    async fn query_packet_receipt(
+        &self,
+        port_id: &str,
+        channel_id: &str,
+        sequence: u64,
+        at: Option<T::Hash>,
+    ) -> Result<StateResponse<T::Hash>, Error>;
+
+    async fn query_next_sequence_recv(
+        &self,
+        port_id: &str,
+        channel_id: &str,
+        at: Option<T::Hash>,
+    ) -> Result<Option<StateResponse<T::Hash>>, Error>;
+}
+
+#[async_trait]
+impl<T: Ibc + Runtime> IbcRpcExt<T> for Client<T> {
+    async fn query_client_state(
+        &self,
+        client_id: &str,
+        at: Option<T::Hash>,
+    ) -> Result<Option<StateResponse<T::Hash>>, Error> {
+        let params = &[to_json_value(client_id)?, to_json_value(at)?];
+        self.rpc_client()
+            .request("ibc_queryClientState", params)
+            .await
+    }
+
+    async fn query_consensus_state(
+        &self,
+        client_id: &str,
+        height: Vec<u8>,
+        at: Option<T::Hash>,
+    ) -> Result<Option<StateResponse<T::Hash>>, Error> {
+        let params = &[
+            to_json_value(client_id)?,
+            to_json_value(Bytes(height))?,
+            to_json_value(at)?,
+        ];
+        self.rpc_client()
+            .request("ibc_queryConsensusState", params)
+            .await
+    }
+
+    async fn query_connection(
+        &self,
+        connection_id: &str,
+        at: Option<T::Hash>,
+    ) -> Result<Option<StateResponse<T::Hash>>, Error> {
+        let params = &[to_json_value(connection_id)?, to_json_value(at)?];
+        self.rpc_client()
+            .request("ibc_queryConnection", params)
+            .await
+    }
+
+    async fn query_connections(
+        &self,
+        at: Option<T::Hash>,
+    ) -> Result<Vec<(String, StateResponse<T::Hash>)>, Error> {
+        let params = &[to_json_value(at)?];
+        self.rpc_client()
+            .request("ibc_queryConnections", params)
+            .await
+    }
+
+    async fn query_channel(
+        &self,
+        port_id: &str,
+        channel_id: &str,
+        at: Option<T::Hash>,
+    ) -> Result<Option<StateResponse<T::Hash>>, Error> {
+        let params = &[
+            to_json_value(port_id)?,
+            to_json_value(channel_id)?,
+            to_json_value(at)?,
+        ];
+        self.rpc_client().request("ibc_queryChannel", params).await
+    }
+
+    async fn query_channels(
+        &self,
+        at: Option<T::Hash>,
+    ) -> Result<Vec<(String, String, StateResponse<T::Hash>)>, Error> {
+        let params = &[to_json_value(at)?];
+        self.rpc_client().request("ibc_queryChannels", params).await
+    }
+
+    async fn query_packet_commitment(
+        &self,
+        port_id: &str,
+        channel_id: &str,
+        sequence: u64,
+        at: Option<T::Hash>,
+    ) -> Result<Option<StateResponse<T::Hash>>, Error> {
+        let params = &[
+            to_json_value(port_id)?,
+            to_json_value(channel_id)?,
+            to_json_value(sequence)?,
+            to_json_value(at)?,
+        ];
+        self.rpc_client()
+            .request("ibc_queryPacketCommitment", params)
+            .await
+    }
+
+    async fn query_packet_commitments(
+        &self,
+        port_id: &str,
+        channel_id: &str,
+        at: Option<T::Hash>,
+    ) -> Result<Vec<(u64, StateResponse<T::Hash>)>, Error> {
+        let params = &[
+            to_json_value(port_id)?,
+            to_json_value(channel_id)?,
+            to_json_value(at)?,
+        ];
+        self.rpc_client()
+            .request("ibc_queryPacketCommitments", params)
+            .await
+    }
+
+    async fn query_packet_acknowledgement(
+        &self,
+        port_id: &str,
+        channel_id: &str,
+        sequence: u64,
+        at: Option<T::Hash>,
+    ) -> Result<Option<StateResponse<T::Hash>>, Error> {
+        let params = &[
+            to_json_value(port_id)?,
+            to_json_value(channel_id)?,
+            to_json_value(sequence)?,
+            to_json_value(at)?,
+        ];
+        self.rpc_client()
+            .request("ibc_queryPacketAcknowledgement", params)
+            .await
+    }
+
+    async fn query_packet_receipt(
+        &self,
+        port_id: &str,
+        channel_id: &str,
+        sequence: u64,
+        at: Option<T::Hash>,
+    ) -> Result<StateResponse<T::Hash>, Error> {
+        let params = &[
+            to_json_value(port_id)?,
+            to_json_value(channel_id)?,
+            to_json_value(sequence)?,
+            to_json_value(at)?,
+        ];
+        self.rpc_client()
+            .request("ibc_queryPacketReceipt", params)
+            .await
+    }
+
+    async fn query_next_sequence_recv(
+        &self,
+        port_id: &str,
+        channel_id: &str,
+        at: Option<T::Hash>,
+    ) -> Result<Option<StateResponse<T::Hash>>, Error> {
+        let params = &[
+            to_json_value(port_id)?,
+            to_json_value(channel_id)?,
+            to_json_value(at)?,
+        ];
+        self.rpc_client()
+            .request("ibc_queryNextSequenceRecv", params)
+            .await
+    }
+}
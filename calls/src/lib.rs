@@ -17,8 +17,14 @@ use substrate_subxt::{
     BasicSessionKeys, EventTypeRegistry, Runtime,
 };
 
+pub mod author;
+pub mod config;
+pub mod grandpa;
 pub mod ibc;
+pub mod signer;
 pub mod template;
+pub mod transfer;
+pub mod utility;
 
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub struct NodeRuntime;
@@ -34,6 +40,7 @@ impl Runtime for NodeRuntime {
         event_type_registry.with_session();
         event_type_registry.register_type_size::<H256>("H256");
         event_type_registry.register_type_size::<u64>("TAssetBalance");
+        event_type_registry.register_type_size::<u32>("AssetId");
         event_type_registry.register_type_size::<pallet_ibc::event::primitive::Height>("Height");
         event_type_registry
             .register_type_size::<pallet_ibc::event::primitive::ClientType>("ClientType");
@@ -72,3 +79,7 @@ impl Contracts for NodeRuntime {}
 impl ibc::Ibc for NodeRuntime {}
 
 impl template::TemplateModule for NodeRuntime {}
+
+impl transfer::IbcTransfer for NodeRuntime {}
+
+impl utility::Utility for NodeRuntime {}
@@ -0,0 +1,72 @@
+use serde::Deserialize;
+use sp_core::{sr25519, Pair};
+use std::env;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+
+/// Where the key of the account signing extrinsics comes from.
+#[derive(Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum KeySource {
+    /// A BIP-39 mnemonic phrase.
+    Mnemonic(String),
+    /// A secret URI, e.g. `//Alice` or `<phrase>//hard/soft`.
+    Seed(String),
+    /// A keystore file as written by the node keystore, i.e. a JSON string holding the secret
+    /// phrase or seed. Its password, if any, is read from the `password_env` environment
+    /// variable.
+    KeyFile {
+        path: String,
+        password_env: Option<String>,
+    },
+}
+
+/// Secrets are redacted, so that configs holding them can be logged.
+impl fmt::Debug for KeySource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeySource::Mnemonic(_) => f.debug_tuple("Mnemonic").field(&"<redacted>").finish(),
+            KeySource::Seed(_) => f.debug_tuple("Seed").field(&"<redacted>").finish(),
+            KeySource::KeyFile { path, password_env } => f
+                .debug_struct("KeyFile")
+                .field("path", path)
+                .field("password_env", password_env)
+                .finish(),
+        }
+    }
+}
+
+impl Default for KeySource {
+    fn default() -> Self {
+        KeySource::Seed("//Alice".to_string())
+    }
+}
+
+impl KeySource {
+    /// Load the sr25519 key pair.
+    pub fn pair(&self) -> Result<sr25519::Pair, Box<dyn Error>> {
+        match self {
+            KeySource::Mnemonic(phrase) => sr25519::Pair::from_phrase(phrase, None)
+                .map(|(pair, _)| pair)
+                .map_err(|e| format!("invalid mnemonic: {:?}", e).into()),
+            KeySource::Seed(suri) => sr25519::Pair::from_string(suri, None)
+                .map_err(|e| format!("invalid seed: {:?}", e).into()),
+            KeySource::KeyFile { path, password_env } => {
+                let contents = fs::read_to_string(path)
+                    .map_err(|e| format!("can not read key file {}: {}", path, e))?;
+                let secret: String = serde_json::from_str(&contents)
+                    .map_err(|e| format!("can not parse key file {}: {}", path, e))?;
+                let password = match password_env {
+                    Some(name) => Some(
+                        env::var(name)
+                            .map_err(|_| format!("environment variable {} is not set", name))?,
+                    ),
+                    None => None,
+                };
+                sr25519::Pair::from_string(&secret, password.as_deref())
+                    .map_err(|e| format!("invalid key file {}: {:?}", path, e).into())
+            }
+        }
+    }
+}
@@ -18,6 +18,7 @@ pub struct TestCreateClientCall<T: TemplateModule> {
     pub set_id: SetId,
     pub authority_list: AuthorityList,
     pub root: H256,
+    pub tmp: u8,
 }
 
 /// Arguments for opening connection.
@@ -27,7 +28,8 @@ pub struct TestConnOpenInitCall<T: TemplateModule> {
     pub identifier: H256,
     pub desired_counterparty_connection_identifier: H256,
     pub client_identifier: H256,
-    pub counterparty_client_identifier: H256,
+    pub counterparty_client_id: Vec<u8>,
+    pub tmp: u8,
 }
 
 /// Arguments for binding port.
@@ -54,17 +56,15 @@ pub struct TestChanOpenInitCall<T: TemplateModule> {
     pub channel_identifier: H256,
     pub counterparty_port_identifier: Vec<u8>,
     pub counterparty_channel_identifier: H256,
+    pub tmp: u8,
 }
 
 /// Arguments for sending packet.
 #[derive(Encode, Call)]
 pub struct TestSendPacketCall<T: TemplateModule> {
     pub _runtime: PhantomData<T>,
-    pub sequence: u64,
     pub timeout_height: u32,
     pub source_port: Vec<u8>,
     pub source_channel: H256,
-    pub dest_port: Vec<u8>,
-    pub dest_channel: H256,
     pub data: Vec<u8>,
 }
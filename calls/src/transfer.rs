@@ -0,0 +1,15 @@
+//! Implements support for the pallet_ibc_transfer module.
+use codec::Encode;
+use core::marker::PhantomData;
+use substrate_subxt::{module, system::System, Call};
+
+/// The subset of the `pallet_ibc_transfer::Config` that a client must implement.
+#[module]
+pub trait IbcTransfer: System {}
+
+/// Arguments for sending tokens over ICS-20, as a protobuf encoded `MsgTransfer`.
+#[derive(Encode, Call)]
+pub struct TransferCall<T: IbcTransfer> {
+    pub _runtime: PhantomData<T>,
+    pub message: pallet_ibc::Any,
+}
@@ -0,0 +1,15 @@
+//! Implements support for the pallet_utility module.
+use codec::Encode;
+use core::marker::PhantomData;
+use substrate_subxt::{module, system::System, Call, Encoded};
+
+/// The subset of the `pallet_utility::Config` that a client must implement.
+#[module]
+pub trait Utility: System {}
+
+/// Dispatch `calls` in order, all or none of them: a call that fails reverts the ones before.
+#[derive(Clone, Debug, Eq, PartialEq, Encode, Call)]
+pub struct BatchAllCall<T: Utility> {
+    pub _runtime: PhantomData<T>,
+    pub calls: Vec<Encoded>,
+}
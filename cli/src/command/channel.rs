@@ -15,4 +15,13 @@ pub struct ChannelOpenInit {
 
     /// The identifier of port on counterparty chain
     pub counterparty_port_identifier: String,
-}
\ No newline at end of file
+}
+/// Close a channel
+#[derive(Debug, StructOpt)]
+pub struct ChannelCloseInit {
+    /// The identifier of port
+    pub port_identifier: String,
+
+    /// The identifier of channel
+    pub channel_identifier: String,
+}
@@ -14,6 +14,10 @@ pub enum Client {
     /// Upgrade client
     #[structopt(name = "upgrade-client")]
     UpgradeClient(UpgradeClient),
+
+    /// Submit two conflicting headers to freeze a client
+    #[structopt(name = "misbehaviour")]
+    Misbehaviour(Misbehaviour),
 }
 
 #[derive(Debug, StructOpt)]
@@ -26,6 +30,15 @@ pub struct CreateClient {
 pub struct UpdateClient {
     /// The name of counterparty demo chain
     pub chain_name: String,
+
+    /// The identifier of the client, defaults to the one registered for the counterparty
+    #[structopt(long)]
+    pub client_id: Option<String>,
+
+    /// The counterparty height to update to, or to the first justified block after it, defaults
+    /// to its latest finalized height
+    #[structopt(long)]
+    pub height: Option<u32>,
 }
 
 #[derive(Debug, StructOpt)]
@@ -34,3 +47,18 @@ pub struct UpgradeClient {
     pub chain_name: String,
 }
 
+#[derive(Debug, StructOpt)]
+pub struct Misbehaviour {
+    /// The name of counterparty demo chain
+    pub chain_name: String,
+
+    /// File holding a header of the counterparty, hex encoded as the relayer logs it
+    pub header1: String,
+
+    /// File holding a header of the same height conflicting with the first one
+    pub header2: String,
+
+    /// The identifier of the client, defaults to the one registered for the counterparty
+    #[structopt(long)]
+    pub client_id: Option<String>,
+}
@@ -3,15 +3,17 @@ mod connection;
 mod channel;
 mod port;
 mod packet;
+mod query;
+mod transfer;
 
 use crate::ibc_logic::validate_channel_identifier;
 use crate::ibc_logic::{
     channel as IbcLogicChannel, client as IbcLogicClient, connection as IbcLogicConnection,
-    packet as IbcLogicPacket, port as IbcLogicPort,
+    packet as IbcLogicPacket, port as IbcLogicPort, query as IbcLogicQuery,
+    transfer as IbcLogicTransfer,
 };
-use lazy_static::lazy_static;
+use calls::{config::ChainRegistry, signer::KeySource};
 use sp_core::{Blake2Hasher, Hasher, H256};
-use std::collections::HashMap;
 use structopt::StructOpt;
 
 #[derive(Debug, StructOpt)]
@@ -25,11 +27,20 @@ pub enum SubCommand {
     #[structopt(name = "channel-open-init")]
     ChannelOpenInit(channel::ChannelOpenInit),
 
+    #[structopt(name = "channel-close-init")]
+    ChannelCloseInit(channel::ChannelCloseInit),
+
     #[structopt(name = "port-handle")]
     Port(port::Port),
 
     #[structopt(name = "packet-handle")]
     Packet(packet::Packet),
+
+    #[structopt(name = "query")]
+    Query(query::Query),
+
+    #[structopt(name = "transfer")]
+    Transfer(transfer::Transfer),
 }
 
 /// Octopus Network <hi@oct.network>
@@ -37,62 +48,90 @@ pub enum SubCommand {
 #[derive(Debug, StructOpt)]
 #[structopt(name = "cli")]
 pub struct App {
-    /// Sets the chain to be operated
+    /// Sets the chain to be operated, by its name in the chain registry
     #[structopt(short, long)]
     chain: String,
 
+    /// The chain registry, shared with the relayer
+    #[structopt(long, default_value = "relayer/config.toml")]
+    config: String,
+
+    /// Secret URI or mnemonic of the signing account, defaults to //Bob, as the relayer signs
+    /// with the chain's signer
+    #[structopt(long, conflicts_with = "key-file")]
+    signer: Option<String>,
+
+    /// Keystore file holding the secret of the signing account
+    #[structopt(long)]
+    key_file: Option<String>,
+
+    /// Environment variable holding the password of the key file
+    #[structopt(long, requires = "key-file")]
+    password_env: Option<String>,
+
     #[structopt(subcommand)]
     pub subcommand: SubCommand,
 }
 
-lazy_static! {
-    static ref ENDPOINTS: HashMap<&'static str, &'static str> = {
-        let mut m = HashMap::new();
-        m.insert("appia-client-id", "ws://127.0.0.1:9944");
-        m.insert("flaminia-client-id", "ws://127.0.0.1:8844");
-        m
-    };
-}
-
-pub async fn run() {
+pub async fn run() -> Result<(), Box<dyn std::error::Error>> {
     let cli = App::from_args();
-    println!("{:?}", cli);
-
     let chain = cli.chain.as_ref();
-    println!("chain = {}", chain);
 
-    let subcommand = &cli.subcommand;
-    println!("subcommand = {:?}", subcommand);
+    let registry = ChainRegistry::load(&cli.config)
+        .map_err(|e| format!("can not load chain registry: {}", e))?;
+    let chain_config = registry.get(chain)?;
+
+    let key_source = match (&cli.signer, &cli.key_file) {
+        (_, Some(path)) => KeySource::KeyFile {
+            path: path.clone(),
+            password_env: cli.password_env.clone(),
+        },
+        (Some(suri), None) => KeySource::Seed(suri.clone()),
+        // Not the relayer's signer, whose transactions would collide on nonces with ours.
+        (None, None) => KeySource::Seed("//Bob".to_string()),
+    };
+    // Only the commands submitting a transaction need the signer, so queries work without it.
+    let pair = || {
+        key_source
+            .pair()
+            .map_err(|e| format!("can not load signer: {}", e))
+    };
 
-    let addr = ENDPOINTS.get(&chain).unwrap();
+    let addr = chain_config.endpoint();
+    let tmp = chain_config.tmp;
     match &cli.subcommand {
         SubCommand::Client(val) => match val {
             client::Client::CreateClient(create_client) => {
                 let chain_name = create_client.chain_name.clone();
                 println!("chain_name = {}", chain_name);
 
-                let counterparty_addr = ENDPOINTS.get(&chain_name.as_ref()).unwrap();
-                println!("counterparty_addr = {}", counterparty_addr);
+                let counterparty = registry.get(&chain_name)?;
+                println!("counterparty_addr = {}", counterparty.endpoint());
 
-                let result = IbcLogicClient::create_client::create_client(
-                    &addr,
-                    &counterparty_addr,
-                    chain_name.to_string(),
-                )
-                .await;
+                let result =
+                    IbcLogicClient::create_client::create_client(&addr, pair()?, tmp, counterparty)
+                        .await;
                 println!("create_client: {:?}", result);
             }
             client::Client::UpdateClient(update_client) => {
                 let chain_name = update_client.chain_name.clone();
                 println!("chain_name = {}", chain_name);
 
-                let counterparty_addr = ENDPOINTS.get(&chain_name.as_ref()).unwrap();
-                println!("counterparty_addr = {}", counterparty_addr);
+                let counterparty = registry.get(&chain_name)?;
+                println!("counterparty_addr = {}", counterparty.endpoint());
+
+                let client_id = update_client
+                    .client_id
+                    .as_ref()
+                    .unwrap_or(&counterparty.client_identifier);
 
                 let result = IbcLogicClient::update_client::update_client(
                     &addr,
-                    &counterparty_addr,
-                    chain_name.to_string(),
+                    pair()?,
+                    tmp,
+                    counterparty,
+                    client_id,
+                    update_client.height,
                 )
                 .await;
 
@@ -102,18 +141,42 @@ pub async fn run() {
                 let chain_name = upgrade_client.chain_name.clone();
                 println!("chain_name = {}", chain_name);
 
-                let counterparty_addr = ENDPOINTS.get(&chain_name.as_ref()).unwrap();
-                println!("counterparty_addr = {}", counterparty_addr);
+                let counterparty = registry.get(&chain_name)?;
+                println!("counterparty_addr = {}", counterparty.endpoint());
 
                 let result = IbcLogicClient::upgrade_client::upgrade_client(
                     &addr,
-                    &counterparty_addr,
-                    chain_name.to_string(),
+                    pair()?,
+                    tmp,
+                    counterparty,
                 )
                     .await;
 
                 println!("upgrade client: {:?}", result);
             }
+            client::Client::Misbehaviour(misbehaviour) => {
+                let chain_name = misbehaviour.chain_name.clone();
+                println!("chain_name = {}", chain_name);
+
+                let counterparty = registry.get(&chain_name)?;
+
+                let client_id = misbehaviour
+                    .client_id
+                    .as_ref()
+                    .unwrap_or(&counterparty.client_identifier);
+
+                let result = IbcLogicClient::misbehaviour::submit_misbehaviour(
+                    &addr,
+                    pair()?,
+                    tmp,
+                    client_id,
+                    &misbehaviour.header1,
+                    &misbehaviour.header2,
+                )
+                .await;
+
+                println!("misbehaviour: {:?}", result);
+            }
         },
         SubCommand::ChannelOpenInit(channel::ChannelOpenInit {
             unordered,
@@ -122,8 +185,7 @@ pub async fn run() {
             counterparty_port_identifier,
         }) => {
             if chain != "appia" {
-                println!("CHAIN can only be appia in this demo");
-                return;
+                return Err("CHAIN can only be appia in this demo".into());
             }
 
             let connection_identifier = hex::decode(connection_identifier).unwrap();
@@ -143,6 +205,8 @@ pub async fn run() {
 
             let result = IbcLogicChannel::chan_open_init(
                 &addr,
+                pair()?,
+                tmp,
                 unordered.clone(),
                 connection_hops,
                 port_identifier,
@@ -153,6 +217,43 @@ pub async fn run() {
             .await;
             println!("chan_open_init: {:?}", result);
         }
+        SubCommand::ChannelCloseInit(channel::ChannelCloseInit {
+            port_identifier,
+            channel_identifier,
+        }) => {
+            let result = IbcLogicChannel::chan_close_init(
+                &addr,
+                pair()?,
+                tmp,
+                port_identifier,
+                channel_identifier,
+            )
+            .await;
+            println!("chan_close_init: {:?}", result);
+        }
+        SubCommand::Transfer(transfer::Transfer {
+            source_port,
+            source_channel,
+            denom,
+            amount,
+            receiver,
+            timeout_height,
+            timeout_timestamp,
+        }) => {
+            let result = IbcLogicTransfer::transfer(
+                &addr,
+                pair()?,
+                source_port,
+                source_channel,
+                denom,
+                *amount,
+                receiver,
+                *timeout_height,
+                *timeout_timestamp,
+            )
+            .await;
+            println!("transfer: {:?}", result);
+        }
         SubCommand::ConnectionOpenInit(connection::ConnectionOpenInit {
             client_identifier,
             counterparty_client_identifier,
@@ -181,6 +282,8 @@ pub async fn run() {
 
             let result = IbcLogicConnection::conn_open_init(
                 &addr,
+                pair()?,
+                tmp,
                 identifier,
                 // desired_counterparty_connection_identifier,
                 // client_identifier,
@@ -192,57 +295,151 @@ pub async fn run() {
         }
         SubCommand::Packet(packet) => match packet {
             packet::Packet::SendPacket(packet::SendPacket {
-                sequence,
                 timeout_height,
                 source_port,
                 source_channel,
-                dest_port,
-                dest_channel,
                 data,
             }) => {
                 if chain != "appia" {
-                    println!("CHAIN can only be appia in this demo");
-                    return;
+                    return Err("CHAIN can only be appia in this demo".into());
                 }
 
-                let sequence: u64 = sequence.parse().unwrap();
                 let timeout_height: u32 = timeout_height.parse().unwrap();
                 let source_port = source_port.as_bytes().to_vec();
                 let source_channel = hex::decode(source_channel).unwrap();
                 let source_channel = H256::from_slice(&source_channel);
-                let dest_port = dest_port.as_bytes().to_vec();
-                let dest_channel = hex::decode(dest_channel).unwrap();
-                let dest_channel = H256::from_slice(&dest_channel);
                 let data: Vec<u8> = hex::decode(data).expect("Invalid message");
 
                 let _result = IbcLogicPacket::send_packet(
                     &addr,
-                    sequence,
+                    pair()?,
                     timeout_height,
                     source_port,
                     source_channel,
-                    dest_port,
-                    dest_channel,
                     data,
                 )
                 .await;
             }
         },
+        SubCommand::Query(query::Query {
+            height,
+            json,
+            query,
+        }) => {
+            let (height, json) = (*height, *json);
+            let result = match query {
+                query::QueryKind::ClientState { client_identifier } => {
+                    IbcLogicQuery::client_state(&addr, height, json, client_identifier).await
+                }
+                query::QueryKind::ConsensusState {
+                    client_identifier,
+                    consensus_height,
+                } => {
+                    IbcLogicQuery::consensus_state(
+                        &addr,
+                        height,
+                        json,
+                        client_identifier,
+                        *consensus_height,
+                    )
+                    .await
+                }
+                query::QueryKind::Connection {
+                    connection_identifier,
+                } => IbcLogicQuery::connection(&addr, height, json, connection_identifier).await,
+                query::QueryKind::Connections => {
+                    IbcLogicQuery::connections(&addr, height, json).await
+                }
+                query::QueryKind::Channel {
+                    port_identifier,
+                    channel_identifier,
+                } => {
+                    IbcLogicQuery::channel(&addr, height, json, port_identifier, channel_identifier)
+                        .await
+                }
+                query::QueryKind::Channels => IbcLogicQuery::channels(&addr, height, json).await,
+                query::QueryKind::PacketCommitments {
+                    port_identifier,
+                    channel_identifier,
+                } => {
+                    IbcLogicQuery::packet_commitments(
+                        &addr,
+                        height,
+                        json,
+                        port_identifier,
+                        channel_identifier,
+                    )
+                    .await
+                }
+                query::QueryKind::UnreceivedPackets {
+                    counterparty_chain,
+                    port_identifier,
+                    channel_identifier,
+                } => match registry.get(counterparty_chain) {
+                    Ok(counterparty) => {
+                        IbcLogicQuery::unreceived_packets(
+                            &addr,
+                            height,
+                            json,
+                            counterparty.endpoint(),
+                            port_identifier,
+                            channel_identifier,
+                        )
+                        .await
+                    }
+                    Err(e) => Err(e),
+                },
+                query::QueryKind::UnreceivedAcks {
+                    counterparty_chain,
+                    port_identifier,
+                    channel_identifier,
+                } => match registry.get(counterparty_chain) {
+                    Ok(counterparty) => {
+                        IbcLogicQuery::unreceived_acks(
+                            &addr,
+                            height,
+                            json,
+                            counterparty.endpoint(),
+                            port_identifier,
+                            channel_identifier,
+                        )
+                        .await
+                    }
+                    Err(e) => Err(e),
+                },
+                query::QueryKind::NextSequence {
+                    port_identifier,
+                    channel_identifier,
+                } => {
+                    IbcLogicQuery::next_sequence(
+                        &addr,
+                        height,
+                        json,
+                        port_identifier,
+                        channel_identifier,
+                    )
+                    .await
+                }
+            };
+            result.map_err(|e| format!("query failed: {}", e))?;
+        }
         SubCommand::Port(port) => match port {
             port::Port::BindPort(port::BindPort { identifier }) => {
                 let identifier = identifier.as_bytes().to_vec();
                 println!("identifier: {:?}", identifier);
 
-                let result = IbcLogicPort::bind_port(&addr, identifier).await;
+                let result = IbcLogicPort::bind_port(&addr, pair()?, identifier).await;
                 println!("bind_port: {:?}", result);
             }
             port::Port::ReleasePort(port::ReleasePort { identifier }) => {
                 let identifier = identifier.as_bytes().to_vec();
                 println!("identifier: {:?}", identifier);
 
-                let result = IbcLogicPort::release_port(&addr, identifier).await;
+                let result = IbcLogicPort::release_port(&addr, pair()?, identifier).await;
                 println!("release_port: {:?}", result);
             }
         },
     }
+
+    Ok(())
 }
@@ -0,0 +1,108 @@
+use structopt::StructOpt;
+
+/// Query IBC state
+#[derive(Debug, StructOpt)]
+pub struct Query {
+    /// The height to query at, defaults to the best block
+    #[structopt(long)]
+    pub height: Option<u32>,
+
+    /// Print the result as JSON
+    #[structopt(long)]
+    pub json: bool,
+
+    #[structopt(subcommand)]
+    pub query: QueryKind,
+}
+
+#[derive(Debug, StructOpt)]
+pub enum QueryKind {
+    /// Query the state of a client
+    #[structopt(name = "client-state")]
+    ClientState {
+        /// The identifier of client
+        client_identifier: String,
+    },
+
+    /// Query the consensus state of a client at a counterparty height
+    #[structopt(name = "consensus-state")]
+    ConsensusState {
+        /// The identifier of client
+        client_identifier: String,
+
+        /// The counterparty height of the consensus state
+        consensus_height: u64,
+    },
+
+    /// Query a connection
+    #[structopt(name = "connection")]
+    Connection {
+        /// The identifier of connection
+        connection_identifier: String,
+    },
+
+    /// Query all connections
+    #[structopt(name = "connections")]
+    Connections,
+
+    /// Query a channel
+    #[structopt(name = "channel")]
+    Channel {
+        /// The identifier of port
+        port_identifier: String,
+
+        /// The identifier of channel
+        channel_identifier: String,
+    },
+
+    /// Query all channels
+    #[structopt(name = "channels")]
+    Channels,
+
+    /// Query the commitments of the packets sent on a channel
+    #[structopt(name = "packet-commitments")]
+    PacketCommitments {
+        /// The identifier of port
+        port_identifier: String,
+
+        /// The identifier of channel
+        channel_identifier: String,
+    },
+
+    /// Query the packets sent on a channel that the counterparty has not received
+    #[structopt(name = "unreceived-packets")]
+    UnreceivedPackets {
+        /// The name of counterparty demo chain
+        counterparty_chain: String,
+
+        /// The identifier of port
+        port_identifier: String,
+
+        /// The identifier of channel
+        channel_identifier: String,
+    },
+
+    /// Query the packets sent on a channel whose acknowledgement the counterparty has written
+    /// but which have not been acknowledged on this chain
+    #[structopt(name = "unreceived-acks")]
+    UnreceivedAcks {
+        /// The name of counterparty demo chain
+        counterparty_chain: String,
+
+        /// The identifier of port
+        port_identifier: String,
+
+        /// The identifier of channel
+        channel_identifier: String,
+    },
+
+    /// Query the next sequence to be received on an ordered channel
+    #[structopt(name = "next-sequence")]
+    NextSequence {
+        /// The identifier of port
+        port_identifier: String,
+
+        /// The identifier of channel
+        channel_identifier: String,
+    },
+}
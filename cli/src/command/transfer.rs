@@ -0,0 +1,30 @@
+use structopt::StructOpt;
+
+/// Send tokens to a counterparty chain over ICS-20
+#[derive(Debug, StructOpt)]
+pub struct Transfer {
+    /// The port on the sending chain
+    #[structopt(long, default_value = "transfer")]
+    pub source_port: String,
+
+    /// The channel end on the sending chain
+    pub source_channel: String,
+
+    /// The denomination trace of the token, e.g. UNIT or transfer/channel-0/UNIT
+    pub denom: String,
+
+    /// The amount to send
+    pub amount: u128,
+
+    /// The receiving account on the counterparty chain, as SS58 or 0x prefixed hex
+    pub receiver: String,
+
+    /// The counterparty height at which the transfer times out, 0 for none. When neither timeout
+    /// is given, defaults to 1000 blocks past the counterparty height the channel's client has seen
+    #[structopt(long, default_value = "0")]
+    pub timeout_height: u64,
+
+    /// The counterparty timestamp in nanoseconds at which the transfer times out, 0 for none
+    #[structopt(long, default_value = "0")]
+    pub timeout_timestamp: u64,
+}
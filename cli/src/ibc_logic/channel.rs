@@ -1,10 +1,17 @@
-use calls::{template::TestChanOpenInitCallExt, NodeRuntime as Runtime};
-use sp_core::H256;
-use sp_keyring::AccountKeyring;
+use crate::ibc_logic::get_dummy_account_id;
+use calls::{ibc::DeliverCallExt, template::TestChanOpenInitCallExt, NodeRuntime as Runtime};
+use ibc::ics04_channel::msgs::chan_close_init::{self, MsgChannelCloseInit};
+use ibc::ics24_host::identifier::{ChannelId, PortId};
+use ibc::signer::Signer;
+use sp_core::{sr25519, H256};
+use std::str::FromStr;
 use substrate_subxt::{ClientBuilder, PairSigner};
+use tendermint_proto::Protobuf;
 
 pub async fn chan_open_init(
     addr: &str,
+    pair: sr25519::Pair,
+    tmp: u8,
     unordered: bool,
     connection_hops: Vec<H256>,
     port_identifier: Vec<u8>,
@@ -12,7 +19,7 @@ pub async fn chan_open_init(
     counterparty_port_identifier: Vec<u8>,
     counterparty_channel_identifier: H256,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    let signer = PairSigner::new(AccountKeyring::Bob.pair());
+    let signer = PairSigner::new(pair);
     let client = ClientBuilder::<Runtime>::new()
         .set_url(addr)
         .build()
@@ -26,7 +33,39 @@ pub async fn chan_open_init(
             channel_identifier,
             counterparty_port_identifier,
             counterparty_channel_identifier,
+            tmp,
         )
         .await?;
     Ok(())
 }
+
+pub async fn chan_close_init(
+    addr: &str,
+    pair: sr25519::Pair,
+    tmp: u8,
+    port_identifier: &str,
+    channel_identifier: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let signer = PairSigner::new(pair);
+
+    let msg = MsgChannelCloseInit {
+        port_id: PortId::from_str(port_identifier)?,
+        channel_id: ChannelId::from_str(channel_identifier)?,
+        signer: Signer::new(get_dummy_account_id().to_string()),
+    };
+    println!("msg = {:?}", msg);
+
+    let any = pallet_ibc::Any {
+        type_url: chan_close_init::TYPE_URL.to_string(),
+        value: msg.encode_vec().unwrap(),
+    };
+
+    let client = ClientBuilder::<Runtime>::new()
+        .set_url(addr)
+        .build()
+        .await?;
+    let result = client.deliver(&signer, vec![any], tmp).await?;
+    println!("result = {:?}", result);
+
+    Ok(())
+}
@@ -1,6 +1,7 @@
 pub mod create_client {
     use crate::ibc_logic::get_dummy_account_id;
-    use sp_keyring::AccountKeyring;
+    use sp_core::{sr25519, storage::StorageKey, twox_128};
+    use sp_finality_grandpa::{SetId, VersionedAuthorityList, GRANDPA_AUTHORITIES_KEY};
     use substrate_subxt::{ClientBuilder, PairSigner};
 
     use ibc::ics02_client::client_consensus::AnyConsensusState;
@@ -9,38 +10,75 @@ pub mod create_client {
     use ibc::ics02_client::msgs::create_client::MsgCreateAnyClient;
     use ibc::ics10_grandpa::client_state::ClientState as GRANDPAClientState;
     use ibc::ics10_grandpa::consensus_state::ConsensusState as GRANDPAConsensusState;
+    use ibc::ics23_commitment::commitment::CommitmentRoot;
     use ibc::ics24_host::identifier::ChainId;
     use ibc::signer::Signer;
 
     use tendermint_proto::Protobuf;
 
-    use calls::{ibc::DeliverCallExt, NodeRuntime as Runtime};
+    use calls::{
+        config::{ChainConfig, GRANDPA_CLIENT_TYPE},
+        ibc::DeliverCallExt,
+        NodeRuntime as Runtime,
+    };
 
     const TYPE_URL: &str = "/ibc.core.client.v1.MsgCreateClient";
 
     pub async fn create_client(
         addr: &str,
-        counterparty_addr: &str,
-        identifier: String,
+        pair: sr25519::Pair,
+        tmp: u8,
+        counterparty: &ChainConfig,
     ) -> Result<(), Box<dyn std::error::Error>> {
-        let signer = PairSigner::new(AccountKeyring::Bob.pair());
+        let signer = PairSigner::new(pair);
         println!("signer");
 
-        let chain_id = ChainId::new("ibc-logic-2".to_string(), 2);
+        if counterparty.client_type != GRANDPA_CLIENT_TYPE {
+            return Err(format!("unsupported client type {}", counterparty.client_type).into());
+        }
+        let counterparty_client = ClientBuilder::<Runtime>::new()
+            .set_url(counterparty.endpoint())
+            .build()
+            .await?;
+
+        // The client starts from the counterparty's latest finalized block, trusting the
+        // authority set that finalized it.
+        let block_hash = counterparty_client.finalized_head().await?;
+        let header = counterparty_client
+            .header(Some(block_hash))
+            .await?
+            .ok_or("finalized header not found")?;
+        let authorities: VersionedAuthorityList = counterparty_client
+            .fetch_unhashed(StorageKey(GRANDPA_AUTHORITIES_KEY.to_vec()), Some(block_hash))
+            .await?
+            .ok_or("grandpa authorities not found")?;
+        let mut set_id_key = twox_128(b"Grandpa").to_vec();
+        set_id_key.extend(twox_128(b"CurrentSetId").to_vec());
+        let set_id: SetId = counterparty_client
+            .fetch_unhashed(StorageKey(set_id_key), Some(block_hash))
+            .await?
+            .unwrap_or_default();
+
+        let chain_id = ChainId::from_string(&counterparty.chain_id);
         println!("chain_id = {:?}", chain_id);
-        let latest_height = Height::new(0, 26);
+        let latest_height = Height::new(0, header.number as u64);
         println!("latest_height = {:?}", latest_height);
         let frozen_height = Height::new(0, 0);
         println!("frozen_height = {:?}", frozen_height);
-
-        // Create mock grandpa client state
-        let client_state = AnyClientState::Grandpa(
-            GRANDPAClientState::new(chain_id, latest_height, frozen_height).unwrap(),
-        );
+        println!("set_id = {}", set_id);
+
+        let client_state = AnyClientState::Grandpa(GRANDPAClientState::new(
+            chain_id,
+            latest_height,
+            frozen_height,
+            set_id,
+            authorities.into(),
+        )?);
         println!("client_state: {:?}", client_state);
 
-        // Create mock grandpa consensus state
-        let consensus_state = AnyConsensusState::Grandpa(GRANDPAConsensusState::new());
+        let consensus_state = AnyConsensusState::Grandpa(GRANDPAConsensusState::new(
+            CommitmentRoot::from(header.state_root.as_bytes().to_vec()),
+        ));
         println!("consensus_state = {:?}", consensus_state);
 
         let tm_signer = get_dummy_account_id();
@@ -63,17 +101,7 @@ pub mod create_client {
             .build()
             .await?;
 
-        let _result = client
-            .deliver(
-                &signer,
-                vec![any],
-                if identifier == "appia-client-id" {
-                    0
-                } else {
-                    1
-                },
-            )
-            .await?;
+        let _result = client.deliver(&signer, vec![any], tmp).await?;
         println!("resut = {:?}", _result);
 
         Ok(())
@@ -82,68 +110,169 @@ pub mod create_client {
 
 pub mod update_client {
     use crate::ibc_logic::get_dummy_account_id;
-    use sp_keyring::AccountKeyring;
-    use substrate_subxt::{ClientBuilder, PairSigner};
+    use sp_core::{sr25519, storage::StorageKey};
+    use sp_finality_grandpa::GRANDPA_AUTHORITIES_KEY;
+    use sp_trie::StorageProof;
+    use substrate_subxt::{BlockNumber, ClientBuilder, PairSigner};
 
-    use ibc::ics02_client::msgs::update_client::MsgUpdateAnyClient;
+    use ibc::ics02_client::client_state::AnyClientState;
+    use ibc::ics02_client::msgs::update_client::{self, MsgUpdateAnyClient};
 
-    use ibc::ics24_host::identifier::{ChainId, ClientId};
+    use ibc::ics24_host::identifier::ClientId;
     use ibc::signer::Signer;
 
     use tendermint_proto::Protobuf;
 
-    use calls::{ibc::DeliverCallExt, NodeRuntime as Runtime};
-    use ibc::ics02_client::client_type::ClientType;
+    use calls::{
+        config::ChainConfig,
+        grandpa::GrandpaRpcExt,
+        ibc::{DeliverCallExt, IbcRpcExt},
+        NodeRuntime as Runtime,
+    };
+    use std::str::FromStr;
     use ibc::ics02_client::header::AnyHeader;
     use ibc::ics10_grandpa::header::Header as GrandpaHeader;
+    use codec::Encode;
 
-    const TYPE_URL: &str = "/ibc.core.client.v1.MsgUpdateClient";
+    /// The most headers submitted in one extrinsic.
+    const MAX_MESSAGES: usize = 50;
+    /// The most bytes of encoded headers in one extrinsic.
+    const MAX_SIZE: usize = 1024 * 1024;
 
+    /// Update `client_id` on the chain at `addr` to `target_height` of the counterparty, or to
+    /// its latest finalized block, through the justified blocks `GrandpaRpcExt::update_path`
+    /// gives: the last block of every authority set the client has not seen, then the latest
+    /// justified block.
     pub async fn update_client(
         addr: &str,
-        counterparty_addr: &str,
-        identifier: String,
+        pair: sr25519::Pair,
+        tmp: u8,
+        counterparty: &ChainConfig,
+        client_id: &str,
+        target_height: Option<u32>,
     ) -> Result<(), Box<dyn std::error::Error>> {
-        let signer = PairSigner::new(AccountKeyring::Bob.pair());
-        println!("signer");
-
-        let tm_signer = get_dummy_account_id();
-        let client_id = ClientId::new(ClientType::Grandpa, 0).unwrap();
-        let header = AnyHeader::Grandpa(GrandpaHeader { height: 0 });
-        let msg = MsgUpdateAnyClient::new(client_id, header, Signer::new(tm_signer.to_string()));
-        println!("msg = {:?}", msg);
-
-        let data = msg.encode_vec().unwrap();
-        let any = pallet_ibc::Any {
-            type_url: TYPE_URL.to_string(),
-            value: data,
-        };
+        let signer = PairSigner::new(pair);
 
         let client = ClientBuilder::<Runtime>::new()
             .set_url(addr)
             .build()
             .await?;
+        let counterparty_client = ClientBuilder::<Runtime>::new()
+            .set_url(counterparty.endpoint())
+            .build()
+            .await?;
+
+        let client_state = client
+            .query_client_state(client_id, None)
+            .await?
+            .ok_or("client state not found")?;
+        let latest_height = match AnyClientState::decode_vec(&client_state.value)? {
+            AnyClientState::Grandpa(client_state) => {
+                client_state.latest_height.revision_height as u32
+            }
+            _ => return Err("not a grandpa client".into()),
+        };
+        let target_height = match target_height {
+            Some(target_height) => target_height,
+            None => {
+                let finalized_head = counterparty_client.finalized_head().await?;
+                counterparty_client
+                    .header(Some(finalized_head))
+                    .await?
+                    .ok_or("finalized header not found")?
+                    .number
+            }
+        };
+        println!(
+            "latest_height = {}, target_height = {}",
+            latest_height, target_height
+        );
+        if target_height <= latest_height {
+            println!("client {} is up to date", client_id);
+            return Ok(());
+        }
 
-        let _result = client
-            .deliver(
-                &signer,
-                vec![any],
-                if identifier == "appia-client-id" {
-                    0
-                } else {
-                    1
-                },
-            )
+        let path = counterparty_client
+            .update_path(latest_height, target_height)
             .await?;
-        println!("resut = {:?}", _result);
+        let reached = match path.last() {
+            Some(&(height, _)) => height,
+            None => return Err("no justified block after the client's latest height".into()),
+        };
+        if reached < target_height {
+            println!(
+                "{} is not justified yet, updating to {} only",
+                target_height, reached
+            );
+        }
+
+        let tm_signer = get_dummy_account_id();
+        let mut messages = vec![];
+        for (height, justification) in path {
+            let hash = counterparty_client
+                .block_hash(Some(BlockNumber::from(height)))
+                .await?
+                .ok_or("block not found")?;
+            let signed_block = counterparty_client
+                .block(Some(hash))
+                .await?
+                .ok_or("block not found")?;
+            let authorities_proof = counterparty_client
+                .read_proof(vec![StorageKey(GRANDPA_AUTHORITIES_KEY.to_vec())], Some(hash))
+                .await?;
+            let header = AnyHeader::Grandpa(GrandpaHeader {
+                height: signed_block.block.header.number.into(),
+                commitment_root: signed_block.block.header.state_root,
+                block_hash: signed_block.block.header.hash(),
+                justification: Some(justification),
+                authorities_proof: StorageProof::new(
+                    authorities_proof.proof.into_iter().map(|b| b.0).collect(),
+                ),
+            });
+            let msg = MsgUpdateAnyClient::new(
+                ClientId::from_str(client_id)?,
+                header,
+                Signer::new(tm_signer.to_string()),
+            );
+            messages.push(pallet_ibc::Any {
+                type_url: update_client::TYPE_URL.to_string(),
+                value: msg.encode_vec().unwrap(),
+            });
+        }
+        println!("headers = {}", messages.len());
+
+        // The headers are applied in order, so a batch only starts once the one before it is in.
+        for batch in batches(messages) {
+            client.deliver(&signer, batch, tmp).await?;
+        }
 
         Ok(())
     }
+
+    /// Split `messages` into extrinsics within the limits the relayer batches with by default.
+    fn batches(messages: Vec<pallet_ibc::Any>) -> Vec<Vec<pallet_ibc::Any>> {
+        let mut batches = vec![];
+        let (mut batch, mut size) = (vec![], 0);
+        for any in messages {
+            let encoded_size = any.encoded_size();
+            if !batch.is_empty() && (batch.len() == MAX_MESSAGES || size + encoded_size > MAX_SIZE)
+            {
+                batches.push(std::mem::take(&mut batch));
+                size = 0;
+            }
+            size += encoded_size;
+            batch.push(any);
+        }
+        if !batch.is_empty() {
+            batches.push(batch);
+        }
+        batches
+    }
 }
 
 pub mod upgrade_client {
     use crate::ibc_logic::get_dummy_account_id;
-    use sp_keyring::AccountKeyring;
+    use sp_core::sr25519;
     use substrate_subxt::{ClientBuilder, PairSigner};
 
     use ibc::ics02_client::client_consensus::AnyConsensusState;
@@ -152,12 +281,13 @@ pub mod upgrade_client {
     use ibc::ics02_client::msgs::upgrade_client::MsgUpgradeAnyClient;
     use ibc::ics10_grandpa::client_state::ClientState as GRANDPAClientState;
     use ibc::ics10_grandpa::consensus_state::ConsensusState as GRANDPAConsensusState;
+    use ibc::ics23_commitment::commitment::CommitmentRoot;
     use ibc::ics24_host::identifier::{ChainId, ClientId};
-    use ibc::ics02_client::client_type::ClientType;
     use ibc_proto::ibc::core::commitment::v1::MerkleProof as RawMerkleProof;
     use ibc::signer::Signer;
     use tendermint_proto::Protobuf;
-    use calls::{ibc::DeliverCallExt, NodeRuntime as Runtime};
+    use calls::{config::ChainConfig, ibc::DeliverCallExt, NodeRuntime as Runtime};
+    use std::str::FromStr;
 
     const TYPE_URL: &str = "/ibc.core.client.v1.MsgUpgradeClient";
 
@@ -171,16 +301,17 @@ pub mod upgrade_client {
 
     pub async fn upgrade_client(
         addr: &str,
-        counterparty_addr: &str,
-        identifier: String,
+        pair: sr25519::Pair,
+        tmp: u8,
+        counterparty: &ChainConfig,
     ) -> Result<(), Box<dyn std::error::Error>> {
-        let signer = PairSigner::new(AccountKeyring::Bob.pair());
+        let signer = PairSigner::new(pair);
         println!("signer");
 
 
-        let client_id = ClientId::new(ClientType::Grandpa, 0).unwrap();
+        let client_id = ClientId::from_str(&counterparty.client_identifier)?;
 
-        let chain_id = ChainId::new("ibc-logic-2".to_string(), 2);
+        let chain_id = ChainId::from_string(&counterparty.chain_id);
         println!("chain_id = {:?}", chain_id);
         let latest_height = Height::new(1, 24);
         println!("latest_height = {:?}", latest_height);
@@ -189,12 +320,14 @@ pub mod upgrade_client {
 
         // Create mock grandpa client state
         let client_state = AnyClientState::Grandpa(
-            GRANDPAClientState::new(chain_id, latest_height, frozen_height).unwrap(),
+            GRANDPAClientState::new(chain_id, latest_height, frozen_height, 0, vec![]).unwrap(),
         );
         println!("client_state: {:?}", client_state);
 
         // Create mock grandpa consensus state
-        let consensus_state = AnyConsensusState::Grandpa(GRANDPAConsensusState::new());
+        let consensus_state = AnyConsensusState::Grandpa(GRANDPAConsensusState::new(
+            CommitmentRoot::from(vec![]),
+        ));
         println!("consensus_state = {:?}", consensus_state);
 
         let proof_upgrade_client = get_dummy_merkle_proof();
@@ -223,19 +356,90 @@ pub mod upgrade_client {
             .build()
             .await?;
 
-        let _result = client
-            .deliver(
-                &signer,
-                vec![any],
-                if identifier == "appia-client-id" {
-                    0
-                } else {
-                    1
-                },
-            )
+        let _result = client.deliver(&signer, vec![any], tmp).await?;
+        println!("resut = {:?}", _result);
+
+        Ok(())
+    }
+}
+
+pub mod misbehaviour {
+    use crate::ibc_logic::get_dummy_account_id;
+    use sp_core::sr25519;
+    use std::fs;
+    use std::str::FromStr;
+    use substrate_subxt::{ClientBuilder, PairSigner};
+
+    use ibc::ics02_client::header::AnyHeader;
+    use ibc::ics02_client::misbehaviour::AnyMisbehaviour;
+    use ibc::ics02_client::msgs::misbehavior::{self, MsgSubmitAnyMisbehaviour};
+    use ibc::ics10_grandpa::header::Header as GrandpaHeader;
+    use ibc::ics10_grandpa::misbehaviour::Misbehaviour as GrandpaMisbehaviour;
+    use ibc::ics24_host::identifier::ClientId;
+    use ibc::signer::Signer;
+
+    use tendermint_proto::Protobuf;
+
+    use calls::{ibc::DeliverCallExt, NodeRuntime as Runtime};
+
+    /// Freeze `client_id` on the chain at `addr` with the two conflicting headers in the files
+    /// `header1` and `header2`.
+    pub async fn submit_misbehaviour(
+        addr: &str,
+        pair: sr25519::Pair,
+        tmp: u8,
+        client_id: &str,
+        header1: &str,
+        header2: &str,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let signer = PairSigner::new(pair);
+        println!("signer");
+
+        let (header1, header2) = (read_header(header1)?, read_header(header2)?);
+        if header1.height != header2.height {
+            return Err("the headers are not of the same height".into());
+        }
+        if header1.block_hash == header2.block_hash {
+            return Err("the headers do not conflict".into());
+        }
+
+        let client_id = ClientId::from_str(client_id)?;
+        let tm_signer = get_dummy_account_id();
+        let msg = MsgSubmitAnyMisbehaviour {
+            client_id: client_id.clone(),
+            misbehaviour: AnyMisbehaviour::Grandpa(GrandpaMisbehaviour {
+                client_id,
+                header1,
+                header2,
+            }),
+            signer: Signer::new(tm_signer.to_string()),
+        };
+        println!("msg = {:?}", msg);
+
+        let any = pallet_ibc::Any {
+            type_url: misbehavior::TYPE_URL.to_string(),
+            value: msg.encode_vec().unwrap(),
+        };
+
+        let client = ClientBuilder::<Runtime>::new()
+            .set_url(addr)
+            .build()
             .await?;
+
+        let _result = client.deliver(&signer, vec![any], tmp).await?;
         println!("resut = {:?}", _result);
 
         Ok(())
     }
+
+    /// Read a GRANDPA header, hex encoded as an `AnyHeader`, from the file at `path`.
+    fn read_header(path: &str) -> Result<GrandpaHeader, Box<dyn std::error::Error>> {
+        let contents = fs::read_to_string(path)
+            .map_err(|e| format!("can not read {}: {}", path, e))?;
+        let bytes = hex::decode(contents.trim().trim_start_matches("0x"))?;
+        match AnyHeader::decode_vec(&bytes)? {
+            AnyHeader::Grandpa(header) => Ok(header),
+            _ => Err(format!("{} does not hold a grandpa header", path).into()),
+        }
+    }
 }
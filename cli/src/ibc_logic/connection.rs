@@ -7,8 +7,7 @@ use ibc::ics03_connection::msgs::conn_open_init::MsgConnectionOpenInit;
 use ibc::ics03_connection::version::Version;
 use ibc::ics24_host::identifier::ClientId;
 use ibc::signer::Signer;
-use sp_core::H256;
-use sp_keyring::AccountKeyring;
+use sp_core::{sr25519, H256};
 use std::time::Duration;
 use substrate_subxt::{ClientBuilder, PairSigner};
 use tendermint_proto::Protobuf;
@@ -17,12 +16,14 @@ const TYPE_URL: &str = "/ibc.core.connection.v1.MsgConnectionOpenInit";
 
 pub async fn conn_open_init(
     addr: &str,
+    pair: sr25519::Pair,
+    tmp: u8,
     identifier: String,
     // desired_counterparty_connection_identifier: H256,
     // client_identifier: H256,
     // counterparty_client_identifier: H256,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    let signer = PairSigner::new(AccountKeyring::Bob.pair());
+    let signer = PairSigner::new(pair);
     println!("signer");
 
     let client_id = ClientId::new(ClientType::Grandpa, 0).unwrap();
@@ -52,17 +53,7 @@ pub async fn conn_open_init(
         .build()
         .await?;
 
-    let _result = client
-        .deliver(
-            &signer,
-            vec![any],
-            if identifier == "appia-client-id" {
-                0
-            } else {
-                1
-            },
-        )
-        .await?;
+    let _result = client.deliver(&signer, vec![any], tmp).await?;
     println!("resut = {:?}", _result);
 
     Ok(())
@@ -3,6 +3,8 @@ pub mod client;
 pub mod connection;
 pub mod packet;
 pub mod port;
+pub mod query;
+pub mod transfer;
 
 use std::str::FromStr;
 
@@ -2,11 +2,15 @@ use calls::{
     template::{TestBindPortCallExt, TestReleasePortCallExt},
     NodeRuntime as Runtime,
 };
-use sp_keyring::AccountKeyring;
+use sp_core::sr25519;
 use substrate_subxt::{ClientBuilder, PairSigner};
 
-pub async fn bind_port(addr: &str, identifier: Vec<u8>) -> Result<(), Box<dyn std::error::Error>> {
-    let signer = PairSigner::new(AccountKeyring::Bob.pair());
+pub async fn bind_port(
+    addr: &str,
+    pair: sr25519::Pair,
+    identifier: Vec<u8>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let signer = PairSigner::new(pair);
     let client = ClientBuilder::<Runtime>::new()
         .set_url(addr)
         .build()
@@ -17,9 +21,10 @@ pub async fn bind_port(addr: &str, identifier: Vec<u8>) -> Result<(), Box<dyn st
 
 pub async fn release_port(
     addr: &str,
+    pair: sr25519::Pair,
     identifier: Vec<u8>,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    let signer = PairSigner::new(AccountKeyring::Bob.pair());
+    let signer = PairSigner::new(pair);
     let client = ClientBuilder::<Runtime>::new()
         .set_url(addr)
         .build()
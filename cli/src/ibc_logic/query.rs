@@ -0,0 +1,304 @@
+use calls::{ibc::IbcRpcExt, NodeRuntime as Runtime};
+use codec::Decode;
+use ibc::ics02_client::client_consensus::AnyConsensusState;
+use ibc::ics02_client::client_state::AnyClientState;
+use ibc::ics02_client::height::Height;
+use ibc::ics03_connection::connection::ConnectionEnd;
+use ibc::ics04_channel::channel::{ChannelEnd, Order};
+use serde::Serialize;
+use sp_core::H256;
+use std::error::Error;
+use std::fmt::Debug;
+use substrate_subxt::{BlockNumber, Client, ClientBuilder};
+use tendermint_proto::Protobuf;
+
+/// A query result together with the block it was read at.
+#[derive(Debug, Serialize)]
+struct Output<T> {
+    height: u32,
+    block_hash: H256,
+    result: T,
+}
+
+/// The chain at `addr` and the block at `height`, or at the best block.
+async fn client_at(
+    addr: &str,
+    height: Option<u32>,
+) -> Result<(Client<Runtime>, u32, H256), Box<dyn Error>> {
+    let client = ClientBuilder::<Runtime>::new()
+        .set_url(addr)
+        .build()
+        .await?;
+    let block_hash = client
+        .block_hash(height.map(BlockNumber::from))
+        .await?
+        .ok_or("block not found")?;
+    let height = client
+        .header(Some(block_hash))
+        .await?
+        .ok_or("header not found")?
+        .number;
+    Ok((client, height, block_hash))
+}
+
+fn print<T: Debug + Serialize>(
+    json: bool,
+    height: u32,
+    block_hash: H256,
+    result: T,
+) -> Result<(), Box<dyn Error>> {
+    let output = Output {
+        height,
+        block_hash,
+        result,
+    };
+    if json {
+        println!("{}", serde_json::to_string_pretty(&output)?);
+    } else {
+        println!("{:#?}", output);
+    }
+    Ok(())
+}
+
+pub async fn client_state(
+    addr: &str,
+    height: Option<u32>,
+    json: bool,
+    client_id: &str,
+) -> Result<(), Box<dyn Error>> {
+    let (client, height, block_hash) = client_at(addr, height).await?;
+    let client_state = client
+        .query_client_state(client_id, Some(block_hash))
+        .await?
+        .map(|data| AnyClientState::decode_vec(&data.value))
+        .transpose()?;
+    print(json, height, block_hash, client_state)
+}
+
+pub async fn consensus_state(
+    addr: &str,
+    height: Option<u32>,
+    json: bool,
+    client_id: &str,
+    consensus_height: u64,
+) -> Result<(), Box<dyn Error>> {
+    let (client, height, block_hash) = client_at(addr, height).await?;
+    let consensus_height = Height::new(0, consensus_height).encode_vec().unwrap();
+    let consensus_state = client
+        .query_consensus_state(client_id, consensus_height.clone(), Some(block_hash))
+        .await?
+        .map(|data| data.consensus_state_at(&consensus_height))
+        .transpose()?
+        .flatten()
+        .map(|value| AnyConsensusState::decode_vec(&value))
+        .transpose()?;
+    print(json, height, block_hash, consensus_state)
+}
+
+pub async fn connection(
+    addr: &str,
+    height: Option<u32>,
+    json: bool,
+    connection_id: &str,
+) -> Result<(), Box<dyn Error>> {
+    let (client, height, block_hash) = client_at(addr, height).await?;
+    let connection_end = client
+        .query_connection(connection_id, Some(block_hash))
+        .await?
+        .map(|data| ConnectionEnd::decode_vec(&data.value))
+        .transpose()?;
+    print(json, height, block_hash, connection_end)
+}
+
+pub async fn connections(
+    addr: &str,
+    height: Option<u32>,
+    json: bool,
+) -> Result<(), Box<dyn Error>> {
+    let (client, height, block_hash) = client_at(addr, height).await?;
+    let mut connections = vec![];
+    for (connection_id, data) in client.query_connections(Some(block_hash)).await? {
+        connections.push((connection_id, ConnectionEnd::decode_vec(&data.value)?));
+    }
+    print(json, height, block_hash, connections)
+}
+
+pub async fn channel(
+    addr: &str,
+    height: Option<u32>,
+    json: bool,
+    port_id: &str,
+    channel_id: &str,
+) -> Result<(), Box<dyn Error>> {
+    let (client, height, block_hash) = client_at(addr, height).await?;
+    let channel_end = client
+        .query_channel(port_id, channel_id, Some(block_hash))
+        .await?
+        .map(|data| ChannelEnd::decode_vec(&data.value))
+        .transpose()?;
+    print(json, height, block_hash, channel_end)
+}
+
+pub async fn channels(
+    addr: &str,
+    height: Option<u32>,
+    json: bool,
+) -> Result<(), Box<dyn Error>> {
+    let (client, height, block_hash) = client_at(addr, height).await?;
+    let mut channels = vec![];
+    for (port_id, channel_id, data) in client.query_channels(Some(block_hash)).await? {
+        channels.push((port_id, channel_id, ChannelEnd::decode_vec(&data.value)?));
+    }
+    print(json, height, block_hash, channels)
+}
+
+pub async fn packet_commitments(
+    addr: &str,
+    height: Option<u32>,
+    json: bool,
+    port_id: &str,
+    channel_id: &str,
+) -> Result<(), Box<dyn Error>> {
+    let (client, height, block_hash) = client_at(addr, height).await?;
+    let commitments: Vec<(u64, String)> = client
+        .query_packet_commitments(port_id, channel_id, Some(block_hash))
+        .await?
+        .into_iter()
+        .map(|(sequence, data)| (sequence, hex::encode(&data.value[..])))
+        .collect();
+    print(json, height, block_hash, commitments)
+}
+
+/// The channel end `(port_id, channel_id)` and the sequences of the packets committed on it at
+/// `block_hash`.
+async fn pending_sequences(
+    client: &Client<Runtime>,
+    block_hash: H256,
+    port_id: &str,
+    channel_id: &str,
+) -> Result<(ChannelEnd, Vec<u64>), Box<dyn Error>> {
+    let channel_end = client
+        .query_channel(port_id, channel_id, Some(block_hash))
+        .await?
+        .map(|data| ChannelEnd::decode_vec(&data.value))
+        .transpose()?
+        .ok_or("channel not found")?;
+    let sequences = client
+        .query_packet_commitments(port_id, channel_id, Some(block_hash))
+        .await?
+        .into_iter()
+        .map(|(sequence, _)| sequence)
+        .collect();
+    Ok((channel_end, sequences))
+}
+
+/// List the packets committed on `(port_id, channel_id)` at `height` that the counterparty
+/// at `counterparty_addr` has not received by its best block.
+pub async fn unreceived_packets(
+    addr: &str,
+    height: Option<u32>,
+    json: bool,
+    counterparty_addr: &str,
+    port_id: &str,
+    channel_id: &str,
+) -> Result<(), Box<dyn Error>> {
+    let (client, height, block_hash) = client_at(addr, height).await?;
+    let (channel_end, sequences) =
+        pending_sequences(&client, block_hash, port_id, channel_id).await?;
+    let counterparty = channel_end.counterparty();
+    let counterparty_port_id = counterparty.port_id().to_string();
+    let counterparty_channel_id = counterparty
+        .channel_id()
+        .ok_or("counterparty channel is not open")?
+        .to_string();
+    let counterparty_client = ClientBuilder::<Runtime>::new()
+        .set_url(counterparty_addr)
+        .build()
+        .await?;
+
+    let mut unreceived = vec![];
+    if channel_end.ordering() == &Order::Ordered {
+        let next_sequence_recv = counterparty_client
+            .query_next_sequence_recv(&counterparty_port_id, &counterparty_channel_id, None)
+            .await?
+            .ok_or("next sequence recv not found")?;
+        let next_sequence_recv = u64::decode(&mut &next_sequence_recv.value[..])?;
+        unreceived.extend(
+            sequences
+                .into_iter()
+                .filter(|sequence| *sequence >= next_sequence_recv),
+        );
+    } else {
+        for sequence in sequences {
+            let receipt = counterparty_client
+                .query_packet_receipt(
+                    &counterparty_port_id,
+                    &counterparty_channel_id,
+                    sequence,
+                    None,
+                )
+                .await?;
+            if receipt.value.is_empty() {
+                unreceived.push(sequence);
+            }
+        }
+    }
+    print(json, height, block_hash, unreceived)
+}
+
+/// List the packets committed on `(port_id, channel_id)` at `height` whose acknowledgement
+/// the counterparty at `counterparty_addr` has written by its best block.
+pub async fn unreceived_acks(
+    addr: &str,
+    height: Option<u32>,
+    json: bool,
+    counterparty_addr: &str,
+    port_id: &str,
+    channel_id: &str,
+) -> Result<(), Box<dyn Error>> {
+    let (client, height, block_hash) = client_at(addr, height).await?;
+    let (channel_end, sequences) =
+        pending_sequences(&client, block_hash, port_id, channel_id).await?;
+    let counterparty = channel_end.counterparty();
+    let counterparty_port_id = counterparty.port_id().to_string();
+    let counterparty_channel_id = counterparty
+        .channel_id()
+        .ok_or("counterparty channel is not open")?
+        .to_string();
+    let counterparty_client = ClientBuilder::<Runtime>::new()
+        .set_url(counterparty_addr)
+        .build()
+        .await?;
+
+    let mut unreceived = vec![];
+    for sequence in sequences {
+        let acknowledgement = counterparty_client
+            .query_packet_acknowledgement(
+                &counterparty_port_id,
+                &counterparty_channel_id,
+                sequence,
+                None,
+            )
+            .await?;
+        if acknowledgement.is_some() {
+            unreceived.push(sequence);
+        }
+    }
+    print(json, height, block_hash, unreceived)
+}
+
+pub async fn next_sequence(
+    addr: &str,
+    height: Option<u32>,
+    json: bool,
+    port_id: &str,
+    channel_id: &str,
+) -> Result<(), Box<dyn Error>> {
+    let (client, height, block_hash) = client_at(addr, height).await?;
+    let next_sequence_recv = client
+        .query_next_sequence_recv(port_id, channel_id, Some(block_hash))
+        .await?
+        .map(|data| u64::decode(&mut &data.value[..]))
+        .transpose()?;
+    print(json, height, block_hash, next_sequence_recv)
+}
@@ -0,0 +1,111 @@
+use calls::{ibc::IbcRpcExt, transfer::TransferCallExt, NodeRuntime as Runtime};
+use ibc::application::ics20_fungible_token_transfer::msgs::transfer::{MsgTransfer, TYPE_URL};
+use ibc::ics02_client::client_state::AnyClientState;
+use ibc::ics03_connection::connection::ConnectionEnd;
+use ibc::ics04_channel::channel::ChannelEnd;
+use ibc::ics24_host::identifier::{ChannelId, PortId};
+use ibc::signer::Signer;
+use ibc::Height;
+use ibc_proto::cosmos::base::v1beta1::Coin;
+use sp_core::{crypto::AccountId32, crypto::Ss58Codec, sr25519};
+use std::str::FromStr;
+use substrate_subxt::{Client, ClientBuilder, PairSigner, Signer as _};
+use tendermint_proto::Protobuf;
+
+/// How many counterparty blocks a transfer given no timeout has before it times out.
+const DEFAULT_TIMEOUT_BLOCKS: u64 = 1000;
+
+/// Accounts are hex encoded in ICS-20 packet data, as the transfer pallet expects them.
+fn encode_receiver(receiver: &str) -> Result<String, Box<dyn std::error::Error>> {
+    if receiver.starts_with("0x") {
+        return Ok(receiver.to_string());
+    }
+    let account = AccountId32::from_ss58check(receiver)
+        .map_err(|e| format!("invalid receiver {}: {:?}", receiver, e))?;
+    Ok(format!("0x{}", hex::encode(account)))
+}
+
+/// The counterparty height `DEFAULT_TIMEOUT_BLOCKS` past the latest one the channel's client
+/// on `client` has seen.
+async fn default_timeout_height(
+    client: &Client<Runtime>,
+    port_id: &str,
+    channel_id: &str,
+) -> Result<u64, Box<dyn std::error::Error>> {
+    let channel = client
+        .query_channel(port_id, channel_id, None)
+        .await?
+        .ok_or("channel not found")?;
+    let channel_end = ChannelEnd::decode_vec(&channel.value)?;
+    let connection_id = channel_end
+        .connection_hops()
+        .first()
+        .ok_or("channel without connection")?;
+    let connection = client
+        .query_connection(connection_id.as_str(), None)
+        .await?
+        .ok_or("connection not found")?;
+    let client_id = ConnectionEnd::decode_vec(&connection.value)?
+        .client_id()
+        .to_string();
+    let client_state = client
+        .query_client_state(&client_id, None)
+        .await?
+        .ok_or("client state not found")?;
+    let latest_height = match AnyClientState::decode_vec(&client_state.value)? {
+        AnyClientState::Grandpa(client_state) => client_state.latest_height.revision_height,
+        _ => return Err("not a grandpa client".into()),
+    };
+    Ok(latest_height + DEFAULT_TIMEOUT_BLOCKS)
+}
+
+pub async fn transfer(
+    addr: &str,
+    pair: sr25519::Pair,
+    source_port: &str,
+    source_channel: &str,
+    denom: &str,
+    amount: u128,
+    receiver: &str,
+    timeout_height: u64,
+    timeout_timestamp: u64,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let signer = PairSigner::new(pair);
+
+    let client = ClientBuilder::<Runtime>::new()
+        .set_url(addr)
+        .build()
+        .await?;
+
+    // A packet without either timeout could never be refunded if the counterparty stops
+    // relaying, so fall back to a height relative to the counterparty's latest one.
+    let timeout_height = if timeout_height == 0 && timeout_timestamp == 0 {
+        default_timeout_height(&client, source_port, source_channel).await?
+    } else {
+        timeout_height
+    };
+
+    let msg = MsgTransfer {
+        source_port: PortId::from_str(source_port)?,
+        source_channel: ChannelId::from_str(source_channel)?,
+        token: Some(Coin {
+            denom: denom.to_string(),
+            amount: amount.to_string(),
+        }),
+        sender: Signer::new(signer.account_id().to_string()),
+        receiver: Signer::new(encode_receiver(receiver)?),
+        timeout_height: Height::new(0, timeout_height),
+        timeout_timestamp,
+    };
+    println!("msg = {:?}", msg);
+
+    let any = pallet_ibc::Any {
+        type_url: TYPE_URL.to_string(),
+        value: msg.encode_vec().unwrap(),
+    };
+
+    let result = client.transfer(&signer, any).await?;
+    println!("result = {:?}", result);
+
+    Ok(())
+}
@@ -8,7 +8,7 @@ use sp_finality_grandpa::AuthorityId as GrandpaId;
 use sp_runtime::traits::{IdentifyAccount, Verify};
 
 use beefy_primitives::crypto::AuthorityId as BeefyId;
-use node_template_runtime::BeefyConfig;
+use node_template_runtime::{BeefyConfig, IbcConfig};
 use node_template_runtime::{
 	opaque::SessionKeys, Balance, ImOnlineConfig, SessionConfig, StakingConfig, DOLLARS,
 };
@@ -246,5 +246,6 @@ fn testnet_genesis(
 		im_online: ImOnlineConfig { keys: vec![] },
 		grandpa: GrandpaConfig { authorities: vec![] },
 		beefy: BeefyConfig { authorities: vec![] },
+		ibc: IbcConfig::default(),
 	}
 }
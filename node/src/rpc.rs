@@ -8,7 +8,7 @@
 use std::sync::Arc;
 
 use node_template_runtime::{opaque::Block, AccountId, Balance, BlockNumber, Hash, Index};
-use sc_client_api::AuxStore;
+use sc_client_api::{AuxStore, ProofProvider};
 use sc_consensus_babe::{Config, Epoch};
 use sc_consensus_babe_rpc::BabeRpcHandler;
 use sc_consensus_epochs::SharedEpochChanges;
@@ -103,6 +103,7 @@ pub fn create_full<C, P, SC, B, BT>(
 where
 	C: ProvideRuntimeApi<Block>
 		+ HeaderBackend<Block>
+		+ ProofProvider<Block>
 		+ AuxStore
 		+ HeaderMetadata<Block, Error = BlockChainError>
 		+ Sync
@@ -113,13 +114,16 @@ where
 	C::Api: pallet_transaction_payment_rpc::TransactionPaymentRuntimeApi<Block, Balance>,
 	C::Api: BabeApi<Block>,
 	C::Api: BlockBuilder<Block>,
+	C::Api: pallet_ibc_rpc::IbcRuntimeApi<Block>,
 	P: TransactionPool + 'static,
 	SC: SelectChain<Block> + 'static,
 	B: sc_client_api::Backend<Block> + Send + Sync + 'static,
 	B::State: sc_client_api::backend::StateBackend<sp_runtime::traits::HashFor<Block>>,
 	BT: BlockT,
 {
+	use pallet_ibc_rpc::{Ibc, IbcApi};
 	use pallet_mmr_rpc::{Mmr, MmrApi};
+	use pallet_mmr_leaf_rpc::{MmrLeafApi, MmrLeafRpc};
 	use pallet_transaction_payment_rpc::{TransactionPayment, TransactionPaymentApi};
 	use substrate_frame_rpc_system::{FullSystem, SystemApi};
 
@@ -141,7 +145,9 @@ where
 	// more context: https://github.com/paritytech/substrate/pull/3480
 	// These RPCs should use an asynchronous caller instead.
 	io.extend_with(MmrApi::to_delegate(Mmr::new(client.clone())));
+	io.extend_with(MmrLeafApi::to_delegate(MmrLeafRpc::new(client.clone())));
 	io.extend_with(TransactionPaymentApi::to_delegate(TransactionPayment::new(client.clone())));
+	io.extend_with(IbcApi::to_delegate(Ibc::new(client.clone())));
 	io.extend_with(sc_consensus_babe_rpc::BabeApi::to_delegate(BabeRpcHandler::new(
 		client.clone(),
 		shared_epoch_changes.clone(),
@@ -28,7 +28,7 @@ use sc_service::{
 	config::Configuration, error::Error as ServiceError, RpcHandlers, TaskManager,
 };
 use sc_network::NetworkService;
-use sp_runtime::traits::Block as BlockT;
+use sp_runtime::{generic::BlockId, traits::Block as BlockT};
 use sc_client_api::{ExecutorProvider, RemoteBackend};
 use sc_telemetry::{Telemetry, TelemetryWorker};
 use sc_consensus_babe::SlotProportion;
@@ -52,6 +52,17 @@ type FullGrandpaBlockImport =
 	grandpa::GrandpaBlockImport<FullBackend, Block, FullClient, FullSelectChain>;
 type LightClient = sc_service::TLightClient<Block, RuntimeApi, Executor>;
 
+/// Provides the state root of block `parent` to the block built on it, which sets it for its MMR
+/// leaf to commit.
+fn parent_state_root_inherent_data_provider(
+	client: &FullClient,
+	parent: <Block as BlockT>::Hash,
+) -> Result<pallet_mmr_leaf::InherentDataProvider, sp_blockchain::Error> {
+	let header = sp_blockchain::HeaderBackend::header(client, BlockId::Hash(parent))?
+		.ok_or_else(|| sp_blockchain::Error::UnknownBlock(parent.to_string()))?;
+	Ok(pallet_mmr_leaf::InherentDataProvider(header.state_root))
+}
+
 pub fn new_partial(
 	config: &Configuration,
 ) -> Result<sc_service::PartialComponents<
@@ -119,6 +130,7 @@ pub fn new_partial(
 		client.clone(),
 	)?;
 
+	let client_clone = client.clone();
 	let slot_duration = babe_link.config().slot_duration();
 	let import_queue = sc_consensus_babe::import_queue(
 		babe_link.clone(),
@@ -126,7 +138,8 @@ pub fn new_partial(
 		Some(Box::new(justification_import)),
 		client.clone(),
 		select_chain.clone(),
-		move |_, ()| {
+		move |parent, ()| {
+			let client_clone = client_clone.clone();
 			async move {
 				let timestamp = sp_timestamp::InherentDataProvider::from_system_time();
 
@@ -139,7 +152,10 @@ pub fn new_partial(
 				let uncles =
 					sp_authorship::InherentDataProvider::<<Block as BlockT>::Header>::check_inherents();
 
-				Ok((timestamp, slot, uncles))
+				let parent_state_root =
+					parent_state_root_inherent_data_provider(&*client_clone, parent)?;
+
+				Ok((timestamp, slot, uncles, parent_state_root))
 			}
 		},
 		&task_manager.spawn_essential_handle(),
@@ -343,7 +359,10 @@ pub fn new_full_base(
 							slot_duration,
 						);
 
-					Ok((timestamp, slot, uncles))
+					let parent_state_root =
+						parent_state_root_inherent_data_provider(&*client_clone, parent)?;
+
+					Ok((timestamp, slot, uncles, parent_state_root))
 				}
 			},
 			force_authoring,
@@ -0,0 +1,73 @@
+//! Runtime API definition for querying pallet-ibc state.
+//!
+//! The runtime only knows where each piece of IBC state lives, so every query returns the raw
+//! value together with the storage key it was read from. The node side uses that key to
+//! generate a storage proof at the same block.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+use codec::{Decode, Encode};
+use sp_runtime::RuntimeDebug;
+use sp_std::vec::Vec;
+
+/// A value read from pallet-ibc storage along with its full storage key.
+#[derive(Clone, Default, Encode, Decode, PartialEq, Eq, RuntimeDebug)]
+pub struct IbcStorageEntry {
+	/// The hashed storage key the value was read from.
+	pub key: Vec<u8>,
+	/// The encoded value.
+	pub value: Vec<u8>,
+}
+
+sp_api::decl_runtime_apis! {
+	/// The API to query client, connection, channel and packet state of pallet-ibc.
+	///
+	/// Identifiers are passed in the form pallet-ibc stores them, i.e. the bytes of their string
+	/// representation.
+	pub trait IbcApi {
+		/// Returns the client state of `client_id`.
+		fn client_state(client_id: Vec<u8>) -> Option<IbcStorageEntry>;
+
+		/// Returns the consensus states of `client_id` if it has one at the protobuf-encoded
+		/// `height`, as stored: a list of `(height, consensus state)` pairs under a single key.
+		fn consensus_state(client_id: Vec<u8>, height: Vec<u8>) -> Option<IbcStorageEntry>;
+
+		/// Returns the connection end of `connection_id`.
+		fn connection(connection_id: Vec<u8>) -> Option<IbcStorageEntry>;
+
+		/// Returns all connection ends, keyed by connection identifier.
+		fn connections() -> Vec<(Vec<u8>, IbcStorageEntry)>;
+
+		/// Returns the channel end of `(port_id, channel_id)`.
+		fn channel(port_id: Vec<u8>, channel_id: Vec<u8>) -> Option<IbcStorageEntry>;
+
+		/// Returns all channel ends, keyed by port and channel identifier.
+		fn channels() -> Vec<(Vec<u8>, Vec<u8>, IbcStorageEntry)>;
+
+		/// Returns the commitment of packet `sequence` sent on `(port_id, channel_id)`.
+		fn packet_commitment(
+			port_id: Vec<u8>,
+			channel_id: Vec<u8>,
+			sequence: u64,
+		) -> Option<IbcStorageEntry>;
+
+		/// Returns all packet commitments on `(port_id, channel_id)`, keyed by sequence.
+		fn packet_commitments(port_id: Vec<u8>, channel_id: Vec<u8>) -> Vec<(u64, IbcStorageEntry)>;
+
+		/// Returns the acknowledgement written for packet `sequence` on `(port_id, channel_id)`.
+		fn packet_acknowledgement(
+			port_id: Vec<u8>,
+			channel_id: Vec<u8>,
+			sequence: u64,
+		) -> Option<IbcStorageEntry>;
+
+		/// Returns the receipt of packet `sequence` received on `(port_id, channel_id)`.
+		///
+		/// The value is empty if the packet has not been received, so that the node can still
+		/// prove its absence for timeouts.
+		fn packet_receipt(port_id: Vec<u8>, channel_id: Vec<u8>, sequence: u64) -> IbcStorageEntry;
+
+		/// Returns the next sequence expected to be received on `(port_id, channel_id)`.
+		fn next_sequence_recv(port_id: Vec<u8>, channel_id: Vec<u8>) -> Option<IbcStorageEntry>;
+	}
+}
@@ -0,0 +1,412 @@
+//! RPC interface for querying pallet-ibc state.
+//!
+//! Every method returns the requested value together with a storage proof generated at the same
+//! block, so that relayers can hand both to the counterparty chain without knowing the storage
+//! layout of pallet-ibc.
+
+use std::sync::Arc;
+
+use jsonrpc_core::{Error as RpcError, ErrorCode, Result};
+use jsonrpc_derive::rpc;
+use serde::{Deserialize, Serialize};
+use sp_api::ProvideRuntimeApi;
+use sp_blockchain::HeaderBackend;
+use sp_core::Bytes;
+use sp_runtime::{
+	generic::BlockId,
+	traits::{Block as BlockT, NumberFor},
+};
+
+pub use pallet_ibc_rpc_runtime_api::{IbcApi as IbcRuntimeApi, IbcStorageEntry};
+
+/// A pallet-ibc value with the proof of its inclusion in the state of `block_hash`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StateResponse<BlockNumber, BlockHash> {
+	/// The encoded value.
+	pub value: Bytes,
+	/// The trie nodes proving `value` against the state root of `block_hash`.
+	pub proof: Vec<Bytes>,
+	/// The number of the block the value was read at.
+	pub height: BlockNumber,
+	/// The hash of the block the value was read at.
+	pub block_hash: BlockHash,
+}
+
+/// IBC RPC methods.
+#[rpc]
+pub trait IbcApi<BlockHash, BlockNumber> {
+	/// Query the client state of `client_id`.
+	#[rpc(name = "ibc_queryClientState")]
+	fn query_client_state(
+		&self,
+		client_id: String,
+		at: Option<BlockHash>,
+	) -> Result<Option<StateResponse<BlockNumber, BlockHash>>>;
+
+	/// Query the consensus state of `client_id` at the protobuf-encoded `height`.
+	///
+	/// The consensus states of a client are stored together, so the value is the SCALE encoded
+	/// list of `(height, consensus state)` pairs the proof covers, which contains `height`.
+	#[rpc(name = "ibc_queryConsensusState")]
+	fn query_consensus_state(
+		&self,
+		client_id: String,
+		height: Bytes,
+		at: Option<BlockHash>,
+	) -> Result<Option<StateResponse<BlockNumber, BlockHash>>>;
+
+	/// Query the connection end of `connection_id`.
+	#[rpc(name = "ibc_queryConnection")]
+	fn query_connection(
+		&self,
+		connection_id: String,
+		at: Option<BlockHash>,
+	) -> Result<Option<StateResponse<BlockNumber, BlockHash>>>;
+
+	/// Query all connection ends, paired with their connection identifiers.
+	#[rpc(name = "ibc_queryConnections")]
+	fn query_connections(
+		&self,
+		at: Option<BlockHash>,
+	) -> Result<Vec<(String, StateResponse<BlockNumber, BlockHash>)>>;
+
+	/// Query the channel end of `(port_id, channel_id)`.
+	#[rpc(name = "ibc_queryChannel")]
+	fn query_channel(
+		&self,
+		port_id: String,
+		channel_id: String,
+		at: Option<BlockHash>,
+	) -> Result<Option<StateResponse<BlockNumber, BlockHash>>>;
+
+	/// Query all channel ends, paired with their port and channel identifiers.
+	#[rpc(name = "ibc_queryChannels")]
+	fn query_channels(
+		&self,
+		at: Option<BlockHash>,
+	) -> Result<Vec<(String, String, StateResponse<BlockNumber, BlockHash>)>>;
+
+	/// Query the commitment of packet `sequence` sent on `(port_id, channel_id)`.
+	#[rpc(name = "ibc_queryPacketCommitment")]
+	fn query_packet_commitment(
+		&self,
+		port_id: String,
+		channel_id: String,
+		sequence: u64,
+		at: Option<BlockHash>,
+	) -> Result<Option<StateResponse<BlockNumber, BlockHash>>>;
+
+	/// Query all packet commitments on `(port_id, channel_id)`, paired with their sequences.
+	#[rpc(name = "ibc_queryPacketCommitments")]
+	fn query_packet_commitments(
+		&self,
+		port_id: String,
+		channel_id: String,
+		at: Option<BlockHash>,
+	) -> Result<Vec<(u64, StateResponse<BlockNumber, BlockHash>)>>;
+
+	/// Query the acknowledgement of packet `sequence` received on `(port_id, channel_id)`.
+	#[rpc(name = "ibc_queryPacketAcknowledgement")]
+	fn query_packet_acknowledgement(
+		&self,
+		port_id: String,
+		channel_id: String,
+		sequence: u64,
+		at: Option<BlockHash>,
+	) -> Result<Option<StateResponse<BlockNumber, BlockHash>>>;
+
+	/// Query the receipt of packet `sequence` received on `(port_id, channel_id)`.
+	///
+	/// The value is empty if the packet has not been received, and the proof then proves the
+	/// absence of the receipt.
+	#[rpc(name = "ibc_queryPacketReceipt")]
+	fn query_packet_receipt(
+		&self,
+		port_id: String,
+		channel_id: String,
+		sequence: u64,
+		at: Option<BlockHash>,
+	) -> Result<StateResponse<BlockNumber, BlockHash>>;
+
+	/// Query the next sequence expected to be received on `(port_id, channel_id)`.
+	#[rpc(name = "ibc_queryNextSequenceRecv")]
+	fn query_next_sequence_recv(
+		&self,
+		port_id: String,
+		channel_id: String,
+		at: Option<BlockHash>,
+	) -> Result<Option<StateResponse<BlockNumber, BlockHash>>>;
+}
+
+/// Error code for failed runtime API calls.
+const RUNTIME_ERROR: i64 = 1;
+/// Error code for failures while reading storage proofs or block headers.
+const CLIENT_ERROR: i64 = 2;
+
+fn runtime_error(err: impl std::fmt::Debug) -> RpcError {
+	RpcError {
+		code: ErrorCode::ServerError(RUNTIME_ERROR),
+		message: "Unable to query IBC state.".into(),
+		data: Some(format!("{:?}", err).into()),
+	}
+}
+
+fn client_error(err: impl std::fmt::Debug) -> RpcError {
+	RpcError {
+		code: ErrorCode::ServerError(CLIENT_ERROR),
+		message: "Unable to generate IBC state proof.".into(),
+		data: Some(format!("{:?}", err).into()),
+	}
+}
+
+fn utf8(bytes: Vec<u8>) -> String {
+	String::from_utf8_lossy(&bytes).into_owned()
+}
+
+/// An implementation of IBC specific RPC methods.
+pub struct Ibc<C, B> {
+	client: Arc<C>,
+	_marker: std::marker::PhantomData<B>,
+}
+
+impl<C, B> Ibc<C, B> {
+	/// Create new `Ibc` with the given reference to the client.
+	pub fn new(client: Arc<C>) -> Self {
+		Self { client, _marker: Default::default() }
+	}
+}
+
+impl<C, Block> Ibc<C, Block>
+where
+	Block: BlockT,
+	C: HeaderBackend<Block> + sc_client_api::ProofProvider<Block>,
+{
+	fn block_hash(&self, at: Option<Block::Hash>) -> Block::Hash {
+		// If the block hash is not supplied assume the finalized block, the latest one a light
+		// client of the chain can verify proofs against.
+		at.unwrap_or_else(|| self.client.info().finalized_hash)
+	}
+
+	/// Attach a storage proof of `entry` at block `at`.
+	fn prove(
+		&self,
+		at: Block::Hash,
+		entry: IbcStorageEntry,
+	) -> Result<StateResponse<NumberFor<Block>, Block::Hash>> {
+		let height = self
+			.client
+			.number(at)
+			.map_err(client_error)?
+			.ok_or_else(|| client_error(format!("unknown block {:?}", at)))?;
+		let proof = self
+			.client
+			.read_proof(&BlockId::hash(at), &mut std::iter::once(entry.key.as_slice()))
+			.map_err(client_error)?;
+
+		Ok(StateResponse {
+			value: entry.value.into(),
+			proof: proof.iter_nodes().map(Into::into).collect(),
+			height,
+			block_hash: at,
+		})
+	}
+
+	fn prove_optional(
+		&self,
+		at: Block::Hash,
+		entry: Option<IbcStorageEntry>,
+	) -> Result<Option<StateResponse<NumberFor<Block>, Block::Hash>>> {
+		entry.map(|entry| self.prove(at, entry)).transpose()
+	}
+}
+
+impl<C, Block> IbcApi<<Block as BlockT>::Hash, NumberFor<Block>> for Ibc<C, Block>
+where
+	Block: BlockT,
+	C: 'static + ProvideRuntimeApi<Block> + HeaderBackend<Block>,
+	C: sc_client_api::ProofProvider<Block> + Send + Sync,
+	C::Api: IbcRuntimeApi<Block>,
+{
+	fn query_client_state(
+		&self,
+		client_id: String,
+		at: Option<Block::Hash>,
+	) -> Result<Option<StateResponse<NumberFor<Block>, Block::Hash>>> {
+		let at = self.block_hash(at);
+		let entry = self
+			.client
+			.runtime_api()
+			.client_state(&BlockId::hash(at), client_id.into_bytes())
+			.map_err(runtime_error)?;
+		self.prove_optional(at, entry)
+	}
+
+	fn query_consensus_state(
+		&self,
+		client_id: String,
+		height: Bytes,
+		at: Option<Block::Hash>,
+	) -> Result<Option<StateResponse<NumberFor<Block>, Block::Hash>>> {
+		let at = self.block_hash(at);
+		let entry = self
+			.client
+			.runtime_api()
+			.consensus_state(&BlockId::hash(at), client_id.into_bytes(), height.to_vec())
+			.map_err(runtime_error)?;
+		self.prove_optional(at, entry)
+	}
+
+	fn query_connection(
+		&self,
+		connection_id: String,
+		at: Option<Block::Hash>,
+	) -> Result<Option<StateResponse<NumberFor<Block>, Block::Hash>>> {
+		let at = self.block_hash(at);
+		let entry = self
+			.client
+			.runtime_api()
+			.connection(&BlockId::hash(at), connection_id.into_bytes())
+			.map_err(runtime_error)?;
+		self.prove_optional(at, entry)
+	}
+
+	fn query_connections(
+		&self,
+		at: Option<Block::Hash>,
+	) -> Result<Vec<(String, StateResponse<NumberFor<Block>, Block::Hash>)>> {
+		let at = self.block_hash(at);
+		self.client
+			.runtime_api()
+			.connections(&BlockId::hash(at))
+			.map_err(runtime_error)?
+			.into_iter()
+			.map(|(connection_id, entry)| Ok((utf8(connection_id), self.prove(at, entry)?)))
+			.collect()
+	}
+
+	fn query_channel(
+		&self,
+		port_id: String,
+		channel_id: String,
+		at: Option<Block::Hash>,
+	) -> Result<Option<StateResponse<NumberFor<Block>, Block::Hash>>> {
+		let at = self.block_hash(at);
+		let entry = self
+			.client
+			.runtime_api()
+			.channel(&BlockId::hash(at), port_id.into_bytes(), channel_id.into_bytes())
+			.map_err(runtime_error)?;
+		self.prove_optional(at, entry)
+	}
+
+	fn query_channels(
+		&self,
+		at: Option<Block::Hash>,
+	) -> Result<Vec<(String, String, StateResponse<NumberFor<Block>, Block::Hash>)>> {
+		let at = self.block_hash(at);
+		self.client
+			.runtime_api()
+			.channels(&BlockId::hash(at))
+			.map_err(runtime_error)?
+			.into_iter()
+			.map(|(port_id, channel_id, entry)| {
+				Ok((utf8(port_id), utf8(channel_id), self.prove(at, entry)?))
+			})
+			.collect()
+	}
+
+	fn query_packet_commitment(
+		&self,
+		port_id: String,
+		channel_id: String,
+		sequence: u64,
+		at: Option<Block::Hash>,
+	) -> Result<Option<StateResponse<NumberFor<Block>, Block::Hash>>> {
+		let at = self.block_hash(at);
+		let entry = self
+			.client
+			.runtime_api()
+			.packet_commitment(
+				&BlockId::hash(at),
+				port_id.into_bytes(),
+				channel_id.into_bytes(),
+				sequence,
+			)
+			.map_err(runtime_error)?;
+		self.prove_optional(at, entry)
+	}
+
+	fn query_packet_commitments(
+		&self,
+		port_id: String,
+		channel_id: String,
+		at: Option<Block::Hash>,
+	) -> Result<Vec<(u64, StateResponse<NumberFor<Block>, Block::Hash>)>> {
+		let at = self.block_hash(at);
+		self.client
+			.runtime_api()
+			.packet_commitments(&BlockId::hash(at), port_id.into_bytes(), channel_id.into_bytes())
+			.map_err(runtime_error)?
+			.into_iter()
+			.map(|(sequence, entry)| Ok((sequence, self.prove(at, entry)?)))
+			.collect()
+	}
+
+	fn query_packet_acknowledgement(
+		&self,
+		port_id: String,
+		channel_id: String,
+		sequence: u64,
+		at: Option<Block::Hash>,
+	) -> Result<Option<StateResponse<NumberFor<Block>, Block::Hash>>> {
+		let at = self.block_hash(at);
+		let entry = self
+			.client
+			.runtime_api()
+			.packet_acknowledgement(
+				&BlockId::hash(at),
+				port_id.into_bytes(),
+				channel_id.into_bytes(),
+				sequence,
+			)
+			.map_err(runtime_error)?;
+		self.prove_optional(at, entry)
+	}
+
+	fn query_packet_receipt(
+		&self,
+		port_id: String,
+		channel_id: String,
+		sequence: u64,
+		at: Option<Block::Hash>,
+	) -> Result<StateResponse<NumberFor<Block>, Block::Hash>> {
+		let at = self.block_hash(at);
+		let entry = self
+			.client
+			.runtime_api()
+			.packet_receipt(
+				&BlockId::hash(at),
+				port_id.into_bytes(),
+				channel_id.into_bytes(),
+				sequence,
+			)
+			.map_err(runtime_error)?;
+		self.prove(at, entry)
+	}
+
+	fn query_next_sequence_recv(
+		&self,
+		port_id: String,
+		channel_id: String,
+		at: Option<Block::Hash>,
+	) -> Result<Option<StateResponse<NumberFor<Block>, Block::Hash>>> {
+		let at = self.block_hash(at);
+		let entry = self
+			.client
+			.runtime_api()
+			.next_sequence_recv(&BlockId::hash(at), port_id.into_bytes(), channel_id.into_bytes())
+			.map_err(runtime_error)?;
+		self.prove_optional(at, entry)
+	}
+}
@@ -0,0 +1,404 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
+//! ICS-20 fungible token transfer.
+//!
+//! Native tokens sent to a counterparty are escrowed in an account of the channel they leave
+//! through, and released when they come back. Tokens received from a counterparty are minted as
+//! voucher assets in pallet-assets, one asset per denomination trace, and burnt when sent back.
+//! If a packet times out or is acknowledged with an error, the sender is refunded.
+//!
+//! The runtime routes the channels and packets of [`PORT_ID`] to the `on_*` hooks of this
+//! pallet.
+
+extern crate alloc;
+
+pub use pallet::*;
+
+pub mod packet;
+
+#[cfg(test)]
+mod mock;
+
+#[cfg(test)]
+mod tests;
+
+/// The port the transfer module is bound to.
+pub const PORT_ID: &str = "transfer";
+
+/// The channel version of ICS-20.
+pub const VERSION: &str = "ics20-1";
+
+#[frame_support::pallet]
+pub mod pallet {
+	use crate::packet::{self, Acknowledgement, FungibleTokenPacketData};
+	use crate::{PORT_ID, VERSION};
+	use alloc::{
+		format,
+		string::{String, ToString},
+	};
+	use codec::{DecodeAll, Encode};
+	use frame_support::{
+		dispatch::DispatchResult,
+		pallet_prelude::*,
+		storage::{with_transaction, TransactionOutcome},
+		traits::{
+			tokens::fungibles::{Create, Inspect, Mutate, Transfer},
+			Currency, ExistenceRequirement,
+		},
+		transactional, PalletId,
+	};
+	use frame_system::pallet_prelude::*;
+	use ibc::application::ics20_fungible_token_transfer::msgs::transfer::{MsgTransfer, TYPE_URL};
+	use ibc::ics04_channel::channel::Order;
+	use ibc::ics04_channel::packet::{Packet, Sequence};
+	use ibc::ics24_host::identifier::{ChannelId, PortId};
+	use ibc_proto::ibc::applications::transfer::v1::MsgTransfer as RawMsgTransfer;
+	use pallet_ibc_utils::ChannelError;
+	use prost::Message;
+	use sp_runtime::traits::{AccountIdConversion, AtLeast32BitUnsigned, One, Zero};
+	use sp_std::{convert::TryFrom, prelude::*};
+
+	type BalanceOf<T> =
+		<<T as Config>::Currency as Currency<<T as frame_system::Config>::AccountId>>::Balance;
+	type AssetBalanceOf<T> =
+		<<T as Config>::Assets as Inspect<<T as frame_system::Config>::AccountId>>::Balance;
+
+	#[pallet::config]
+	pub trait Config: frame_system::Config + pallet_ibc::Config {
+		type Event: From<Event<Self>> + IsType<<Self as frame_system::Config>::Event>;
+
+		/// The native currency, escrowed while on a counterparty.
+		type Currency: Currency<Self::AccountId>;
+
+		/// The denomination of the native currency in ICS-20 packets.
+		type NativeDenom: Get<&'static str>;
+
+		type AssetId: Parameter
+			+ Member
+			+ Copy
+			+ Default
+			+ AtLeast32BitUnsigned
+			+ MaybeSerializeDeserialize;
+
+		/// The assets the vouchers of counterparty tokens are minted in.
+		type Assets: Create<Self::AccountId, AssetId = Self::AssetId>
+			+ Mutate<Self::AccountId>
+			+ Transfer<Self::AccountId>;
+
+		/// Derives the escrow account of each channel, which also administers the vouchers.
+		#[pallet::constant]
+		type PalletId: Get<PalletId>;
+	}
+
+	#[pallet::pallet]
+	#[pallet::generate_store(pub(super) trait Store)]
+	pub struct Pallet<T>(_);
+
+	/// The denomination trace of each voucher asset, e.g. `transfer/channel-0/UNIT`.
+	#[pallet::storage]
+	#[pallet::getter(fn denom_trace)]
+	pub type DenomTraces<T: Config> = StorageMap<_, Blake2_128Concat, T::AssetId, Vec<u8>>;
+
+	/// The voucher asset of each denomination trace.
+	#[pallet::storage]
+	#[pallet::getter(fn voucher)]
+	pub type Vouchers<T: Config> = StorageMap<_, Blake2_128Concat, Vec<u8>, T::AssetId>;
+
+	/// The lowest id the next voucher asset may be created with.
+	#[pallet::storage]
+	pub type NextAssetId<T: Config> = StorageValue<_, T::AssetId, ValueQuery>;
+
+	#[pallet::event]
+	#[pallet::metadata(T::AccountId = "AccountId", T::AssetId = "AssetId")]
+	#[pallet::generate_deposit(pub(super) fn deposit_event)]
+	pub enum Event<T: Config> {
+		/// Tokens were sent to a counterparty. [sender, source_channel, denom, amount, sequence]
+		Transferred(T::AccountId, Vec<u8>, Vec<u8>, u128, u64),
+		/// Tokens were received from a counterparty. [receiver, denom, amount]
+		Received(T::AccountId, Vec<u8>, u128),
+		/// A voucher asset was created for a counterparty token. [asset_id, denom]
+		VoucherCreated(T::AssetId, Vec<u8>),
+		/// Tokens were refunded after their packet failed. [sender, denom, amount]
+		Refunded(T::AccountId, Vec<u8>, u128),
+	}
+
+	#[pallet::error]
+	pub enum Error<T> {
+		/// The message is not a valid `MsgTransfer`.
+		InvalidMessage,
+		/// The message is not sent from the transfer port.
+		InvalidPort,
+		/// The packet data is not valid ICS-20 packet data.
+		InvalidPacketData,
+		/// The amount is zero or does not fit the balance type.
+		InvalidAmount,
+		/// The account is not a hex encoded account id.
+		InvalidAccount,
+		/// No voucher asset exists for the denomination.
+		UnknownDenom,
+		/// The channel does not exist.
+		ChannelNotFound,
+		/// The channel has no counterparty channel yet.
+		ChannelNotOpen,
+		/// ICS-20 channels must be unordered.
+		InvalidChannelOrder,
+		/// The channel version is not ICS-20's.
+		InvalidVersion,
+	}
+
+	impl<T> From<ChannelError> for Error<T> {
+		fn from(error: ChannelError) -> Self {
+			match error {
+				ChannelError::NotFound => Error::<T>::ChannelNotFound,
+				ChannelError::NotOpen => Error::<T>::ChannelNotOpen,
+			}
+		}
+	}
+
+	#[pallet::hooks]
+	impl<T: Config> Hooks<BlockNumberFor<T>> for Pallet<T> {}
+
+	#[pallet::call]
+	impl<T: Config> Pallet<T> {
+		/// Send tokens to a counterparty as described by a protobuf encoded `MsgTransfer`.
+		///
+		/// The signer of the extrinsic is the sender, whatever the message says. The receiver is
+		/// the hex encoded account id on the counterparty.
+		///
+		/// The tokens are escrowed or burnt before the packet is sent, and restored if sending it
+		/// fails.
+		#[pallet::weight(10_000 + T::DbWeight::get().reads_writes(5, 5))]
+		#[transactional]
+		pub fn transfer(origin: OriginFor<T>, message: pallet_ibc::Any) -> DispatchResult {
+			let sender = ensure_signed(origin)?;
+
+			ensure!(message.type_url == TYPE_URL, Error::<T>::InvalidMessage);
+			let msg = RawMsgTransfer::decode(&message.value[..])
+				.ok()
+				.and_then(|raw| MsgTransfer::try_from(raw).ok())
+				.ok_or(Error::<T>::InvalidMessage)?;
+			ensure!(msg.source_port.as_str() == PORT_ID, Error::<T>::InvalidPort);
+			let token = msg.token.ok_or(Error::<T>::InvalidMessage)?;
+			let amount: u128 = token.amount.parse().map_err(|_| Error::<T>::InvalidAmount)?;
+			ensure!(amount > 0, Error::<T>::InvalidAmount);
+
+			let (destination_port, destination_channel) =
+				pallet_ibc_utils::counterparty::<T>(&msg.source_port, &msg.source_channel)
+					.map_err(Error::<T>::from)?;
+			Self::send_tokens(
+				&sender,
+				&msg.source_port,
+				&msg.source_channel,
+				&token.denom,
+				amount,
+			)?;
+
+			let data = FungibleTokenPacketData {
+				denom: token.denom.clone(),
+				amount: amount.to_string(),
+				sender: Self::encode_account(&sender),
+				receiver: msg.receiver.to_string(),
+			};
+			let sequence =
+				pallet_ibc_utils::next_sequence_send::<T>(&msg.source_port, &msg.source_channel);
+			let packet = Packet {
+				sequence: Sequence::from(sequence),
+				source_port: msg.source_port.clone(),
+				source_channel: msg.source_channel.clone(),
+				destination_port,
+				destination_channel,
+				data: data.encode(),
+				timeout_height: msg.timeout_height,
+				timeout_timestamp: msg.timeout_timestamp,
+			};
+			pallet_ibc::Pallet::<T>::send_packet(packet)?;
+
+			Self::deposit_event(Event::Transferred(
+				sender,
+				msg.source_channel.as_bytes().to_vec(),
+				token.denom.into_bytes(),
+				amount,
+				sequence,
+			));
+			Ok(())
+		}
+	}
+
+	impl<T: Config> Pallet<T> {
+		/// Accept only unordered ICS-20 channels on the transfer port.
+		pub fn on_chan_open(order: &Order, version: &str) -> DispatchResult {
+			ensure!(order == &Order::Unordered, Error::<T>::InvalidChannelOrder);
+			ensure!(version == VERSION, Error::<T>::InvalidVersion);
+			Ok(())
+		}
+
+		pub fn on_chan_open_ack(counterparty_version: &str) -> DispatchResult {
+			ensure!(counterparty_version == VERSION, Error::<T>::InvalidVersion);
+			Ok(())
+		}
+
+		/// Credit the receiver of a packet and return the acknowledgement to write. Nothing is
+		/// changed if the tokens can not be credited.
+		pub fn on_recv_packet(packet: &Packet) -> Vec<u8> {
+			let ack = with_transaction(|| match Self::receive_tokens(packet) {
+				Ok(()) => TransactionOutcome::Commit(Acknowledgement::success()),
+				Err(e) => TransactionOutcome::Rollback(Acknowledgement::error(e.into())),
+			});
+			ack.encode()
+		}
+
+		/// Refund the sender if the counterparty could not credit the receiver. Nothing is
+		/// changed if the refund fails.
+		#[transactional]
+		pub fn on_acknowledgement_packet(
+			packet: &Packet,
+			acknowledgement: &[u8],
+		) -> DispatchResult {
+			match Acknowledgement::decode(acknowledgement) {
+				Some(ack) if ack.is_success() => Ok(()),
+				_ => Self::refund(packet),
+			}
+		}
+
+		#[transactional]
+		pub fn on_timeout_packet(packet: &Packet) -> DispatchResult {
+			Self::refund(packet)
+		}
+
+		/// The account tokens sent out through `(port_id, channel_id)` are escrowed in.
+		pub fn escrow_account(port_id: &PortId, channel_id: &ChannelId) -> T::AccountId {
+			T::PalletId::get().into_sub_account((port_id.as_str(), channel_id.as_str()))
+		}
+
+		fn send_tokens(
+			sender: &T::AccountId,
+			port_id: &PortId,
+			channel_id: &ChannelId,
+			denom: &str,
+			amount: u128,
+		) -> DispatchResult {
+			if packet::is_from(denom, port_id.as_str(), channel_id.as_str()) {
+				// The token returns to the chain it came from, where it is released from escrow.
+				let asset_id =
+					Self::voucher(denom.as_bytes().to_vec()).ok_or(Error::<T>::UnknownDenom)?;
+				T::Assets::burn_from(asset_id, sender, Self::asset_balance(amount)?)?;
+			} else {
+				let escrow = Self::escrow_account(port_id, channel_id);
+				Self::transfer_tokens(denom, sender, &escrow, amount)?;
+			}
+			Ok(())
+		}
+
+		fn receive_tokens(packet: &Packet) -> DispatchResult {
+			let data = FungibleTokenPacketData::decode(&packet.data)
+				.ok_or(Error::<T>::InvalidPacketData)?;
+			let amount = data.amount().ok_or(Error::<T>::InvalidAmount)?;
+			let receiver = Self::decode_account(&data.receiver)?;
+
+			let source_port = packet.source_port.as_str();
+			let source_channel = packet.source_channel.as_str();
+			let denom = match packet::strip_prefix(&data.denom, source_port, source_channel) {
+				// The token comes back from the counterparty.
+				Some(denom) => {
+					let escrow =
+						Self::escrow_account(&packet.destination_port, &packet.destination_channel);
+					Self::transfer_tokens(denom, &escrow, &receiver, amount)?;
+					denom.to_string()
+				},
+				// The token comes from the counterparty or beyond.
+				None => {
+					let denom = format!(
+						"{}{}",
+						packet::denom_prefix(
+							packet.destination_port.as_str(),
+							packet.destination_channel.as_str(),
+						),
+						data.denom
+					);
+					let asset_id = Self::voucher_or_create(&denom)?;
+					T::Assets::mint_into(asset_id, &receiver, Self::asset_balance(amount)?)?;
+					denom
+				},
+			};
+
+			Self::deposit_event(Event::Received(receiver, denom.into_bytes(), amount));
+			Ok(())
+		}
+
+		fn refund(packet: &Packet) -> DispatchResult {
+			let data = FungibleTokenPacketData::decode(&packet.data)
+				.ok_or(Error::<T>::InvalidPacketData)?;
+			let amount = data.amount().ok_or(Error::<T>::InvalidAmount)?;
+			let sender = Self::decode_account(&data.sender)?;
+
+			let source_port = packet.source_port.as_str();
+			let source_channel = packet.source_channel.as_str();
+			if packet::is_from(&data.denom, source_port, source_channel) {
+				// The voucher was burnt when sent.
+				let asset_id = Self::voucher(data.denom.as_bytes().to_vec())
+					.ok_or(Error::<T>::UnknownDenom)?;
+				T::Assets::mint_into(asset_id, &sender, Self::asset_balance(amount)?)?;
+			} else {
+				let escrow = Self::escrow_account(&packet.source_port, &packet.source_channel);
+				Self::transfer_tokens(&data.denom, &escrow, &sender, amount)?;
+			}
+
+			Self::deposit_event(Event::Refunded(sender, data.denom.into_bytes(), amount));
+			Ok(())
+		}
+
+		/// Move native tokens or vouchers, depending on `denom`.
+		fn transfer_tokens(
+			denom: &str,
+			from: &T::AccountId,
+			to: &T::AccountId,
+			amount: u128,
+		) -> DispatchResult {
+			if denom == T::NativeDenom::get() {
+				let amount =
+					BalanceOf::<T>::try_from(amount).map_err(|_| Error::<T>::InvalidAmount)?;
+				T::Currency::transfer(from, to, amount, ExistenceRequirement::AllowDeath)
+			} else {
+				let asset_id =
+					Self::voucher(denom.as_bytes().to_vec()).ok_or(Error::<T>::UnknownDenom)?;
+				T::Assets::transfer(asset_id, from, to, Self::asset_balance(amount)?, false)?;
+				Ok(())
+			}
+		}
+
+		fn voucher_or_create(denom: &str) -> Result<T::AssetId, DispatchError> {
+			if let Some(asset_id) = Self::voucher(denom.as_bytes().to_vec()) {
+				return Ok(asset_id)
+			}
+
+			// Skip the ids of assets created by other means.
+			let mut asset_id = NextAssetId::<T>::get();
+			while !T::Assets::minimum_balance(asset_id).is_zero() {
+				asset_id += One::one();
+			}
+			let admin = T::PalletId::get().into_account();
+			T::Assets::create(asset_id, admin, true, One::one())?;
+			NextAssetId::<T>::put(asset_id + One::one());
+			DenomTraces::<T>::insert(asset_id, denom.as_bytes().to_vec());
+			Vouchers::<T>::insert(denom.as_bytes().to_vec(), asset_id);
+
+			Self::deposit_event(Event::VoucherCreated(asset_id, denom.as_bytes().to_vec()));
+			Ok(asset_id)
+		}
+
+		fn asset_balance(amount: u128) -> Result<AssetBalanceOf<T>, DispatchError> {
+			Ok(AssetBalanceOf::<T>::try_from(amount).map_err(|_| Error::<T>::InvalidAmount)?)
+		}
+
+		/// Accounts are hex encoded in packet data, with a `0x` prefix.
+		fn encode_account(account: &T::AccountId) -> String {
+			format!("0x{}", hex::encode(account.encode()))
+		}
+
+		fn decode_account(account: &str) -> Result<T::AccountId, DispatchError> {
+			let account = account.strip_prefix("0x").unwrap_or(account);
+			let data = hex::decode(account).map_err(|_| Error::<T>::InvalidAccount)?;
+			Ok(T::AccountId::decode_all(&data).map_err(|_| Error::<T>::InvalidAccount)?)
+		}
+	}
+}
@@ -0,0 +1,76 @@
+//! ICS-20 packet data, acknowledgements and denomination traces.
+use alloc::{format, string::String};
+use serde::{Deserialize, Serialize};
+use sp_std::prelude::*;
+
+/// The data of an ICS-20 packet, JSON encoded on the wire.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FungibleTokenPacketData {
+	/// The denomination trace of the token, as seen by the sending chain.
+	pub denom: String,
+	/// The amount, as a decimal string.
+	pub amount: String,
+	pub sender: String,
+	pub receiver: String,
+}
+
+impl FungibleTokenPacketData {
+	pub fn encode(&self) -> Vec<u8> {
+		serde_json::to_vec(self).expect("packet data is always serializable; qed")
+	}
+
+	pub fn decode(data: &[u8]) -> Option<Self> {
+		serde_json::from_slice(data).ok()
+	}
+
+	pub fn amount(&self) -> Option<u128> {
+		self.amount.parse().ok()
+	}
+}
+
+/// The acknowledgement written for an ICS-20 packet.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Acknowledgement {
+	/// Base64 of the single byte `0x01`.
+	Result(String),
+	Error(String),
+}
+
+impl Acknowledgement {
+	pub fn success() -> Self {
+		Acknowledgement::Result("AQ==".into())
+	}
+
+	pub fn error(error: &str) -> Self {
+		Acknowledgement::Error(error.into())
+	}
+
+	pub fn is_success(&self) -> bool {
+		matches!(self, Acknowledgement::Result(_))
+	}
+
+	pub fn encode(&self) -> Vec<u8> {
+		serde_json::to_vec(self).expect("acknowledgement is always serializable; qed")
+	}
+
+	pub fn decode(data: &[u8]) -> Option<Self> {
+		serde_json::from_slice(data).ok()
+	}
+}
+
+/// The prefix `port/channel/` a denomination gets when it crosses `(port_id, channel_id)`.
+pub fn denom_prefix(port_id: &str, channel_id: &str) -> String {
+	format!("{}/{}/", port_id, channel_id)
+}
+
+/// Whether a token with denomination trace `denom` came in over `(port_id, channel_id)`, i.e.
+/// it goes back to its source when sent out on that channel.
+pub fn is_from(denom: &str, port_id: &str, channel_id: &str) -> bool {
+	denom.starts_with(&denom_prefix(port_id, channel_id))
+}
+
+/// Strip the `port/channel/` prefix of the hop `(port_id, channel_id)` from `denom`.
+pub fn strip_prefix<'a>(denom: &'a str, port_id: &str, channel_id: &str) -> Option<&'a str> {
+	denom.strip_prefix(denom_prefix(port_id, channel_id).as_str())
+}
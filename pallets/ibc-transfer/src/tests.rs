@@ -0,0 +1,326 @@
+use crate::mock::*;
+use crate::packet::{self, Acknowledgement, FungibleTokenPacketData};
+use crate::{PORT_ID, VERSION};
+use codec::Encode;
+use frame_support::{assert_ok, assert_storage_noop, traits::tokens::fungibles::Mutate};
+use ibc::application::ics20_fungible_token_transfer::msgs::transfer::{MsgTransfer, TYPE_URL};
+use ibc::ics02_client::client_consensus::AnyConsensusState;
+use ibc::ics02_client::client_state::AnyClientState;
+use ibc::ics02_client::client_type::ClientType;
+use ibc::ics02_client::height::Height;
+use ibc::ics03_connection::connection::{
+	ConnectionEnd, Counterparty as ConnectionCounterparty, State as ConnectionState,
+};
+use ibc::ics03_connection::version::Version;
+use ibc::ics04_channel::channel::{ChannelEnd, Counterparty, Order, State};
+use ibc::ics04_channel::packet::{Packet, Sequence};
+use ibc::ics10_grandpa::client_state::ClientState as GrandpaClientState;
+use ibc::ics10_grandpa::consensus_state::ConsensusState as GrandpaConsensusState;
+use ibc::ics23_commitment::commitment::CommitmentRoot;
+use ibc::ics24_host::identifier::{ChainId, ChannelId, ClientId, ConnectionId, PortId};
+use ibc::signer::Signer;
+use ibc_proto::cosmos::base::v1beta1::Coin;
+use ibc_proto::google::protobuf::Any as RawAny;
+use ibc_proto::ibc::applications::transfer::v1::MsgTransfer as RawMsgTransfer;
+use ibc_proto::ibc::core::channel::v1::Channel as RawChannel;
+use ibc_proto::ibc::core::client::v1::Height as RawHeight;
+use ibc_proto::ibc::core::connection::v1::ConnectionEnd as RawConnectionEnd;
+use prost::Message;
+use std::str::FromStr;
+
+#[test]
+fn packet_data_round_trips_as_json() {
+	let data = FungibleTokenPacketData {
+		denom: "transfer/channel-0/UNIT".into(),
+		amount: "1000".into(),
+		sender: "0x01".into(),
+		receiver: "0x02".into(),
+	};
+	let encoded = data.encode();
+	assert_eq!(
+		encoded,
+		br#"{"denom":"transfer/channel-0/UNIT","amount":"1000","sender":"0x01","receiver":"0x02"}"#
+			.to_vec()
+	);
+	assert_eq!(FungibleTokenPacketData::decode(&encoded), Some(data.clone()));
+	assert_eq!(data.amount(), Some(1000));
+}
+
+#[test]
+fn acknowledgements_match_ics20() {
+	assert_eq!(Acknowledgement::success().encode(), br#"{"result":"AQ=="}"#.to_vec());
+	assert_eq!(Acknowledgement::error("failed").encode(), br#"{"error":"failed"}"#.to_vec());
+	assert!(Acknowledgement::decode(br#"{"result":"AQ=="}"#).unwrap().is_success());
+	assert!(!Acknowledgement::decode(br#"{"error":"failed"}"#).unwrap().is_success());
+	assert_eq!(Acknowledgement::decode(b"garbage"), None);
+}
+
+#[test]
+fn denominations_are_traced_per_hop() {
+	assert!(packet::is_from("transfer/channel-0/UNIT", "transfer", "channel-0"));
+	assert!(!packet::is_from("transfer/channel-1/UNIT", "transfer", "channel-0"));
+	assert!(!packet::is_from("UNIT", "transfer", "channel-0"));
+	assert_eq!(
+		packet::strip_prefix("transfer/channel-0/transfer/channel-3/UNIT", "transfer", "channel-0"),
+		Some("transfer/channel-3/UNIT")
+	);
+	assert_eq!(packet::strip_prefix("UNIT", "transfer", "channel-0"), None);
+}
+
+fn account(account: u64) -> String {
+	format!("0x{}", hex::encode(account.encode()))
+}
+
+fn port() -> PortId {
+	PortId::from_str(PORT_ID).unwrap()
+}
+
+fn channel(channel_id: &str) -> ChannelId {
+	ChannelId::from_str(channel_id).unwrap()
+}
+
+/// A packet of ICS-20 `data` from `transfer/source_channel` to `transfer/destination_channel`.
+fn packet(
+	source_channel: &str,
+	destination_channel: &str,
+	data: FungibleTokenPacketData,
+) -> Packet {
+	Packet {
+		sequence: Sequence::from(1),
+		source_port: port(),
+		source_channel: channel(source_channel),
+		destination_port: port(),
+		destination_channel: channel(destination_channel),
+		data: data.encode(),
+		..Default::default()
+	}
+}
+
+fn data(denom: &str, amount: u128, sender: u64, receiver: u64) -> FungibleTokenPacketData {
+	FungibleTokenPacketData {
+		denom: denom.into(),
+		amount: amount.to_string(),
+		sender: account(sender),
+		receiver: account(receiver),
+	}
+}
+
+/// Store an open `transfer/channel-0` towards `transfer/channel-1`, without the connection and
+/// client behind it, so that packets can not actually be sent on it.
+fn insert_channel() {
+	let channel_end = ChannelEnd::new(
+		State::Open,
+		Order::Unordered,
+		Counterparty::new(port(), Some(channel("channel-1"))),
+		vec![ConnectionId::default()],
+		VERSION.to_string(),
+	);
+	let mut value = vec![];
+	RawChannel::from(channel_end).encode(&mut value).unwrap();
+	pallet_ibc::Channels::<Test>::insert(PORT_ID.as_bytes().to_vec(), b"channel-0".to_vec(), value);
+}
+
+/// Store the grandpa client at height 10 and the open connection behind `insert_channel`, so that
+/// packets timing out after that height can be sent on the channel.
+fn insert_connection() {
+	let client_id = ClientId::new(ClientType::Grandpa, 0).unwrap();
+	let height = Height::new(0, 10);
+	let client_state = AnyClientState::Grandpa(
+		GrandpaClientState::new(
+			ChainId::from_string("flaminia"),
+			height,
+			Height::new(0, 0),
+			0,
+			vec![],
+		)
+		.unwrap(),
+	);
+	let consensus_state =
+		AnyConsensusState::Grandpa(GrandpaConsensusState::new(CommitmentRoot::from(vec![])));
+	let connection_end = ConnectionEnd::new(
+		ConnectionState::Open,
+		client_id.clone(),
+		ConnectionCounterparty::new(
+			ClientId::new(ClientType::Grandpa, 0).unwrap(),
+			Some(ConnectionId::default()),
+			b"ibc".to_vec().into(),
+		),
+		vec![Version::default()],
+		Default::default(),
+	);
+
+	let mut value = vec![];
+	RawAny::from(client_state).encode(&mut value).unwrap();
+	pallet_ibc::ClientStates::<Test>::insert(client_id.as_bytes().to_vec(), value);
+	let (mut key, mut value) = (vec![], vec![]);
+	RawHeight::from(height).encode(&mut key).unwrap();
+	RawAny::from(consensus_state).encode(&mut value).unwrap();
+	pallet_ibc::ConsensusStates::<Test>::insert(client_id.as_bytes().to_vec(), vec![(key, value)]);
+	let mut value = vec![];
+	RawConnectionEnd::from(connection_end).encode(&mut value).unwrap();
+	pallet_ibc::Connections::<Test>::insert(ConnectionId::default().as_bytes().to_vec(), value);
+	pallet_ibc::NextSequenceSend::<Test>::insert(
+		PORT_ID.as_bytes().to_vec(),
+		b"channel-0".to_vec(),
+		1u64.encode(),
+	);
+}
+
+fn transfer_message(denom: &str, amount: u128) -> pallet_ibc::Any {
+	let msg = MsgTransfer {
+		source_port: port(),
+		source_channel: channel("channel-0"),
+		token: Some(Coin { denom: denom.into(), amount: amount.to_string() }),
+		sender: Signer::new(account(ALICE)),
+		receiver: Signer::new(account(BOB)),
+		timeout_height: Height::new(0, 100),
+		timeout_timestamp: Default::default(),
+	};
+	let mut value = vec![];
+	RawMsgTransfer::from(msg).encode(&mut value).unwrap();
+	pallet_ibc::Any { type_url: TYPE_URL.to_string(), value }
+}
+
+/// Receive `amount` of the counterparty's `ATOM` for `receiver`, returning its voucher asset.
+fn receive_vouchers(receiver: u64, amount: u128) -> u32 {
+	let packet = packet("channel-1", "channel-0", data("ATOM", amount, BOB, receiver));
+	assert_eq!(IbcTransfer::on_recv_packet(&packet), Acknowledgement::success().encode());
+	IbcTransfer::voucher(b"transfer/channel-0/ATOM".to_vec()).unwrap()
+}
+
+#[test]
+fn receiving_counterparty_tokens_mints_vouchers() {
+	new_test_ext().execute_with(|| {
+		let asset_id = receive_vouchers(BOB, 100);
+		assert_eq!(Assets::balance(asset_id, BOB), 100);
+		assert_eq!(IbcTransfer::denom_trace(asset_id), Some(b"transfer/channel-0/ATOM".to_vec()));
+	});
+}
+
+#[test]
+fn receiving_returning_tokens_releases_escrow() {
+	new_test_ext().execute_with(|| {
+		let escrow = IbcTransfer::escrow_account(&port(), &channel("channel-0"));
+		assert_ok!(Balances::transfer(Origin::signed(ALICE), escrow, 300));
+
+		let packet =
+			packet("channel-1", "channel-0", data("transfer/channel-1/UNIT", 100, ALICE, BOB));
+		assert_eq!(IbcTransfer::on_recv_packet(&packet), Acknowledgement::success().encode());
+		assert_eq!(Balances::free_balance(BOB), 100);
+		assert_eq!(Balances::free_balance(escrow), 200);
+	});
+}
+
+#[test]
+fn receiving_invalid_packet_changes_nothing() {
+	new_test_ext().execute_with(|| {
+		let mut data = data("ATOM", 100, BOB, ALICE);
+		data.receiver = "not an account".into();
+		let packet = packet("channel-1", "channel-0", data);
+		assert_storage_noop!({
+			let ack = IbcTransfer::on_recv_packet(&packet);
+			assert!(!Acknowledgement::decode(&ack).unwrap().is_success());
+		});
+	});
+}
+
+/// Whether the packet of `sequence` sent on `transfer/channel-0` is committed to.
+fn packet_committed(sequence: u64) -> bool {
+	let path = (PORT_ID.as_bytes().to_vec(), b"channel-0".to_vec());
+	pallet_ibc::PacketCommitment::<Test>::contains_key(&path, sequence)
+}
+
+#[test]
+fn transfer_escrows_native_tokens() {
+	new_test_ext().execute_with(|| {
+		insert_channel();
+		insert_connection();
+		assert_ok!(IbcTransfer::transfer(Origin::signed(ALICE), transfer_message("UNIT", 100)));
+
+		let escrow = IbcTransfer::escrow_account(&port(), &channel("channel-0"));
+		assert_eq!(Balances::free_balance(ALICE), 900);
+		assert_eq!(Balances::free_balance(escrow), 100);
+		assert!(packet_committed(1));
+	});
+}
+
+#[test]
+fn transfer_burns_returning_vouchers() {
+	new_test_ext().execute_with(|| {
+		let asset_id = receive_vouchers(ALICE, 100);
+		insert_channel();
+		insert_connection();
+		let message = transfer_message("transfer/channel-0/ATOM", 60);
+		assert_ok!(IbcTransfer::transfer(Origin::signed(ALICE), message));
+
+		assert_eq!(Assets::balance(asset_id, ALICE), 40);
+		assert_eq!(Assets::total_supply(asset_id), 40);
+		assert!(packet_committed(1));
+	});
+}
+
+#[test]
+fn transfer_restores_escrow_if_packet_not_sent() {
+	new_test_ext().execute_with(|| {
+		insert_channel();
+		assert_storage_noop!({
+			let message = transfer_message("UNIT", 100);
+			assert!(IbcTransfer::transfer(Origin::signed(ALICE), message).is_err());
+		});
+		assert_eq!(Balances::free_balance(ALICE), 1_000);
+	});
+}
+
+#[test]
+fn transfer_restores_burnt_vouchers_if_packet_not_sent() {
+	new_test_ext().execute_with(|| {
+		let asset_id = receive_vouchers(ALICE, 100);
+		insert_channel();
+		assert_storage_noop!({
+			let message = transfer_message("transfer/channel-0/ATOM", 100);
+			assert!(IbcTransfer::transfer(Origin::signed(ALICE), message).is_err());
+		});
+		assert_eq!(Assets::balance(asset_id, ALICE), 100);
+	});
+}
+
+#[test]
+fn error_acknowledgement_refunds_escrow() {
+	new_test_ext().execute_with(|| {
+		let escrow = IbcTransfer::escrow_account(&port(), &channel("channel-0"));
+		assert_ok!(Balances::transfer(Origin::signed(ALICE), escrow, 100));
+		let packet = packet("channel-0", "channel-1", data("UNIT", 100, ALICE, BOB));
+
+		let success = Acknowledgement::success().encode();
+		assert_storage_noop!(assert_ok!(IbcTransfer::on_acknowledgement_packet(&packet, &success)));
+
+		let error = Acknowledgement::error("failed").encode();
+		assert_ok!(IbcTransfer::on_acknowledgement_packet(&packet, &error));
+		assert_eq!(Balances::free_balance(ALICE), 1_000);
+		assert_eq!(Balances::free_balance(escrow), 0);
+	});
+}
+
+#[test]
+fn timeout_refunds_burnt_vouchers() {
+	new_test_ext().execute_with(|| {
+		let asset_id = receive_vouchers(ALICE, 100);
+		// Burnt when sent back to the counterparty.
+		assert_ok!(<Assets as Mutate<u64>>::burn_from(asset_id, &ALICE, 100));
+		let packet =
+			packet("channel-0", "channel-1", data("transfer/channel-0/ATOM", 100, ALICE, BOB));
+
+		assert_ok!(IbcTransfer::on_timeout_packet(&packet));
+		assert_eq!(Assets::balance(asset_id, ALICE), 100);
+	});
+}
+
+#[test]
+fn failed_refund_changes_nothing() {
+	new_test_ext().execute_with(|| {
+		// No voucher exists for the denomination.
+		let packet =
+			packet("channel-0", "channel-1", data("transfer/channel-0/ATOM", 100, ALICE, BOB));
+		assert_storage_noop!(assert!(IbcTransfer::on_timeout_packet(&packet).is_err()));
+	});
+}
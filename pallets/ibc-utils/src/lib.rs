@@ -0,0 +1,52 @@
+//! Helpers shared by the application modules sending packets over pallet-ibc.
+//!
+//! Both read the channel state pallet-ibc stores to address the packets they send.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+use codec::Decode;
+use ibc::ics04_channel::channel::ChannelEnd;
+use ibc::ics24_host::identifier::{ChannelId, PortId};
+use ibc_proto::ibc::core::channel::v1::Channel as RawChannel;
+use prost::Message;
+use sp_std::convert::TryFrom;
+
+/// Why a channel can not be sent on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChannelError {
+	/// No channel end is stored for the port and channel.
+	NotFound,
+	/// The channel has no counterparty channel yet.
+	NotOpen,
+}
+
+/// The port and channel on the other end of `(port_id, channel_id)`.
+pub fn counterparty<T: pallet_ibc::Config>(
+	port_id: &PortId,
+	channel_id: &ChannelId,
+) -> Result<(PortId, ChannelId), ChannelError> {
+	let data = pallet_ibc::Channels::<T>::try_get(
+		port_id.as_bytes().to_vec(),
+		channel_id.as_bytes().to_vec(),
+	)
+	.map_err(|_| ChannelError::NotFound)?;
+	let channel_end = RawChannel::decode(&data[..])
+		.ok()
+		.and_then(|raw| ChannelEnd::try_from(raw).ok())
+		.ok_or(ChannelError::NotFound)?;
+	let counterparty = channel_end.counterparty();
+	let counterparty_channel_id =
+		counterparty.channel_id().cloned().ok_or(ChannelError::NotOpen)?;
+	Ok((counterparty.port_id().clone(), counterparty_channel_id))
+}
+
+/// The sequence of the next packet sent on `(port_id, channel_id)`.
+pub fn next_sequence_send<T: pallet_ibc::Config>(port_id: &PortId, channel_id: &ChannelId) -> u64 {
+	pallet_ibc::NextSequenceSend::<T>::try_get(
+		port_id.as_bytes().to_vec(),
+		channel_id.as_bytes().to_vec(),
+	)
+	.ok()
+	.and_then(|data| u64::decode(&mut &data[..]).ok())
+	.unwrap_or(1)
+}
@@ -0,0 +1,115 @@
+//! RPC interface for proving the leaves of the MMR.
+//!
+//! `mmr_generateProof` returns a leaf SCALE encoded as an opaque blob; `mmrLeaf_generateProof`
+//! returns it decoded, as `pallet-mmr-leaf` appends it, so that a relayer reads the parent block,
+//! the next BEEFY validator set and the parent state root a proof is for without the leaf type.
+
+use std::sync::Arc;
+
+use codec::Encode;
+use jsonrpc_core::{Error as RpcError, ErrorCode, Result};
+use jsonrpc_derive::rpc;
+use serde::{Deserialize, Serialize};
+use sp_api::ProvideRuntimeApi;
+use sp_blockchain::HeaderBackend;
+use sp_core::{Bytes, ExecutionContext};
+use sp_runtime::{
+	generic::BlockId,
+	traits::{Block as BlockT, NumberFor},
+};
+
+pub use pallet_mmr_leaf::MmrLeaf;
+pub use pallet_mmr_primitives::MmrApi as MmrRuntimeApi;
+
+/// A leaf of the MMR with the proof of its inclusion in the MMR of `block_hash`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LeafProof<BlockNumber, BlockHash> {
+	/// The hash of the block whose MMR the leaf is proven in.
+	pub block_hash: BlockHash,
+	/// The decoded leaf.
+	pub leaf: MmrLeaf<BlockNumber, BlockHash>,
+	/// The SCALE encoded `pallet_mmr_primitives::Proof` of the leaf.
+	pub proof: Bytes,
+}
+
+/// MMR leaf RPC methods.
+#[rpc]
+pub trait MmrLeafApi<BlockHash, BlockNumber> {
+	/// Prove the leaf at `leaf_index` in the MMR of block `at`, or of the best block if `None`.
+	#[rpc(name = "mmrLeaf_generateProof")]
+	fn generate_proof(
+		&self,
+		leaf_index: u64,
+		at: Option<BlockHash>,
+	) -> Result<LeafProof<BlockNumber, BlockHash>>;
+}
+
+/// Error code for failed runtime API calls.
+const RUNTIME_ERROR: i64 = 1;
+/// Error code for failures of the MMR to prove a leaf.
+const MMR_ERROR: i64 = 2;
+/// Error code for leaves that are not `pallet-mmr-leaf` ones.
+const DECODE_ERROR: i64 = 3;
+
+fn runtime_error(err: impl std::fmt::Debug) -> RpcError {
+	RpcError {
+		code: ErrorCode::ServerError(RUNTIME_ERROR),
+		message: "Unable to generate MMR proof.".into(),
+		data: Some(format!("{:?}", err).into()),
+	}
+}
+
+fn mmr_error(err: impl std::fmt::Debug) -> RpcError {
+	RpcError {
+		code: ErrorCode::ServerError(MMR_ERROR),
+		message: "Unable to prove the MMR leaf.".into(),
+		data: Some(format!("{:?}", err).into()),
+	}
+}
+
+/// An implementation of the MMR leaf RPC methods.
+pub struct MmrLeafRpc<C, B> {
+	client: Arc<C>,
+	_marker: std::marker::PhantomData<B>,
+}
+
+impl<C, B> MmrLeafRpc<C, B> {
+	/// Create new `MmrLeafRpc` with the given reference to the client.
+	pub fn new(client: Arc<C>) -> Self {
+		Self { client, _marker: Default::default() }
+	}
+}
+
+impl<C, Block> MmrLeafApi<<Block as BlockT>::Hash, NumberFor<Block>> for MmrLeafRpc<C, Block>
+where
+	Block: BlockT,
+	C: 'static + ProvideRuntimeApi<Block> + HeaderBackend<Block> + Send + Sync,
+	C::Api: MmrRuntimeApi<Block, <Block as BlockT>::Hash>,
+{
+	fn generate_proof(
+		&self,
+		leaf_index: u64,
+		at: Option<Block::Hash>,
+	) -> Result<LeafProof<NumberFor<Block>, Block::Hash>> {
+		// If the block hash is not supplied assume the best block.
+		let block_hash = at.unwrap_or_else(|| self.client.info().best_hash);
+		// The MMR nodes are read from the offchain database, which only offchain calls can.
+		let (leaf, proof) = self
+			.client
+			.runtime_api()
+			.generate_proof_with_context(
+				&BlockId::hash(block_hash),
+				ExecutionContext::OffchainCall(None),
+				leaf_index,
+			)
+			.map_err(runtime_error)?
+			.map_err(mmr_error)?;
+		let leaf = leaf.into_opaque_leaf().try_decode().ok_or_else(|| RpcError {
+			code: ErrorCode::ServerError(DECODE_ERROR),
+			message: "Unable to decode the MMR leaf.".into(),
+			data: None,
+		})?;
+		Ok(LeafProof { block_hash, leaf, proof: proof.encode().into() })
+	}
+}
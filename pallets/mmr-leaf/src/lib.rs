@@ -0,0 +1,280 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
+//! The leaves of the chain's MMR, as `pallet-mmr`'s leaf data provider.
+//!
+//! A leaf is appended in the `on_initialize` of every block, before the block's state root is
+//! known, so it commits to the parent block: its number and hash, and through the hash its header
+//! and state root. Besides, it commits the BEEFY validator set after the current one and the state
+//! root of the parent block's own parent, which the parent block set with the
+//! `set_parent_state_root` inherent. A BEEFY commitment to the MMR root and the proof of a leaf
+//! from `MmrApi::generate_proof` then prove all of them for any past block, the leaf of block `n`
+//! being at index `n`.
+//!
+//! The state root commits every path pallet-ibc stores, clients, connections, channels, packet
+//! commitments, receipts, acknowledgements and sequences, which `IbcApi` proves against it, so a
+//! leaf costs a few reads however many of them there are.
+
+pub use pallet::*;
+
+#[cfg(test)]
+mod mock;
+
+#[cfg(test)]
+mod tests;
+
+use beefy_primitives::ValidatorSetId;
+use codec::{Decode, Encode};
+use frame_support::{traits::Get, weights::Weight};
+#[cfg(feature = "std")]
+use serde::{Deserialize, Serialize};
+use sp_core::H256;
+use sp_inherents::{InherentIdentifier, IsFatalError};
+use sp_io::hashing::keccak_256;
+use sp_runtime::{
+	traits::{One, Saturating},
+	RuntimeDebug,
+};
+use sp_std::prelude::*;
+
+/// The identifier of the inherent data of the parent block's state root.
+pub const INHERENT_IDENTIFIER: InherentIdentifier = *b"mmrleaf0";
+
+/// The error of a `set_parent_state_root` inherent importers do not agree with.
+#[derive(Encode, RuntimeDebug)]
+#[cfg_attr(feature = "std", derive(Decode))]
+pub enum InherentError {
+	/// The state root set is not the one of the parent block.
+	WrongParentStateRoot,
+	/// The inherent data has no state root to check against.
+	MissingParentStateRoot,
+}
+
+impl IsFatalError for InherentError {
+	fn is_fatal_error(&self) -> bool {
+		true
+	}
+}
+
+/// Provides the state root of the parent block to the `set_parent_state_root` inherent, as the
+/// client reads it from the parent's header.
+#[cfg(feature = "std")]
+pub struct InherentDataProvider(pub H256);
+
+#[cfg(feature = "std")]
+#[async_trait::async_trait]
+impl sp_inherents::InherentDataProvider for InherentDataProvider {
+	fn provide_inherent_data(
+		&self,
+		inherent_data: &mut sp_inherents::InherentData,
+	) -> Result<(), sp_inherents::Error> {
+		inherent_data.put_data(INHERENT_IDENTIFIER, &self.0)
+	}
+
+	async fn try_handle_error(
+		&self,
+		identifier: &InherentIdentifier,
+		error: &[u8],
+	) -> Option<Result<(), sp_inherents::Error>> {
+		if *identifier != INHERENT_IDENTIFIER {
+			return None
+		}
+		let error = InherentError::decode(&mut &error[..]).ok()?;
+		Some(Err(sp_inherents::Error::Application(Box::from(format!("{:?}", error)))))
+	}
+}
+
+/// The BEEFY validator set after the current one, as a leaf commits it.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Encode, Decode)]
+#[cfg_attr(feature = "std", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "std", serde(rename_all = "camelCase"))]
+pub struct BeefyNextAuthoritySet {
+	pub id: ValidatorSetId,
+	/// The number of validators in the set.
+	pub len: u32,
+	/// The [`merkle_root`] of the validators' SCALE encoded keys.
+	pub root: H256,
+}
+
+/// A leaf of the MMR.
+#[derive(Clone, Debug, PartialEq, Eq, Encode, Decode)]
+#[cfg_attr(feature = "std", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "std", serde(rename_all = "camelCase"))]
+pub struct MmrLeaf<BlockNumber, Hash> {
+	/// The number and hash of the parent of the block the leaf is appended in.
+	pub parent_number_and_hash: (BlockNumber, Hash),
+	pub beefy_next_authority_set: BeefyNextAuthoritySet,
+	/// The state root of the parent of the block of `parent_number_and_hash`, as that block's
+	/// `set_parent_state_root` inherent set it. Zero for the genesis block.
+	pub parent_state_root: H256,
+}
+
+/// The keccak-256 binary Merkle root of `leaves`, each hashed first. The last node of a layer
+/// with an odd number of them is promoted to the next layer as it is. Zero without leaves.
+pub fn merkle_root<I: IntoIterator<Item = Vec<u8>>>(leaves: I) -> H256 {
+	let mut layer: Vec<H256> =
+		leaves.into_iter().map(|leaf| H256::from(keccak_256(&leaf))).collect();
+	if layer.is_empty() {
+		return H256::zero()
+	}
+	while layer.len() > 1 {
+		layer = layer
+			.chunks(2)
+			.map(|pair| match pair {
+				[left, right] => {
+					let mut concat = [0u8; 64];
+					concat[..32].copy_from_slice(left.as_bytes());
+					concat[32..].copy_from_slice(right.as_bytes());
+					H256::from(keccak_256(&concat))
+				},
+				_ => pair[0],
+			})
+			.collect();
+	}
+	layer[0]
+}
+
+#[frame_support::pallet]
+pub mod pallet {
+	use crate::{BeefyNextAuthoritySet, InherentError, INHERENT_IDENTIFIER};
+	use frame_support::{inherent::ProvideInherent, pallet_prelude::*};
+	use frame_system::pallet_prelude::*;
+	use sp_core::H256;
+	use sp_inherents::{InherentData, InherentIdentifier};
+
+	#[pallet::config]
+	pub trait Config: frame_system::Config + pallet_mmr::Config + pallet_beefy::Config {}
+
+	#[pallet::pallet]
+	#[pallet::generate_store(pub(super) trait Store)]
+	pub struct Pallet<T>(_);
+
+	/// The next BEEFY validator set, as last committed, kept until the set id changes.
+	#[pallet::storage]
+	#[pallet::getter(fn beefy_next_authorities)]
+	pub type BeefyNextAuthorities<T> = StorageValue<_, BeefyNextAuthoritySet, ValueQuery>;
+
+	/// The state root of the parent block, as this block's inherent set it, which the next leaf
+	/// commits.
+	#[pallet::storage]
+	#[pallet::getter(fn parent_state_root)]
+	pub type ParentStateRoot<T> = StorageValue<_, H256, ValueQuery>;
+
+	/// Whether the parent state root was set in this block.
+	#[pallet::storage]
+	pub(super) type DidSetParentStateRoot<T> = StorageValue<_, bool, ValueQuery>;
+
+	#[pallet::hooks]
+	impl<T: Config> Hooks<BlockNumberFor<T>> for Pallet<T> {
+		fn on_initialize(_n: BlockNumberFor<T>) -> Weight {
+			// The `DidSetParentStateRoot` taken in `on_finalize`.
+			T::DbWeight::get().reads_writes(1, 1)
+		}
+
+		fn on_finalize(_n: BlockNumberFor<T>) {
+			assert!(
+				DidSetParentStateRoot::<T>::take(),
+				"The parent state root must be set once in the block"
+			);
+		}
+	}
+
+	#[pallet::call]
+	impl<T: Config> Pallet<T> {
+		/// Set the state root of the parent block, for the next leaf to commit.
+		///
+		/// An inherent, which every block includes exactly once. Importers check `state_root`
+		/// against the parent's header.
+		#[pallet::weight((T::DbWeight::get().reads_writes(1, 2), DispatchClass::Mandatory))]
+		pub fn set_parent_state_root(origin: OriginFor<T>, state_root: H256) -> DispatchResult {
+			ensure_none(origin)?;
+			assert!(
+				!DidSetParentStateRoot::<T>::exists(),
+				"The parent state root must be set only once in the block"
+			);
+			ParentStateRoot::<T>::put(state_root);
+			DidSetParentStateRoot::<T>::put(true);
+			Ok(())
+		}
+	}
+
+	#[pallet::inherent]
+	impl<T: Config> ProvideInherent for Pallet<T> {
+		type Call = Call<T>;
+		type Error = InherentError;
+		const INHERENT_IDENTIFIER: InherentIdentifier = INHERENT_IDENTIFIER;
+
+		fn create_inherent(data: &InherentData) -> Option<Self::Call> {
+			let state_root = data
+				.get_data::<H256>(&INHERENT_IDENTIFIER)
+				.expect("The parent state root inherent data must decode")
+				.expect("The parent state root inherent data must be provided");
+			Some(Call::set_parent_state_root(state_root))
+		}
+
+		fn check_inherent(call: &Self::Call, data: &InherentData) -> Result<(), Self::Error> {
+			let state_root = match call {
+				Call::set_parent_state_root(state_root) => state_root,
+				_ => return Ok(()),
+			};
+			let expected = data
+				.get_data::<H256>(&INHERENT_IDENTIFIER)
+				.ok()
+				.flatten()
+				.ok_or(InherentError::MissingParentStateRoot)?;
+			if *state_root != expected {
+				return Err(InherentError::WrongParentStateRoot)
+			}
+			Ok(())
+		}
+
+		fn is_inherent(call: &Self::Call) -> bool {
+			matches!(call, Call::set_parent_state_root(_))
+		}
+	}
+}
+
+/// The leaf type of a runtime.
+pub type MmrLeafOf<T> =
+	MmrLeaf<<T as frame_system::Config>::BlockNumber, <T as frame_system::Config>::Hash>;
+
+impl<T: Config> Pallet<T> {
+	/// The weight of the storage [`LeafDataProvider::leaf_data`] reads and writes, for the
+	/// runtime to add to `pallet_mmr`'s weight of appending a leaf.
+	///
+	/// [`LeafDataProvider::leaf_data`]: pallet_mmr::primitives::LeafDataProvider::leaf_data
+	pub fn leaf_data_weight() -> Weight {
+		// `ParentStateRoot`, pallet-beefy's `ValidatorSetId` and `NextAuthorities`, and
+		// `BeefyNextAuthorities` read and written again when the set changes.
+		T::DbWeight::get().reads_writes(4, 1)
+	}
+
+	/// The next BEEFY validator set, computed again only when pallet-beefy changes sets.
+	fn beefy_next_authority_set() -> BeefyNextAuthoritySet {
+		let id = pallet_beefy::Pallet::<T>::validator_set_id() + 1;
+		let cached = Self::beefy_next_authorities();
+		if cached.id == id {
+			return cached
+		}
+		let next_authorities = pallet_beefy::Pallet::<T>::next_authorities();
+		let set = BeefyNextAuthoritySet {
+			id,
+			len: next_authorities.len() as u32,
+			root: merkle_root(next_authorities.iter().map(|authority| authority.encode())),
+		};
+		BeefyNextAuthorities::<T>::put(&set);
+		set
+	}
+}
+
+impl<T: Config> pallet_mmr::primitives::LeafDataProvider for Pallet<T> {
+	type LeafData = MmrLeafOf<T>;
+
+	fn leaf_data() -> Self::LeafData {
+		let parent_number = frame_system::Pallet::<T>::block_number().saturating_sub(One::one());
+		MmrLeaf {
+			parent_number_and_hash: (parent_number, frame_system::Pallet::<T>::parent_hash()),
+			beefy_next_authority_set: Self::beefy_next_authority_set(),
+			parent_state_root: Self::parent_state_root(),
+		}
+	}
+}
@@ -0,0 +1,89 @@
+use crate as pallet_mmr_leaf;
+use beefy_primitives::crypto::AuthorityId as BeefyId;
+use frame_support::parameter_types;
+use frame_system as system;
+use sp_core::{ecdsa, H256};
+use sp_runtime::{
+	testing::Header,
+	traits::{BlakeTwo256, IdentityLookup, Keccak256},
+	BuildStorage,
+};
+
+type UncheckedExtrinsic = frame_system::mocking::MockUncheckedExtrinsic<Test>;
+type Block = frame_system::mocking::MockBlock<Test>;
+
+// Configure a mock runtime to test the pallet.
+frame_support::construct_runtime!(
+	pub enum Test where
+		Block = Block,
+		NodeBlock = Block,
+		UncheckedExtrinsic = UncheckedExtrinsic,
+	{
+		System: frame_system::{Pallet, Call, Config, Storage, Event<T>},
+		Mmr: pallet_mmr::{Pallet, Storage},
+		Beefy: pallet_beefy::{Pallet, Config<T>},
+		MmrLeaf: pallet_mmr_leaf::{Pallet, Call, Storage, Inherent},
+	}
+);
+
+parameter_types! {
+	pub const BlockHashCount: u64 = 250;
+	pub const SS58Prefix: u8 = 42;
+}
+
+impl system::Config for Test {
+	type BaseCallFilter = frame_support::traits::AllowAll;
+	type BlockWeights = ();
+	type BlockLength = ();
+	type DbWeight = ();
+	type Origin = Origin;
+	type Call = Call;
+	type Index = u64;
+	type BlockNumber = u64;
+	type Hash = H256;
+	type Hashing = BlakeTwo256;
+	type AccountId = u64;
+	type Lookup = IdentityLookup<Self::AccountId>;
+	type Header = Header;
+	type Event = Event;
+	type BlockHashCount = BlockHashCount;
+	type Version = ();
+	type PalletInfo = PalletInfo;
+	type AccountData = ();
+	type OnNewAccount = ();
+	type OnKilledAccount = ();
+	type SystemWeightInfo = ();
+	type SS58Prefix = SS58Prefix;
+	type OnSetCode = ();
+}
+
+impl pallet_mmr::Config for Test {
+	const INDEXING_PREFIX: &'static [u8] = b"mmr";
+	type Hashing = Keccak256;
+	type Hash = H256;
+	type LeafData = MmrLeaf;
+	type OnNewRoot = ();
+	type WeightInfo = ();
+}
+
+impl pallet_beefy::Config for Test {
+	type BeefyId = BeefyId;
+}
+
+impl pallet_mmr_leaf::Config for Test {}
+
+/// The BEEFY keys of validators `ids`.
+pub fn beefy_ids(ids: &[u8]) -> Vec<BeefyId> {
+	ids.iter().map(|id| BeefyId::from(ecdsa::Public::from_raw([*id; 33]))).collect()
+}
+
+// Build genesis storage according to the mock runtime.
+pub fn new_test_ext() -> sp_io::TestExternalities {
+	GenesisConfig {
+		system: Default::default(),
+		beefy: pallet_beefy::GenesisConfig { authorities: beefy_ids(&[1, 2, 3]) },
+	}
+	.build_storage()
+	.unwrap()
+	.into()
+}
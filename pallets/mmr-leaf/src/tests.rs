@@ -0,0 +1,148 @@
+use crate::{merkle_root, mock::*, BeefyNextAuthoritySet, InherentError, INHERENT_IDENTIFIER};
+use codec::Encode;
+use frame_support::{
+	assert_ok,
+	inherent::{InherentData, ProvideInherent},
+	traits::{OnFinalize, OnInitialize},
+};
+use sp_core::H256;
+use sp_io::hashing::{keccak_256, twox_128};
+
+/// Start block `number` with parent hash `[number; 32]`, appending its leaf, and set its parent
+/// state root to `[0xa0 + number; 32]`.
+fn new_block() -> u64 {
+	let number = System::block_number() + 1;
+	System::initialize(
+		&number,
+		&H256::repeat_byte(number as u8),
+		&Default::default(),
+		frame_system::InitKind::Full,
+	);
+	Mmr::on_initialize(number);
+	MmrLeaf::on_initialize(number);
+	assert_ok!(MmrLeaf::set_parent_state_root(
+		Origin::none(),
+		H256::repeat_byte(0xa0 + number as u8)
+	));
+	MmrLeaf::on_finalize(number);
+	number
+}
+
+/// Inherent data carrying `state_root`.
+fn inherent_data(state_root: H256) -> InherentData {
+	let mut data = InherentData::new();
+	data.put_data(INHERENT_IDENTIFIER, &state_root).unwrap();
+	data
+}
+
+/// Overwrite storage item `item` of pallet-beefy.
+fn put_beefy<V: Encode>(item: &[u8], value: V) {
+	let key = [twox_128(b"Beefy"), twox_128(item)].concat();
+	frame_support::storage::unhashed::put(&key, &value);
+}
+
+#[test]
+fn merkle_root_hashes_pairs_and_promotes_odd_nodes() {
+	assert_eq!(merkle_root(vec![]), H256::zero());
+	assert_eq!(merkle_root(vec![b"a".to_vec()]), H256::from(keccak_256(b"a")));
+
+	let (a, b, c) = (keccak_256(b"a"), keccak_256(b"b"), keccak_256(b"c"));
+	let ab = keccak_256(&[a, b].concat());
+	assert_eq!(merkle_root(vec![b"a".to_vec(), b"b".to_vec()]), H256::from(ab));
+	assert_eq!(
+		merkle_root(vec![b"a".to_vec(), b"b".to_vec(), b"c".to_vec()]),
+		H256::from(keccak_256(&[ab, c].concat()))
+	);
+}
+
+#[test]
+fn leaves_commit_the_parent_block_beefy_set_and_parent_state_root() {
+	new_test_ext().execute_with(|| {
+		new_block();
+		new_block();
+		System::initialize(
+			&3,
+			&H256::repeat_byte(3),
+			&Default::default(),
+			frame_system::InitKind::Full,
+		);
+
+		// The leaf of block 3 commits block 2 and the state root of block 1, which block 2 set.
+		let leaf = <MmrLeaf as pallet_mmr::primitives::LeafDataProvider>::leaf_data();
+		assert_eq!(leaf.parent_number_and_hash, (2, H256::repeat_byte(3)));
+		assert_eq!(
+			leaf.beefy_next_authority_set,
+			BeefyNextAuthoritySet {
+				id: 1,
+				len: 3,
+				root: merkle_root(beefy_ids(&[1, 2, 3]).iter().map(|id| id.encode())),
+			}
+		);
+		assert_eq!(leaf.parent_state_root, H256::repeat_byte(0xa2));
+		assert_eq!(MmrLeaf::beefy_next_authorities(), leaf.beefy_next_authority_set);
+	});
+}
+
+#[test]
+fn next_authority_set_is_recomputed_when_the_set_changes() {
+	new_test_ext().execute_with(|| {
+		new_block();
+		assert_eq!(MmrLeaf::beefy_next_authorities().id, 1);
+
+		put_beefy(b"NextAuthorities", beefy_ids(&[4, 5]));
+		new_block();
+		// Same set id, so the committed set is still the cached one.
+		assert_eq!(MmrLeaf::beefy_next_authorities().len, 3);
+
+		put_beefy(b"ValidatorSetId", 1u64);
+		new_block();
+		assert_eq!(
+			MmrLeaf::beefy_next_authorities(),
+			BeefyNextAuthoritySet {
+				id: 2,
+				len: 2,
+				root: merkle_root(beefy_ids(&[4, 5]).iter().map(|id| id.encode())),
+			}
+		);
+	});
+}
+
+#[test]
+fn inherent_sets_the_state_root_importers_agree_with() {
+	let state_root = H256::repeat_byte(7);
+	let call = MmrLeaf::create_inherent(&inherent_data(state_root)).unwrap();
+	assert!(MmrLeaf::is_inherent(&call));
+	assert!(MmrLeaf::check_inherent(&call, &inherent_data(state_root)).is_ok());
+	assert!(matches!(
+		MmrLeaf::check_inherent(&call, &inherent_data(H256::zero())),
+		Err(InherentError::WrongParentStateRoot)
+	));
+	assert!(matches!(
+		MmrLeaf::check_inherent(&call, &InherentData::new()),
+		Err(InherentError::MissingParentStateRoot)
+	));
+}
+
+#[test]
+#[should_panic(expected = "The parent state root must be set only once in the block")]
+fn parent_state_root_cannot_be_set_twice() {
+	new_test_ext().execute_with(|| {
+		new_block();
+		let _ = MmrLeaf::set_parent_state_root(Origin::none(), H256::zero());
+		let _ = MmrLeaf::set_parent_state_root(Origin::none(), H256::zero());
+	});
+}
+
+#[test]
+#[should_panic(expected = "The parent state root must be set once in the block")]
+fn blocks_must_set_the_parent_state_root() {
+	new_test_ext().execute_with(|| {
+		System::initialize(
+			&1,
+			&H256::repeat_byte(1),
+			&Default::default(),
+			frame_system::InitKind::Full,
+		);
+		MmrLeaf::on_finalize(1);
+	});
+}
@@ -6,14 +6,24 @@ use super::*;
 use crate::Pallet as Template;
 use frame_benchmarking::{benchmarks, impl_benchmark_test_suite, whitelisted_caller};
 use frame_system::RawOrigin;
+use sp_std::prelude::*;
 
 benchmarks! {
-	do_something {
-		let s in 0 .. 100;
+	test_bind_port {
 		let caller: T::AccountId = whitelisted_caller();
-	}: _(RawOrigin::Signed(caller), s)
+		let port = b"bank".to_vec();
+	}: _(RawOrigin::Signed(caller.clone()), port.clone())
 	verify {
-		assert_eq!(Something::<T>::get(), Some(s));
+		assert_eq!(Ports::<T>::get(&port), Some(caller));
+	}
+
+	test_release_port {
+		let caller: T::AccountId = whitelisted_caller();
+		let port = b"bank".to_vec();
+		Ports::<T>::insert(&port, &caller);
+	}: _(RawOrigin::Signed(caller), port.clone())
+	verify {
+		assert_eq!(Ports::<T>::get(&port), None);
 	}
 }
 
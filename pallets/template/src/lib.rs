@@ -1,8 +1,18 @@
 #![cfg_attr(not(feature = "std"), no_std)]
 
-/// Edit this file to define custom logic or remove it if it is not needed.
-/// Learn more about FRAME and the core library of Substrate FRAME pallets:
-/// <https://substrate.dev/docs/en/knowledgebase/runtime/frame>
+//! A demo IBC application module.
+//!
+//! The `test_*` calls drive pallet-ibc from a signed extrinsic: they create GRANDPA clients,
+//! open connections and channels on the ports this module binds, and send raw packets. The
+//! runtime routes the channels and packets of those ports to the `on_*` callbacks, which
+//! acknowledge every packet and record what happened in events.
+//!
+//! Callers name clients, connections and channels by an `H256` of their choosing. pallet-ibc
+//! allocates the real identifiers, which the module reads from the events pallet-ibc deposits and
+//! remembers by the `H256` they stand for.
+//!
+//! The calls delivering messages to pallet-ibc take the `tmp` argument of its `deliver`, which
+//! differs between the demo chains, as the chain registry of the cli and relayer records.
 pub use pallet::*;
 
 #[cfg(test)]
@@ -14,90 +24,472 @@ mod tests;
 #[cfg(feature = "runtime-benchmarks")]
 mod benchmarking;
 
+extern crate alloc;
+
+/// The channel version spoken by this module.
+pub const VERSION: &str = "template-1";
+
+/// The acknowledgement written for every packet received.
+pub const ACK: &[u8] = &[1];
+
 #[frame_support::pallet]
 pub mod pallet {
-	use frame_support::{dispatch::DispatchResult, pallet_prelude::*};
+	use crate::{ACK, VERSION};
+	use alloc::{
+		format,
+		string::{String, ToString},
+	};
+	use codec::Encode;
+	use core::{str::FromStr, time::Duration};
+	use frame_support::{dispatch::DispatchResult, pallet_prelude::*, transactional};
 	use frame_system::pallet_prelude::*;
+	use ibc::ics02_client::client_consensus::AnyConsensusState;
+	use ibc::ics02_client::client_state::AnyClientState;
+	use ibc::ics02_client::height::Height;
+	use ibc::ics02_client::msgs::create_client::{self, MsgCreateAnyClient};
+	use ibc::ics03_connection::connection::Counterparty as ConnectionCounterparty;
+	use ibc::ics03_connection::msgs::conn_open_init::{self, MsgConnectionOpenInit};
+	use ibc::ics03_connection::version::Version as ConnectionVersion;
+	use ibc::ics04_channel::channel::{
+		ChannelEnd, Counterparty as ChannelCounterparty, Order, State,
+	};
+	use ibc::ics04_channel::msgs::chan_open_init::{self, MsgChannelOpenInit};
+	use ibc::ics04_channel::packet::{Packet, Sequence};
+	use ibc::ics10_grandpa::client_state::ClientState as GRANDPAClientState;
+	use ibc::ics10_grandpa::consensus_state::ConsensusState as GRANDPAConsensusState;
+	use ibc::ics23_commitment::commitment::CommitmentRoot;
+	use ibc::ics24_host::identifier::{ChainId, ChannelId, ClientId, ConnectionId, PortId};
+	use ibc::signer::Signer;
+	use ibc_proto::ibc::core::channel::v1::MsgChannelOpenInit as RawMsgChannelOpenInit;
+	use ibc_proto::ibc::core::client::v1::MsgCreateClient as RawMsgCreateClient;
+	use ibc_proto::ibc::core::connection::v1::MsgConnectionOpenInit as RawMsgConnectionOpenInit;
+	use pallet_ibc_utils::ChannelError;
+	use prost::Message;
+	use sp_core::H256;
+	use sp_finality_grandpa::{AuthorityList, SetId};
+	use sp_std::{convert::TryInto, prelude::*};
 
 	/// Configure the pallet by specifying the parameters and types on which it depends.
 	#[pallet::config]
-	pub trait Config: frame_system::Config {
+	pub trait Config: frame_system::Config + pallet_ibc::Config {
 		/// Because this pallet emits events, it depends on the runtime's definition of an event.
-		type Event: From<Event<Self>> + IsType<<Self as frame_system::Config>::Event>;
+		/// The identifiers pallet-ibc allocates are read back from its events.
+		type Event: From<Event<Self>>
+			+ IsType<<Self as frame_system::Config>::Event>
+			+ TryInto<pallet_ibc::Event<Self>>;
 	}
 
 	#[pallet::pallet]
 	#[pallet::generate_store(pub(super) trait Store)]
 	pub struct Pallet<T>(_);
 
-	// The pallet's runtime storage items.
-	// https://substrate.dev/docs/en/knowledgebase/runtime/storage
+	/// The ports bound to this module, and who bound them.
+	#[pallet::storage]
+	#[pallet::getter(fn port_owner)]
+	pub type Ports<T: Config> = StorageMap<_, Blake2_128Concat, Vec<u8>, T::AccountId>;
+
+	/// The identifier pallet-ibc allocated for each client, connection or channel created here,
+	/// by the `H256` the caller named it with.
 	#[pallet::storage]
-	#[pallet::getter(fn something)]
-	// Learn more about declaring storage items:
-	// https://substrate.dev/docs/en/knowledgebase/runtime/storage#declaring-storage-items
-	pub type Something<T> = StorageValue<_, u32>;
+	#[pallet::getter(fn identifier)]
+	pub type Identifiers<T> = StorageMap<_, Blake2_128Concat, H256, Vec<u8>>;
 
-	// Pallets use events to inform users when important changes are made.
-	// https://substrate.dev/docs/en/knowledgebase/runtime/events
 	#[pallet::event]
 	#[pallet::metadata(T::AccountId = "AccountId")]
 	#[pallet::generate_deposit(pub(super) fn deposit_event)]
 	pub enum Event<T: Config> {
-		/// Event documentation should end with an array that provides descriptive names for event
-		/// parameters. [something, who]
-		SomethingStored(u32, T::AccountId),
+		/// A port was bound to this module. [port_id, who]
+		PortBound(Vec<u8>, T::AccountId),
+		/// A port was released. [port_id]
+		PortReleased(Vec<u8>),
+		/// pallet-ibc allocated an identifier for a client, connection or channel. [name, identifier]
+		IdentifierAssigned(H256, Vec<u8>),
+		/// A channel of this module was opened. [port_id, channel_id]
+		ChannelOpened(Vec<u8>, Vec<u8>),
+		/// A packet was received and acknowledged. [port_id, channel_id, sequence, data]
+		PacketReceived(Vec<u8>, Vec<u8>, u64, Vec<u8>),
+		/// A packet sent was acknowledged. [port_id, channel_id, sequence, acknowledgement]
+		PacketAcknowledged(Vec<u8>, Vec<u8>, u64, Vec<u8>),
+		/// A packet sent timed out. [port_id, channel_id, sequence]
+		PacketTimedOut(Vec<u8>, Vec<u8>, u64),
 	}
 
-	// Errors inform users that something went wrong.
 	#[pallet::error]
 	pub enum Error<T> {
-		/// Error names should be descriptive.
-		NoneValue,
-		/// Errors should have helpful documentation associated with them.
-		StorageOverflow,
+		/// The identifier is not a valid IBC identifier.
+		InvalidIdentifier,
+		/// The name already stands for another identifier.
+		IdentifierInUse,
+		/// No identifier was assigned to the name.
+		UnknownIdentifier,
+		/// pallet-ibc did not create the client, connection or channel.
+		NotCreated,
+		/// The client state can not be built from the arguments.
+		InvalidClientState,
+		/// The port is already bound.
+		PortInUse,
+		/// The port is not bound to this module.
+		PortNotBound,
+		/// The port was bound by someone else.
+		NotPortOwner,
+		/// The channel does not exist.
+		ChannelNotFound,
+		/// The channel has no counterparty channel yet.
+		ChannelNotOpen,
+		/// The channel version is not this module's.
+		InvalidVersion,
+		/// Packets sent on the port are neither acknowledged nor timed out yet.
+		PacketsInFlight,
+	}
+
+	impl<T> From<ChannelError> for Error<T> {
+		fn from(error: ChannelError) -> Self {
+			match error {
+				ChannelError::NotFound => Error::<T>::ChannelNotFound,
+				ChannelError::NotOpen => Error::<T>::ChannelNotOpen,
+			}
+		}
 	}
 
-	// Dispatchable functions allows users to interact with the pallet and invoke state changes.
-	// These functions materialize as "extrinsics", which are often compared to transactions.
-	// Dispatchable functions must be annotated with a weight and must return a DispatchResult.
 	#[pallet::call]
 	impl<T: Config> Pallet<T> {
-		/// An example dispatchable that takes a singles value as a parameter, writes the value to
-		/// storage and emits an event. This function must be dispatched by a signed extrinsic.
-		#[pallet::weight(10_000 + T::DbWeight::get().writes(1))]
-		pub fn do_something(origin: OriginFor<T>, something: u32) -> DispatchResult {
-			// Check that the extrinsic was signed and get the signer.
-			// This function will return an error if the extrinsic is not signed.
-			// https://substrate.dev/docs/en/knowledgebase/runtime/origin
+		/// Create a GRANDPA client of a counterparty trusting `authority_list` at `height`.
+		#[pallet::weight(10_000 + T::DbWeight::get().reads_writes(4, 4))]
+		#[transactional]
+		pub fn test_create_client(
+			origin: OriginFor<T>,
+			identifier: H256,
+			height: u32,
+			set_id: SetId,
+			authority_list: AuthorityList,
+			root: H256,
+			tmp: u8,
+		) -> DispatchResult {
+			let who = ensure_signed(origin.clone())?;
+			ensure!(!Identifiers::<T>::contains_key(identifier), Error::<T>::IdentifierInUse);
+
+			let client_state = GRANDPAClientState::new(
+				ChainId::from_string(&format!("{:x}", identifier)),
+				Height::new(0, height as u64),
+				Height::new(0, 0),
+				set_id,
+				authority_list.into(),
+			)
+			.map_err(|_| Error::<T>::InvalidClientState)?;
+			let consensus_state =
+				GRANDPAConsensusState::new(CommitmentRoot::from(root.as_bytes().to_vec()));
+			let msg = MsgCreateAnyClient::new(
+				AnyClientState::Grandpa(client_state),
+				AnyConsensusState::Grandpa(consensus_state),
+				Self::signer(&who),
+			)
+			.map_err(|_| Error::<T>::InvalidClientState)?;
+
+			let client_id = Self::deliver(
+				origin,
+				create_client::TYPE_URL,
+				RawMsgCreateClient::from(msg),
+				tmp,
+				|event| match event {
+					pallet_ibc::Event::CreateClient(_height, client_id, ..) =>
+						Some(client_id.to_ibc_client_id().as_bytes().to_vec()),
+					_ => None,
+				},
+			)?;
+
+			Self::assign(identifier, client_id);
+			Ok(())
+		}
+
+		/// Open a connection on a client created with `test_create_client`.
+		///
+		/// `counterparty_client_id` is the identifier the counterparty allocated to its client of
+		/// this chain; the counterparty allocates its own connection identifier.
+		#[pallet::weight(10_000 + T::DbWeight::get().reads_writes(4, 4))]
+		#[transactional]
+		pub fn test_conn_open_init(
+			origin: OriginFor<T>,
+			identifier: H256,
+			_desired_counterparty_connection_identifier: H256,
+			client_identifier: H256,
+			counterparty_client_id: Vec<u8>,
+			tmp: u8,
+		) -> DispatchResult {
+			let who = ensure_signed(origin.clone())?;
+			ensure!(!Identifiers::<T>::contains_key(identifier), Error::<T>::IdentifierInUse);
+
+			let client_id = ClientId::from_str(&Self::resolve(&client_identifier)?)
+				.map_err(|_| Error::<T>::InvalidIdentifier)?;
+			let counterparty_client_id = core::str::from_utf8(&counterparty_client_id)
+				.ok()
+				.and_then(|client_id| ClientId::from_str(client_id).ok())
+				.ok_or(Error::<T>::InvalidIdentifier)?;
+			let msg = MsgConnectionOpenInit::new(
+				client_id,
+				ConnectionCounterparty::new(counterparty_client_id, None, b"ibc".to_vec().into()),
+				ConnectionVersion::default(),
+				Duration::from_secs(0),
+				Self::signer(&who),
+			);
+
+			let connection_id = Self::deliver(
+				origin,
+				conn_open_init::TYPE_URL,
+				RawMsgConnectionOpenInit::from(msg),
+				tmp,
+				|event| match event {
+					pallet_ibc::Event::OpenInitConnection(_height, Some(connection_id), ..) =>
+						Some(connection_id.to_ibc_connection_id().as_bytes().to_vec()),
+					_ => None,
+				},
+			)?;
+
+			Self::assign(identifier, connection_id);
+			Ok(())
+		}
+
+		/// Bind a port to this module, routing its channels and packets here.
+		#[pallet::weight(10_000 + T::DbWeight::get().reads_writes(1, 1))]
+		pub fn test_bind_port(origin: OriginFor<T>, identifier: Vec<u8>) -> DispatchResult {
 			let who = ensure_signed(origin)?;
+			Self::port_id(&identifier)?;
+			ensure!(!Ports::<T>::contains_key(&identifier), Error::<T>::PortInUse);
 
-			// Update storage.
-			<Something<T>>::put(something);
+			Ports::<T>::insert(&identifier, &who);
+			Self::deposit_event(Event::PortBound(identifier, who));
+			Ok(())
+		}
+
+		/// Release a port bound with `test_bind_port`.
+		///
+		/// The acknowledgements and timeouts of the packets sent on the port are routed here, so
+		/// it is only released once none is in flight.
+		#[pallet::weight(10_000 + T::DbWeight::get().reads_writes(1, 1))]
+		pub fn test_release_port(origin: OriginFor<T>, identifier: Vec<u8>) -> DispatchResult {
+			let who = ensure_signed(origin)?;
+			let owner = Ports::<T>::get(&identifier).ok_or(Error::<T>::PortNotBound)?;
+			ensure!(owner == who, Error::<T>::NotPortOwner);
+			ensure!(!Self::has_packets_in_flight(&identifier), Error::<T>::PacketsInFlight);
 
-			// Emit an event.
-			Self::deposit_event(Event::SomethingStored(something, who));
-			// Return a successful DispatchResultWithPostInfo
+			Ports::<T>::remove(&identifier);
+			Self::deposit_event(Event::PortReleased(identifier));
 			Ok(())
 		}
 
-		/// An example dispatchable that may throw a custom error.
-		#[pallet::weight(10_000 + T::DbWeight::get().reads_writes(1,1))]
-		pub fn cause_error(origin: OriginFor<T>) -> DispatchResult {
-			let _who = ensure_signed(origin)?;
-
-			// Read a value from storage.
-			match <Something<T>>::get() {
-				// Return an error if the value has not been set.
-				None => Err(Error::<T>::NoneValue)?,
-				Some(old) => {
-					// Increment the value read from storage; will error in the event of overflow.
-					let new = old.checked_add(1).ok_or(Error::<T>::StorageOverflow)?;
-					// Update the value in storage with the incremented result.
-					<Something<T>>::put(new);
-					Ok(())
+		/// Open a channel on a bound port over connections opened with `test_conn_open_init`.
+		///
+		/// The counterparty allocates its own channel identifier during the handshake.
+		#[pallet::weight(10_000 + T::DbWeight::get().reads_writes(4, 4))]
+		#[transactional]
+		pub fn test_chan_open_init(
+			origin: OriginFor<T>,
+			unordered: bool,
+			connection_hops: Vec<H256>,
+			port_identifier: Vec<u8>,
+			channel_identifier: H256,
+			counterparty_port_identifier: Vec<u8>,
+			_counterparty_channel_identifier: H256,
+			tmp: u8,
+		) -> DispatchResult {
+			let who = ensure_signed(origin.clone())?;
+			ensure!(Ports::<T>::contains_key(&port_identifier), Error::<T>::PortNotBound);
+			ensure!(
+				!Identifiers::<T>::contains_key(channel_identifier),
+				Error::<T>::IdentifierInUse
+			);
+
+			let connection_hops = connection_hops
+				.iter()
+				.map(|hop| {
+					ConnectionId::from_str(&Self::resolve(hop)?)
+						.map_err(|_| Error::<T>::InvalidIdentifier.into())
+				})
+				.collect::<Result<Vec<_>, DispatchError>>()?;
+			let order = if unordered { Order::Unordered } else { Order::Ordered };
+			let msg = MsgChannelOpenInit {
+				port_id: Self::port_id(&port_identifier)?,
+				channel: ChannelEnd::new(
+					State::Init,
+					order,
+					ChannelCounterparty::new(Self::port_id(&counterparty_port_identifier)?, None),
+					connection_hops,
+					VERSION.to_string(),
+				),
+				signer: Self::signer(&who),
+			};
+
+			let channel_id = Self::deliver(
+				origin,
+				chan_open_init::TYPE_URL,
+				RawMsgChannelOpenInit::from(msg),
+				tmp,
+				|event| match event {
+					pallet_ibc::Event::OpenInitChannel(_height, _port_id, Some(channel_id), ..) =>
+						Some(channel_id.to_ibc_channel_id().as_bytes().to_vec()),
+					_ => None,
 				},
-			}
+			)?;
+
+			Self::assign(channel_identifier, channel_id);
+			Ok(())
+		}
+
+		/// Send `data` over a channel opened with `test_chan_open_init`, with the next sequence
+		/// of the channel, to the port and channel on the other end of it.
+		#[pallet::weight(10_000 + T::DbWeight::get().reads_writes(5, 3))]
+		pub fn test_send_packet(
+			origin: OriginFor<T>,
+			timeout_height: u32,
+			source_port: Vec<u8>,
+			source_channel: H256,
+			data: Vec<u8>,
+		) -> DispatchResult {
+			ensure_signed(origin)?;
+			ensure!(Ports::<T>::contains_key(&source_port), Error::<T>::PortNotBound);
+
+			let source_port = Self::port_id(&source_port)?;
+			let source_channel = ChannelId::from_str(&Self::resolve(&source_channel)?)
+				.map_err(|_| Error::<T>::InvalidIdentifier)?;
+			let (destination_port, destination_channel) =
+				pallet_ibc_utils::counterparty::<T>(&source_port, &source_channel)
+					.map_err(Error::<T>::from)?;
+			let sequence = pallet_ibc_utils::next_sequence_send::<T>(&source_port, &source_channel);
+
+			pallet_ibc::Pallet::<T>::send_packet(Packet {
+				sequence: Sequence::from(sequence),
+				source_port,
+				source_channel,
+				destination_port,
+				destination_channel,
+				data,
+				timeout_height: Height::new(0, timeout_height as u64),
+				timeout_timestamp: Default::default(),
+			})
+		}
+	}
+
+	impl<T: Config> Pallet<T> {
+		/// Whether the channels and packets of `port_id` are routed to this module.
+		pub fn is_bound(port_id: &PortId) -> bool {
+			Ports::<T>::contains_key(port_id.as_bytes())
+		}
+
+		pub fn on_chan_open_init(_order: &Order, version: &str) -> DispatchResult {
+			ensure!(version == VERSION, Error::<T>::InvalidVersion);
+			Ok(())
+		}
+
+		pub fn on_chan_open_try(_order: &Order, counterparty_version: &str) -> DispatchResult {
+			ensure!(counterparty_version == VERSION, Error::<T>::InvalidVersion);
+			Ok(())
+		}
+
+		pub fn on_chan_open_ack(
+			port_id: &PortId,
+			channel_id: &ChannelId,
+			counterparty_version: &str,
+		) -> DispatchResult {
+			ensure!(counterparty_version == VERSION, Error::<T>::InvalidVersion);
+			Self::deposit_event(Event::ChannelOpened(
+				port_id.as_bytes().to_vec(),
+				channel_id.as_bytes().to_vec(),
+			));
+			Ok(())
+		}
+
+		pub fn on_chan_open_confirm(port_id: &PortId, channel_id: &ChannelId) -> DispatchResult {
+			Self::deposit_event(Event::ChannelOpened(
+				port_id.as_bytes().to_vec(),
+				channel_id.as_bytes().to_vec(),
+			));
+			Ok(())
+		}
+
+		/// Record a received packet and return the acknowledgement to write.
+		pub fn on_recv_packet(packet: &Packet) -> Vec<u8> {
+			Self::deposit_event(Event::PacketReceived(
+				packet.destination_port.as_bytes().to_vec(),
+				packet.destination_channel.as_bytes().to_vec(),
+				u64::from(packet.sequence),
+				packet.data.clone(),
+			));
+			ACK.to_vec()
+		}
+
+		pub fn on_acknowledgement_packet(
+			packet: &Packet,
+			acknowledgement: &[u8],
+		) -> DispatchResult {
+			Self::deposit_event(Event::PacketAcknowledged(
+				packet.source_port.as_bytes().to_vec(),
+				packet.source_channel.as_bytes().to_vec(),
+				u64::from(packet.sequence),
+				acknowledgement.to_vec(),
+			));
+			Ok(())
+		}
+
+		pub fn on_timeout_packet(packet: &Packet) -> DispatchResult {
+			Self::deposit_event(Event::PacketTimedOut(
+				packet.source_port.as_bytes().to_vec(),
+				packet.source_channel.as_bytes().to_vec(),
+				u64::from(packet.sequence),
+			));
+			Ok(())
+		}
+
+		/// Deliver `msg` to pallet-ibc, returning the identifier `created` finds in the events
+		/// it deposits. Callers are transactional, so nothing delivered is kept when no
+		/// identifier is found.
+		fn deliver<M: Message>(
+			origin: OriginFor<T>,
+			type_url: &str,
+			msg: M,
+			tmp: u8,
+			created: impl Fn(pallet_ibc::Event<T>) -> Option<Vec<u8>>,
+		) -> Result<Vec<u8>, DispatchError> {
+			let mut value = Vec::new();
+			msg.encode(&mut value).expect("a Vec grows to fit the message; qed");
+			let any = pallet_ibc::Any { type_url: type_url.to_string(), value };
+
+			let event_count = frame_system::Pallet::<T>::event_count();
+			pallet_ibc::Pallet::<T>::deliver(origin, vec![any], tmp)?;
+			frame_system::Pallet::<T>::events()
+				.into_iter()
+				.skip(event_count as usize)
+				.filter_map(|record| <T as Config>::Event::from(record.event).try_into().ok())
+				.find_map(created)
+				.ok_or_else(|| Error::<T>::NotCreated.into())
+		}
+
+		/// Whether a packet sent on a channel of `port_id` still has a commitment.
+		fn has_packets_in_flight(port_id: &[u8]) -> bool {
+			pallet_ibc::Channels::<T>::iter_key_prefix(port_id).any(|channel_id| {
+				pallet_ibc::PacketCommitment::<T>::iter_prefix((port_id.to_vec(), channel_id))
+					.next()
+					.is_some()
+			})
+		}
+
+		fn assign(name: H256, identifier: Vec<u8>) {
+			Identifiers::<T>::insert(name, &identifier);
+			Self::deposit_event(Event::IdentifierAssigned(name, identifier));
+		}
+
+		fn resolve(name: &H256) -> Result<String, DispatchError> {
+			let identifier = Identifiers::<T>::get(name).ok_or(Error::<T>::UnknownIdentifier)?;
+			Ok(String::from_utf8(identifier).map_err(|_| Error::<T>::InvalidIdentifier)?)
+		}
+
+		fn port_id(identifier: &[u8]) -> Result<PortId, DispatchError> {
+			let identifier =
+				core::str::from_utf8(identifier).map_err(|_| Error::<T>::InvalidIdentifier)?;
+			Ok(PortId::from_str(identifier).map_err(|_| Error::<T>::InvalidIdentifier)?)
+		}
+
+		/// The signer of the messages built for `who`, its hex encoded account id.
+		fn signer(who: &T::AccountId) -> Signer {
+			Signer::new(format!("0x{}", hex::encode(who.encode())))
 		}
 	}
 }
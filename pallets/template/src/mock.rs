@@ -18,6 +18,7 @@ frame_support::construct_runtime!(
 		UncheckedExtrinsic = UncheckedExtrinsic,
 	{
 		System: frame_system::{Pallet, Call, Config, Storage, Event<T>},
+		Ibc: pallet_ibc::{Pallet, Call, Storage, Event<T>},
 		TemplateModule: pallet_template::{Pallet, Call, Storage, Event<T>},
 	}
 );
@@ -53,11 +54,20 @@ impl system::Config for Test {
 	type OnSetCode = ();
 }
 
+impl pallet_ibc::Config for Test {
+	type Event = Event;
+	type ModuleCallbacks = ();
+}
+
 impl pallet_template::Config for Test {
 	type Event = Event;
 }
 
 // Build genesis storage according to the mock runtime.
 pub fn new_test_ext() -> sp_io::TestExternalities {
-	system::GenesisConfig::default().build_storage::<Test>().unwrap().into()
+	let mut ext: sp_io::TestExternalities =
+		system::GenesisConfig::default().build_storage::<Test>().unwrap().into();
+	// Events are not recorded in the genesis block.
+	ext.execute_with(|| System::set_block_number(1));
+	ext
 }
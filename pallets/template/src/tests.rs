@@ -1,20 +1,146 @@
-use crate::{mock::*, Error};
+use crate::{mock::*, Error, Event as TemplateEvent, ACK};
 use frame_support::{assert_noop, assert_ok};
+use ibc::ics02_client::height::Height;
+use ibc::ics04_channel::packet::{Packet, Sequence};
+use ibc::ics24_host::identifier::{ChannelId, PortId};
+use sp_core::H256;
+use std::str::FromStr;
+
+fn last_event() -> Event {
+	System::events().pop().expect("an event was deposited").event
+}
+
+fn packet() -> Packet {
+	Packet {
+		sequence: Sequence::from(1),
+		source_port: PortId::from_str("bank").unwrap(),
+		source_channel: ChannelId::new(0),
+		destination_port: PortId::from_str("bank").unwrap(),
+		destination_channel: ChannelId::new(1),
+		data: vec![1, 2, 3, 4],
+		timeout_height: Height::new(0, 1000),
+		timeout_timestamp: Default::default(),
+	}
+}
 
 #[test]
-fn it_works_for_default_value() {
+fn binds_and_releases_ports() {
 	new_test_ext().execute_with(|| {
-		// Dispatch a signed extrinsic.
-		assert_ok!(TemplateModule::do_something(Origin::signed(1), 42));
-		// Read pallet storage and assert an expected result.
-		assert_eq!(TemplateModule::something(), Some(42));
+		assert_ok!(TemplateModule::test_bind_port(Origin::signed(1), b"bank".to_vec()));
+		assert_eq!(TemplateModule::port_owner(b"bank".to_vec()), Some(1));
+		assert!(TemplateModule::is_bound(&PortId::from_str("bank").unwrap()));
+		assert_eq!(
+			last_event(),
+			Event::TemplateModule(TemplateEvent::PortBound(b"bank".to_vec(), 1))
+		);
+
+		assert_ok!(TemplateModule::test_release_port(Origin::signed(1), b"bank".to_vec()));
+		assert_eq!(TemplateModule::port_owner(b"bank".to_vec()), None);
+		assert_eq!(
+			last_event(),
+			Event::TemplateModule(TemplateEvent::PortReleased(b"bank".to_vec()))
+		);
 	});
 }
 
 #[test]
-fn correct_error_for_none_value() {
+fn correct_errors_for_port_binding() {
 	new_test_ext().execute_with(|| {
-		// Ensure the expected error is thrown when no value is present.
-		assert_noop!(TemplateModule::cause_error(Origin::signed(1)), Error::<Test>::NoneValue);
+		assert_noop!(
+			TemplateModule::test_bind_port(Origin::signed(1), b"b".to_vec()),
+			Error::<Test>::InvalidIdentifier
+		);
+		assert_noop!(
+			TemplateModule::test_release_port(Origin::signed(1), b"bank".to_vec()),
+			Error::<Test>::PortNotBound
+		);
+
+		assert_ok!(TemplateModule::test_bind_port(Origin::signed(1), b"bank".to_vec()));
+		assert_noop!(
+			TemplateModule::test_bind_port(Origin::signed(2), b"bank".to_vec()),
+			Error::<Test>::PortInUse
+		);
+		assert_noop!(
+			TemplateModule::test_release_port(Origin::signed(2), b"bank".to_vec()),
+			Error::<Test>::NotPortOwner
+		);
+	});
+}
+
+#[test]
+fn keeps_ports_with_packets_in_flight() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(TemplateModule::test_bind_port(Origin::signed(1), b"bank".to_vec()));
+		pallet_ibc::Channels::<Test>::insert(b"bank".to_vec(), b"channel-0".to_vec(), vec![0]);
+		let path = (b"bank".to_vec(), b"channel-0".to_vec());
+		pallet_ibc::PacketCommitment::<Test>::insert(&path, 1, vec![0]);
+		assert_noop!(
+			TemplateModule::test_release_port(Origin::signed(1), b"bank".to_vec()),
+			Error::<Test>::PacketsInFlight
+		);
+
+		pallet_ibc::PacketCommitment::<Test>::remove(&path, 1);
+		assert_ok!(TemplateModule::test_release_port(Origin::signed(1), b"bank".to_vec()));
+	});
+}
+
+#[test]
+fn correct_errors_for_sending_packets() {
+	new_test_ext().execute_with(|| {
+		let send = || {
+			TemplateModule::test_send_packet(
+				Origin::signed(1),
+				1000,
+				b"bank".to_vec(),
+				H256::repeat_byte(1),
+				vec![1, 2, 3, 4],
+			)
+		};
+		assert_noop!(send(), Error::<Test>::PortNotBound);
+
+		assert_ok!(TemplateModule::test_bind_port(Origin::signed(1), b"bank".to_vec()));
+		assert_noop!(send(), Error::<Test>::UnknownIdentifier);
+	});
+}
+
+#[test]
+fn acknowledges_received_packets() {
+	new_test_ext().execute_with(|| {
+		assert_eq!(TemplateModule::on_recv_packet(&packet()), ACK.to_vec());
+		assert_eq!(
+			last_event(),
+			Event::TemplateModule(TemplateEvent::PacketReceived(
+				b"bank".to_vec(),
+				b"channel-1".to_vec(),
+				1,
+				vec![1, 2, 3, 4]
+			))
+		);
+	});
+}
+
+#[test]
+fn records_acknowledgements_and_timeouts() {
+	new_test_ext().execute_with(|| {
+		assert_ok!(TemplateModule::on_acknowledgement_packet(&packet(), ACK));
+		assert_eq!(
+			last_event(),
+			Event::TemplateModule(TemplateEvent::PacketAcknowledged(
+				b"bank".to_vec(),
+				b"channel-0".to_vec(),
+				1,
+				ACK.to_vec()
+			))
+		);
+
+		assert_ok!(TemplateModule::on_timeout_packet(&packet()));
+		assert_eq!(
+			last_event(),
+			Event::TemplateModule(TemplateEvent::PacketTimedOut(
+				b"bank".to_vec(),
+				b"channel-0".to_vec(),
+				1
+			))
+		);
 	});
 }
@@ -0,0 +1,135 @@
+//! A chain's RPC connection, reconnected with exponential backoff when it drops, failing over
+//! between the chain's endpoints.
+use crate::metrics;
+use async_std::future;
+use async_std::sync::Mutex;
+use calls::NodeRuntime as Runtime;
+use log::{info, warn};
+use std::cmp;
+use std::error::Error;
+use std::sync::Arc;
+use std::time::Duration;
+use substrate_subxt::{Client, ClientBuilder};
+
+/// How long a connection has to answer before it is considered dropped.
+const PING_TIMEOUT: Duration = Duration::from_secs(10);
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+/// How many times every endpoint is tried before reconnecting is given up on.
+const MAX_ROUNDS: u32 = 10;
+
+struct Connection {
+    client: Client<Runtime>,
+    /// The index of the endpoint `client` is connected to.
+    endpoint: usize,
+}
+
+#[derive(Clone)]
+pub struct Chain {
+    pub name: String,
+    endpoints: Arc<Vec<String>>,
+    connection: Arc<Mutex<Connection>>,
+    /// Held while reconnecting, so that tasks finding the connection dropped at the same time
+    /// wait for one reconnection, while the others keep the current client.
+    reconnecting: Arc<Mutex<()>>,
+}
+
+impl Chain {
+    /// Connect to the first of `endpoints` that accepts the connection.
+    pub async fn connect(name: String, endpoints: Vec<String>) -> Result<Self, Box<dyn Error>> {
+        for (index, endpoint) in endpoints.iter().enumerate() {
+            match build(endpoint).await {
+                Ok(client) => {
+                    return Ok(Chain {
+                        name,
+                        endpoints: Arc::new(endpoints),
+                        connection: Arc::new(Mutex::new(Connection {
+                            client,
+                            endpoint: index,
+                        })),
+                        reconnecting: Arc::new(Mutex::new(())),
+                    })
+                }
+                Err(e) => warn!("[{}] can not connect to {}; error = {}", name, endpoint, e),
+            }
+        }
+        Err(format!("[{}] can not connect to any endpoint", name).into())
+    }
+
+    /// The client of the current connection.
+    pub async fn client(&self) -> Client<Runtime> {
+        self.connection.lock().await.client.clone()
+    }
+
+    /// Reconnect if the current connection no longer answers, trying the endpoints in turn,
+    /// starting from the one after the current one, until one accepts the connection.
+    ///
+    /// Tasks that find the connection dropped at the same time wait for one reconnection. The
+    /// connection is not locked while reconnecting, and every endpoint is tried `MAX_ROUNDS`
+    /// times with exponential backoff before this fails.
+    pub async fn ensure_connected(&self) -> Result<(), Box<dyn Error>> {
+        let _reconnecting = self.reconnecting.lock().await;
+        let (client, current) = {
+            let connection = self.connection.lock().await;
+            (connection.client.clone(), connection.endpoint)
+        };
+        if ping(&self.name, &client).await {
+            return Ok(());
+        }
+        warn!(
+            "[{}] lost connection to {}",
+            self.name, self.endpoints[current]
+        );
+        let mut backoff = INITIAL_BACKOFF;
+        for round in 1..=MAX_ROUNDS {
+            for offset in 1..=self.endpoints.len() {
+                let index = (current + offset) % self.endpoints.len();
+                let endpoint = &self.endpoints[index];
+                match build(endpoint).await {
+                    Ok(client) => {
+                        info!("[{}] connected to {}", self.name, endpoint);
+                        *self.connection.lock().await = Connection {
+                            client,
+                            endpoint: index,
+                        };
+                        return Ok(());
+                    }
+                    Err(e) => warn!(
+                        "[{}] can not connect to {}; error = {}",
+                        self.name, endpoint, e
+                    ),
+                }
+            }
+            if round < MAX_ROUNDS {
+                warn!("[{}] retrying to connect in {:?}", self.name, backoff);
+                async_std::task::sleep(backoff).await;
+                backoff = cmp::min(backoff * 2, MAX_BACKOFF);
+            }
+        }
+        Err(format!(
+            "[{}] can not reconnect to any endpoint after {} rounds",
+            self.name, MAX_ROUNDS
+        )
+        .into())
+    }
+}
+
+async fn build(endpoint: &str) -> Result<Client<Runtime>, substrate_subxt::Error> {
+    ClientBuilder::<Runtime>::new()
+        .set_url(endpoint)
+        .build()
+        .await
+}
+
+/// Whether `client` still answers requests.
+async fn ping(chain_name: &str, client: &Client<Runtime>) -> bool {
+    let finalized_head = metrics::time_rpc(
+        chain_name,
+        "chain_getFinalizedHead",
+        client.finalized_head(),
+    );
+    matches!(
+        future::timeout(PING_TIMEOUT, finalized_head).await,
+        Ok(Ok(_))
+    )
+}
@@ -0,0 +1,334 @@
+//! Packet clearing: relay what the event-driven path missed, e.g. packets sent while the relayer
+//! was offline, by comparing the packet commitments on a chain with the receipts and
+//! acknowledgements on the counterparty.
+use crate::filter::ChannelFilter;
+use crate::packet;
+use crate::store::{Relayed, Store};
+use async_std::channel::Sender;
+use calls::{ibc::IbcRpcExt, NodeRuntime as Runtime};
+use codec::Decode;
+use ibc::ics03_connection::connection::ConnectionEnd;
+use ibc::ics04_channel::channel::{ChannelEnd, Order, State as ChannelState};
+use ibc::ics04_channel::packet::Packet;
+use log::info;
+use sp_core::H256;
+use std::error::Error;
+use substrate_subxt::{BlockNumber, Client};
+use tendermint_proto::Protobuf;
+
+/// Relay the `RecvPacket`, `Acknowledgement` and `Timeout` messages still missing for the packets
+/// sent on `client` to `counterparty_client`.
+///
+/// Proofs are taken at the heights the light clients on either side already know, so nothing
+/// here needs a client update; packets sent after that are left to the next pass. If `channel`
+/// is `None`, every open channel of `client` towards the counterparty `filter` allows is
+/// cleared. `client_type` and `counterparty_client_type` are the types of the clients tracking
+/// `client` and `counterparty_client`.
+pub async fn clear_packets(
+    chain_name: &str,
+    counterparty_name: &str,
+    tx: &Sender<pallet_ibc::Any>,
+    source_tx: &Sender<pallet_ibc::Any>,
+    client: &Client<Runtime>,
+    client_identifier: &str,
+    counterparty_client: &Client<Runtime>,
+    counterparty_client_identifier: &str,
+    client_type: &str,
+    counterparty_client_type: &str,
+    channel: Option<(&str, &str)>,
+    filter: &ChannelFilter,
+    store: &Store,
+) -> Result<(), Box<dyn Error>> {
+    // The latest block of the counterparty known on `client`, and the other way around.
+    let counterparty_height = client_height(
+        client,
+        counterparty_client_identifier,
+        counterparty_client_type,
+    )
+    .await?;
+    let counterparty_block_hash = counterparty_client
+        .block_hash(Some(BlockNumber::from(counterparty_height)))
+        .await?
+        .ok_or("counterparty block not found")?;
+    let counterparty_timestamp =
+        packet::block_timestamp(counterparty_client, counterparty_block_hash).await?;
+    let height = client_height(counterparty_client, client_identifier, client_type).await?;
+    let block_hash = client
+        .block_hash(Some(BlockNumber::from(height)))
+        .await?
+        .ok_or("block not found")?;
+
+    for (port_id, channel_id, data) in client.query_channels(None).await?.into_iter() {
+        if let Some((port, chan)) = channel {
+            if port != port_id || chan != channel_id {
+                continue;
+            }
+        }
+        if !filter.allows(&port_id, &channel_id) {
+            continue;
+        }
+        let channel_end = ChannelEnd::decode_vec(&data.value)?;
+        if channel_end.state() != &ChannelState::Open {
+            continue;
+        }
+        let connection_id = channel_end
+            .connection_hops()
+            .first()
+            .ok_or("channel without connection")?;
+        let connection = client
+            .query_connection(connection_id.as_str(), None)
+            .await?
+            .ok_or("connection not found")?;
+        let connection_end = ConnectionEnd::decode_vec(&connection.value)?;
+        if connection_end.client_id().as_str() != counterparty_client_identifier {
+            continue;
+        }
+
+        let commitments = client
+            .query_packet_commitments(&port_id, &channel_id, None)
+            .await?;
+        info!(
+            "[{}] clearing {} packets on channel {}/{}",
+            chain_name,
+            commitments.len(),
+            port_id,
+            channel_id
+        );
+        for (sequence, _) in commitments.into_iter() {
+            let packet = match store.packet(chain_name, &port_id, &channel_id, sequence)? {
+                Some(packet) => packet,
+                None => {
+                    info!(
+                        "[{}] can not clear unknown packet {}/{}/{}, rescan it with \
+                         `clear-packets --from-height`",
+                        chain_name, port_id, channel_id, sequence
+                    );
+                    continue;
+                }
+            };
+            clear_packet(
+                chain_name,
+                counterparty_name,
+                tx,
+                source_tx,
+                packet,
+                channel_end.ordering(),
+                block_hash,
+                height,
+                client,
+                counterparty_client,
+                counterparty_height,
+                counterparty_block_hash,
+                counterparty_timestamp,
+                store,
+            )
+            .await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Relay whichever message a packet with a commitment still on `client` is waiting for.
+async fn clear_packet(
+    chain_name: &str,
+    counterparty_name: &str,
+    tx: &Sender<pallet_ibc::Any>,
+    source_tx: &Sender<pallet_ibc::Any>,
+    packet: Packet,
+    order: &Order,
+    block_hash: H256,
+    block_number: u32,
+    client: &Client<Runtime>,
+    counterparty_client: &Client<Runtime>,
+    counterparty_height: u32,
+    counterparty_block_hash: H256,
+    counterparty_timestamp: u64,
+    store: &Store,
+) -> Result<(), Box<dyn Error>> {
+    let (port_id, channel_id) = (
+        packet.source_port.to_string(),
+        packet.source_channel.to_string(),
+    );
+    let (destination_port, destination_channel) = (
+        packet.destination_port.to_string(),
+        packet.destination_channel.to_string(),
+    );
+    let sequence = u64::from(packet.sequence);
+
+    // Checked at the counterparty's latest state rather than at the height known on `client`,
+    // so that a packet received since is not sent again.
+    let received = if order == &Order::Ordered {
+        let next_sequence_recv = counterparty_client
+            .query_next_sequence_recv(&destination_port, &destination_channel, None)
+            .await?
+            .ok_or("next sequence recv not found")?;
+        u64::decode(&mut &next_sequence_recv.value[..])? > sequence
+    } else {
+        !counterparty_client
+            .query_packet_receipt(&destination_port, &destination_channel, sequence, None)
+            .await?
+            .value
+            .is_empty()
+    };
+
+    if received {
+        if store.is_relayed(
+            counterparty_name,
+            Relayed::Ack,
+            &destination_port,
+            &destination_channel,
+            sequence,
+        )? {
+            return Ok(());
+        }
+        let acknowledgement = counterparty_client
+            .query_packet_acknowledgement(
+                &destination_port,
+                &destination_channel,
+                sequence,
+                Some(counterparty_block_hash),
+            )
+            .await?;
+        if acknowledgement.is_none() {
+            // Not acknowledged yet, or not at a height known on `client`.
+            return Ok(());
+        }
+        let ack = store.ack(
+            counterparty_name,
+            &destination_port,
+            &destination_channel,
+            sequence,
+        )?;
+        let ack = match ack {
+            Some(ack) => ack,
+            None => {
+                info!(
+                    "[{}] can not clear unknown acknowledgement {}/{}/{}, rescan it with \
+                     `clear-packets --from-height`",
+                    counterparty_name, destination_port, destination_channel, sequence
+                );
+                return Ok(());
+            }
+        };
+        info!(
+            "[{}] clearing acknowledgement {}/{}/{}",
+            counterparty_name, destination_port, destination_channel, sequence
+        );
+        packet::send_acknowledgement(
+            counterparty_name,
+            source_tx,
+            packet,
+            ack,
+            counterparty_block_hash,
+            counterparty_height,
+            counterparty_client,
+            store,
+        )
+        .await?;
+        return Ok(());
+    }
+
+    if packet::timed_out(&packet, counterparty_height, counterparty_timestamp) {
+        if store.is_relayed(
+            chain_name,
+            Relayed::Timeout,
+            &port_id,
+            &channel_id,
+            sequence,
+        )? {
+            return Ok(());
+        }
+        packet::relay_timeout(
+            chain_name,
+            source_tx,
+            packet,
+            block_hash,
+            client,
+            counterparty_client,
+            counterparty_height,
+            counterparty_block_hash,
+            store,
+        )
+        .await?;
+        return Ok(());
+    }
+
+    if store.is_relayed(chain_name, Relayed::Recv, &port_id, &channel_id, sequence)? {
+        return Ok(());
+    }
+    info!(
+        "[{}] clearing packet {}/{}/{}",
+        chain_name, port_id, channel_id, sequence
+    );
+    packet::send_recv_packet(
+        chain_name,
+        tx,
+        packet,
+        block_hash,
+        block_number,
+        client,
+        store,
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// Record the packets sent and acknowledgements written on `client` from `from_height` up to
+/// its finalized head, so that packets sent before the relayer first ran can be cleared.
+pub async fn rescan(
+    chain_name: &str,
+    counterparty_name: &str,
+    client: &Client<Runtime>,
+    from_height: u32,
+    store: &Store,
+) -> Result<(), Box<dyn Error>> {
+    let finalized_head = client.finalized_head().await?;
+    let to_height = client
+        .header(Some(finalized_head))
+        .await?
+        .ok_or("finalized header not found")?
+        .number;
+    info!(
+        "[{}] rescanning blocks {} to {}",
+        chain_name, from_height, to_height
+    );
+    for height in from_height..=to_height {
+        let hash = client
+            .block_hash(Some(BlockNumber::from(height)))
+            .await?
+            .ok_or("block not found")?;
+        for event in crate::ibc_events(client, hash).await?.into_iter() {
+            match event {
+                pallet_ibc::Event::SendPacket(_height, packet) => {
+                    store.insert_packet(chain_name, &packet.to_ibc_packet())?;
+                }
+                pallet_ibc::Event::WriteAcknowledgement(_height, packet, ack) => {
+                    store.insert_ack(chain_name, &packet.to_ibc_packet(), &ack)?;
+                }
+                pallet_ibc::Event::AcknowledgePacket(_height, packet)
+                | pallet_ibc::Event::TimeoutPacket(_height, packet)
+                | pallet_ibc::Event::TimeoutOnClosePacket(_height, packet) => {
+                    store.remove_packet(chain_name, counterparty_name, &packet.to_ibc_packet())?;
+                }
+                _ => {}
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// The latest height of the client `client_identifier` of `client_type` hosted on `client`.
+async fn client_height(
+    client: &Client<Runtime>,
+    client_identifier: &str,
+    client_type: &str,
+) -> Result<u32, Box<dyn Error>> {
+    let data = client
+        .query_client_state(client_identifier, None)
+        .await?
+        .ok_or("client state not found")?;
+    crate::client_latest_height(client_type, &data.value)
+}
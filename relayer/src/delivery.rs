@@ -0,0 +1,123 @@
+//! Delivery of the messages relayed to a chain, batched into as few `deliver` extrinsics as the
+//! configured limits allow.
+use crate::chain::Chain;
+use crate::metrics::METRICS;
+use crate::store::Store;
+use crate::submission::{RetryConfig, Submitter};
+use async_std::channel::Receiver;
+use codec::Encode;
+use serde_derive::Deserialize;
+use sp_core::sr25519;
+use std::collections::VecDeque;
+use std::error::Error;
+
+/// Limits on the messages packed into one `deliver` extrinsic.
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub struct BatchConfig {
+    /// The most messages in one extrinsic.
+    #[serde(default = "default_max_messages")]
+    pub max_messages: usize,
+    /// The most bytes of encoded messages in one extrinsic.
+    #[serde(default = "default_max_size")]
+    pub max_size: usize,
+    /// The most extrinsics submitted and not yet finalized.
+    #[serde(default = "default_max_in_flight")]
+    pub max_in_flight: usize,
+}
+
+fn default_max_messages() -> usize {
+    50
+}
+
+fn default_max_size() -> usize {
+    1024 * 1024
+}
+
+fn default_max_in_flight() -> usize {
+    4
+}
+
+impl Default for BatchConfig {
+    fn default() -> Self {
+        BatchConfig {
+            max_messages: default_max_messages(),
+            max_size: default_max_size(),
+            max_in_flight: default_max_in_flight(),
+        }
+    }
+}
+
+/// Submit the messages received on `rx` to `chain`, until all the senders are dropped.
+///
+/// Messages are taken in order, so an update-client goes out in the same extrinsic as the
+/// packet messages queued right after it and proven against it. The messages a previous run
+/// left pending go out first. While no message is queued, the extrinsics in flight are waited
+/// for, so that failed ones are delivered again without waiting for new messages.
+pub async fn deliver_messages(
+    chain: Chain,
+    pair: sr25519::Pair,
+    tmp: u8,
+    rx: Receiver<pallet_ibc::Any>,
+    store: Store,
+    batch_config: BatchConfig,
+    retry_config: RetryConfig,
+) -> Result<(), Box<dyn Error>> {
+    let chain_name = chain.name.clone();
+    let mut queue = VecDeque::from(store.take_pending(&chain_name)?);
+    let mut submitter = Submitter::new(
+        chain,
+        pair,
+        tmp,
+        store,
+        retry_config,
+        batch_config.max_in_flight,
+    )
+    .await?;
+    loop {
+        if queue.is_empty() && rx.is_empty() && submitter.has_in_flight() {
+            submitter.finish_oldest().await;
+            continue;
+        }
+        let batch = match next_batch(&rx, &mut queue, &batch_config).await {
+            Some(batch) => batch,
+            None => break,
+        };
+        METRICS
+            .queue_depth
+            .with_label_values(&[&chain_name])
+            .set((rx.len() + queue.len()) as u64);
+        submitter.submit(batch).await;
+    }
+    submitter.flush().await;
+    Ok(())
+}
+
+/// Wait for the next message, then add to it whatever else is already queued within
+/// `batch_config`. Returns `None` once all the senders are dropped and the queue is empty.
+async fn next_batch(
+    rx: &Receiver<pallet_ibc::Any>,
+    queue: &mut VecDeque<pallet_ibc::Any>,
+    batch_config: &BatchConfig,
+) -> Option<Vec<pallet_ibc::Any>> {
+    if queue.is_empty() {
+        queue.push_back(rx.recv().await.ok()?);
+    }
+    while let Ok(any) = rx.try_recv() {
+        queue.push_back(any);
+    }
+
+    // The first message goes out even if it is over the size limit on its own.
+    let first = queue.pop_front()?;
+    let mut size = first.encoded_size();
+    let mut batch = vec![first];
+    while batch.len() < batch_config.max_messages {
+        match queue.front() {
+            Some(any) if size + any.encoded_size() <= batch_config.max_size => {
+                size += any.encoded_size();
+                batch.extend(queue.pop_front());
+            }
+            _ => break,
+        }
+    }
+    Some(batch)
+}
@@ -0,0 +1,43 @@
+//! Per relay path filters on the channels relayed, so that several relayers can share a pair of
+//! chains without relaying the same channels.
+use serde_derive::Deserialize;
+
+#[cfg(test)]
+mod tests;
+
+/// A channel end on the chain a relay path is `from`, or all of a port's channels if
+/// `channel_id` is not given.
+#[derive(Clone, Debug, Deserialize)]
+pub struct ChannelRef {
+    pub port_id: String,
+    #[serde(default)]
+    pub channel_id: Option<String>,
+}
+
+impl ChannelRef {
+    fn matches(&self, port_id: &str, channel_id: &str) -> bool {
+        self.port_id == port_id && self.channel_id.as_ref().map_or(true, |id| id == channel_id)
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct ChannelFilter {
+    /// Only these channels are relayed, if given.
+    #[serde(default)]
+    pub allow: Option<Vec<ChannelRef>>,
+    /// These channels are not relayed, even if allowed.
+    #[serde(default)]
+    pub deny: Vec<ChannelRef>,
+}
+
+impl ChannelFilter {
+    /// Whether the channel end `(port_id, channel_id)` on the chain the path is `from` is
+    /// relayed.
+    pub fn allows(&self, port_id: &str, channel_id: &str) -> bool {
+        let allowed = match &self.allow {
+            Some(allow) => allow.iter().any(|c| c.matches(port_id, channel_id)),
+            None => true,
+        };
+        allowed && !self.deny.iter().any(|c| c.matches(port_id, channel_id))
+    }
+}
@@ -0,0 +1,75 @@
+use super::{ChannelFilter, ChannelRef};
+
+fn channel(port_id: &str, channel_id: &str) -> ChannelRef {
+    ChannelRef {
+        port_id: port_id.to_string(),
+        channel_id: Some(channel_id.to_string()),
+    }
+}
+
+fn port(port_id: &str) -> ChannelRef {
+    ChannelRef {
+        port_id: port_id.to_string(),
+        channel_id: None,
+    }
+}
+
+#[test]
+fn allows_every_channel_by_default() {
+    let filter = ChannelFilter::default();
+    assert!(filter.allows("transfer", "channel-0"));
+    assert!(filter.allows("bank", "channel-7"));
+}
+
+#[test]
+fn allows_only_the_channels_allowed() {
+    let filter = ChannelFilter {
+        allow: Some(vec![channel("transfer", "channel-0")]),
+        deny: vec![],
+    };
+    assert!(filter.allows("transfer", "channel-0"));
+    assert!(!filter.allows("transfer", "channel-1"));
+    assert!(!filter.allows("bank", "channel-0"));
+}
+
+#[test]
+fn allows_every_channel_of_a_port_allowed() {
+    let filter = ChannelFilter {
+        allow: Some(vec![port("transfer")]),
+        deny: vec![],
+    };
+    assert!(filter.allows("transfer", "channel-0"));
+    assert!(filter.allows("transfer", "channel-1"));
+    assert!(!filter.allows("bank", "channel-0"));
+}
+
+#[test]
+fn allows_no_channel_with_an_empty_allow_list() {
+    let filter = ChannelFilter {
+        allow: Some(vec![]),
+        deny: vec![],
+    };
+    assert!(!filter.allows("transfer", "channel-0"));
+}
+
+#[test]
+fn denies_the_channels_denied() {
+    let filter = ChannelFilter {
+        allow: None,
+        deny: vec![channel("transfer", "channel-1"), port("bank")],
+    };
+    assert!(filter.allows("transfer", "channel-0"));
+    assert!(!filter.allows("transfer", "channel-1"));
+    assert!(!filter.allows("bank", "channel-0"));
+    assert!(!filter.allows("bank", "channel-1"));
+}
+
+#[test]
+fn denies_a_channel_both_allowed_and_denied() {
+    let filter = ChannelFilter {
+        allow: Some(vec![port("transfer")]),
+        deny: vec![channel("transfer", "channel-1")],
+    };
+    assert!(filter.allows("transfer", "channel-0"));
+    assert!(!filter.allows("transfer", "channel-1"));
+}
@@ -0,0 +1,485 @@
+//! ICS-003 connection and ICS-004 channel handshake relaying, including channel closing.
+use crate::filter::ChannelFilter;
+use crate::get_dummy_account_id;
+use crate::packet;
+use crate::store::{Handshake, Store};
+use async_std::channel::Sender;
+use calls::{
+    ibc::{IbcRpcExt, StateResponse},
+    NodeRuntime as Runtime,
+};
+use ibc::ics02_client::client_state::AnyClientState;
+use ibc::ics02_client::height::Height;
+use ibc::ics03_connection::connection::{
+    ConnectionEnd, Counterparty as ConnectionCounterparty, State as ConnectionState,
+};
+use ibc::ics03_connection::msgs::{
+    conn_open_ack::{self, MsgConnectionOpenAck},
+    conn_open_confirm::{self, MsgConnectionOpenConfirm},
+    conn_open_try::{self, MsgConnectionOpenTry},
+};
+use ibc::ics04_channel::channel::{
+    ChannelEnd, Counterparty as ChannelCounterparty, State as ChannelState,
+};
+use ibc::ics04_channel::msgs::{
+    chan_close_confirm::{self, MsgChannelCloseConfirm},
+    chan_open_ack::{self, MsgChannelOpenAck},
+    chan_open_confirm::{self, MsgChannelOpenConfirm},
+    chan_open_try::{self, MsgChannelOpenTry},
+};
+use ibc::ics24_host::identifier::{ChannelId, ClientId, ConnectionId, PortId};
+use ibc::proofs::{ConsensusProof, Proofs};
+use ibc::signer::Signer;
+use log::{debug, info};
+use sp_core::H256;
+use std::error::Error;
+use std::str::FromStr;
+use substrate_subxt::Client;
+use tendermint_proto::Protobuf;
+
+/// The light client on `client` that tracks the counterparty, with its proof at the relayed
+/// block.
+pub struct ClientProof {
+    pub client_id: ClientId,
+    pub client_state: AnyClientState,
+    pub proof: StateResponse<H256>,
+    /// The latest counterparty height known to the client.
+    pub latest_height: u32,
+}
+
+/// Advance the handshake of every connection on `client` built on `client_proof.client_id`.
+///
+/// Each step is sent once per connection, as marked in `store`: the end on `client` stays in the
+/// same state until the counterparty's answer is relayed back, and a step sent again would open
+/// another connection on the counterparty, or fail.
+pub async fn relay_connections(
+    chain_name: &str,
+    tx: &Sender<pallet_ibc::Any>,
+    block_hash: H256,
+    block_number: u32,
+    client: &Client<Runtime>,
+    client_identifier: &str,
+    client_proof: &ClientProof,
+    counterparty_client: &Client<Runtime>,
+    store: &Store,
+) -> Result<(), Box<dyn Error>> {
+    let connections = client.query_connections(Some(block_hash)).await?;
+    for (connection_id, connection) in connections.into_iter() {
+        let connection_end = ConnectionEnd::decode_vec(&connection.value)?;
+        if connection_end.client_id() != &client_proof.client_id {
+            continue;
+        }
+        debug!("[{}] connection_end: {:?}", chain_name, connection_end);
+
+        let counterparty = connection_end.counterparty();
+        let remote_connection_end = match counterparty.connection_id() {
+            Some(counterparty_connection_id) => counterparty_client
+                .query_connection(&counterparty_connection_id.to_string(), None)
+                .await?
+                .map(|data| ConnectionEnd::decode_vec(&data.value))
+                .transpose()?,
+            None => None,
+        };
+        let remote_state = remote_connection_end
+            .as_ref()
+            .map(|connection_end| connection_end.state().clone())
+            .unwrap_or(ConnectionState::Uninitialized);
+        info!(
+            "[{}] connection {} state: {:?}, counterparty connection state: {:?}",
+            chain_name,
+            connection_id,
+            connection_end.state(),
+            remote_state
+        );
+
+        let proof_height = Height::new(0, block_number as u64);
+        let signer = Signer::new(get_dummy_account_id().to_string());
+        match (connection_end.state(), &remote_state) {
+            (ConnectionState::Init, ConnectionState::Uninitialized) => {
+                if already_relayed(chain_name, store, Handshake::ConnOpenTry, &connection_id)? {
+                    continue;
+                }
+                let consensus_proof = consensus_proof(client, client_proof, block_hash).await?;
+                let msg = MsgConnectionOpenTry {
+                    previous_connection_id: None,
+                    client_id: ClientId::from_str(client_identifier)?,
+                    client_state: Some(client_proof.client_state.clone()),
+                    // Both demo chains use the same commitment prefix, so the one recorded for
+                    // the counterparty is ours as well.
+                    counterparty: ConnectionCounterparty::new(
+                        connection_end.client_id().clone(),
+                        Some(ConnectionId::from_str(&connection_id)?),
+                        counterparty.prefix().clone(),
+                    ),
+                    counterparty_versions: connection_end.versions(),
+                    proofs: Proofs::new(
+                        connection.encoded_proof().into(),
+                        Some(client_proof.proof.encoded_proof().into()),
+                        Some(consensus_proof),
+                        None,
+                        proof_height,
+                    )?,
+                    delay_period: connection_end.delay_period(),
+                    signer,
+                };
+                debug!("[{}] msg: {:?}", chain_name, msg);
+                let any = pallet_ibc::Any {
+                    type_url: conn_open_try::TYPE_URL.to_string(),
+                    value: msg.encode_vec().unwrap(),
+                };
+                send_handshake(
+                    chain_name,
+                    tx,
+                    Handshake::ConnOpenTry,
+                    &connection_id,
+                    any,
+                    store,
+                )
+                .await?;
+            }
+            (ConnectionState::TryOpen, ConnectionState::Init) => {
+                if already_relayed(chain_name, store, Handshake::ConnOpenAck, &connection_id)? {
+                    continue;
+                }
+                let consensus_proof = consensus_proof(client, client_proof, block_hash).await?;
+                let msg = MsgConnectionOpenAck {
+                    connection_id: counterparty
+                        .connection_id()
+                        .cloned()
+                        .ok_or("counterparty connection id not set")?,
+                    counterparty_connection_id: ConnectionId::from_str(&connection_id)?,
+                    client_state: Some(client_proof.client_state.clone()),
+                    proofs: Proofs::new(
+                        connection.encoded_proof().into(),
+                        Some(client_proof.proof.encoded_proof().into()),
+                        Some(consensus_proof),
+                        None,
+                        proof_height,
+                    )?,
+                    version: connection_end
+                        .versions()
+                        .first()
+                        .cloned()
+                        .ok_or("connection has no version")?,
+                    signer,
+                };
+                debug!("[{}] msg: {:?}", chain_name, msg);
+                let any = pallet_ibc::Any {
+                    type_url: conn_open_ack::TYPE_URL.to_string(),
+                    value: msg.encode_vec().unwrap(),
+                };
+                send_handshake(
+                    chain_name,
+                    tx,
+                    Handshake::ConnOpenAck,
+                    &connection_id,
+                    any,
+                    store,
+                )
+                .await?;
+            }
+            (ConnectionState::Open, ConnectionState::TryOpen) => {
+                if already_relayed(
+                    chain_name,
+                    store,
+                    Handshake::ConnOpenConfirm,
+                    &connection_id,
+                )? {
+                    continue;
+                }
+                let msg = MsgConnectionOpenConfirm {
+                    connection_id: counterparty
+                        .connection_id()
+                        .cloned()
+                        .ok_or("counterparty connection id not set")?,
+                    proofs: Proofs::new(
+                        connection.encoded_proof().into(),
+                        None,
+                        None,
+                        None,
+                        proof_height,
+                    )?,
+                    signer,
+                };
+                debug!("[{}] msg: {:?}", chain_name, msg);
+                let any = pallet_ibc::Any {
+                    type_url: conn_open_confirm::TYPE_URL.to_string(),
+                    value: msg.encode_vec().unwrap(),
+                };
+                send_handshake(
+                    chain_name,
+                    tx,
+                    Handshake::ConnOpenConfirm,
+                    &connection_id,
+                    any,
+                    store,
+                )
+                .await?;
+            }
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
+/// Advance the opening or closing handshake of every channel on `client` `filter` allows, each
+/// step once per channel as for connections.
+///
+/// Without `handshakes`, only the packets in flight on a closed channel are timed out.
+pub async fn relay_channels(
+    chain_name: &str,
+    counterparty_name: &str,
+    tx: &Sender<pallet_ibc::Any>,
+    block_hash: H256,
+    block_number: u32,
+    client: &Client<Runtime>,
+    counterparty_client: &Client<Runtime>,
+    filter: &ChannelFilter,
+    handshakes: bool,
+    store: &Store,
+) -> Result<(), Box<dyn Error>> {
+    let channels = client.query_channels(Some(block_hash)).await?;
+    for (port_id, channel_id, channel) in channels.into_iter() {
+        if !filter.allows(&port_id, &channel_id) {
+            continue;
+        }
+        let channel_end = ChannelEnd::decode_vec(&channel.value)?;
+        debug!("[{}] channel_end: {:?}", chain_name, channel_end);
+        let end = format!("{}/{}", port_id, channel_id);
+
+        let counterparty = channel_end.counterparty();
+        let remote_channel_end = match counterparty.channel_id() {
+            Some(counterparty_channel_id) => counterparty_client
+                .query_channel(
+                    counterparty.port_id().as_str(),
+                    &counterparty_channel_id.to_string(),
+                    None,
+                )
+                .await?
+                .map(|data| ChannelEnd::decode_vec(&data.value))
+                .transpose()?,
+            None => None,
+        };
+        let remote_state = remote_channel_end
+            .as_ref()
+            .map(|channel_end| channel_end.state().clone())
+            .unwrap_or(ChannelState::Uninitialized);
+        info!(
+            "[{}] channel {}/{} state: {:?}, counterparty channel state: {:?}",
+            chain_name,
+            port_id,
+            channel_id,
+            channel_end.state(),
+            remote_state
+        );
+
+        let proofs = Proofs::new(
+            channel.encoded_proof().into(),
+            None,
+            None,
+            None,
+            Height::new(0, block_number as u64),
+        )?;
+        let signer = Signer::new(get_dummy_account_id().to_string());
+        match (channel_end.state(), &remote_state) {
+            (ChannelState::Init, ChannelState::Uninitialized) if handshakes => {
+                if already_relayed(chain_name, store, Handshake::ChanOpenTry, &end)? {
+                    continue;
+                }
+                let connection_id = channel_end
+                    .connection_hops()
+                    .first()
+                    .ok_or("channel has no connection hop")?;
+                let connection_end = client
+                    .query_connection(connection_id.as_str(), Some(block_hash))
+                    .await?
+                    .map(|data| ConnectionEnd::decode_vec(&data.value))
+                    .transpose()?
+                    .ok_or("connection not found")?;
+                let counterparty_connection_id = connection_end
+                    .counterparty()
+                    .connection_id()
+                    .cloned()
+                    .ok_or("connection is not open")?;
+                let msg = MsgChannelOpenTry {
+                    port_id: counterparty.port_id().clone(),
+                    previous_channel_id: None,
+                    channel: ChannelEnd::new(
+                        ChannelState::TryOpen,
+                        channel_end.ordering().clone(),
+                        ChannelCounterparty::new(
+                            PortId::from_str(&port_id)?,
+                            Some(ChannelId::from_str(&channel_id)?),
+                        ),
+                        vec![counterparty_connection_id],
+                        channel_end.version(),
+                    ),
+                    counterparty_version: channel_end.version(),
+                    proofs,
+                    signer,
+                };
+                debug!("[{}] msg: {:?}", chain_name, msg);
+                let any = pallet_ibc::Any {
+                    type_url: chan_open_try::TYPE_URL.to_string(),
+                    value: msg.encode_vec().unwrap(),
+                };
+                send_handshake(chain_name, tx, Handshake::ChanOpenTry, &end, any, store).await?;
+            }
+            (ChannelState::TryOpen, ChannelState::Init) if handshakes => {
+                if already_relayed(chain_name, store, Handshake::ChanOpenAck, &end)? {
+                    continue;
+                }
+                let msg = MsgChannelOpenAck {
+                    port_id: counterparty.port_id().clone(),
+                    channel_id: counterparty
+                        .channel_id()
+                        .cloned()
+                        .ok_or("counterparty channel id not set")?,
+                    counterparty_channel_id: ChannelId::from_str(&channel_id)?,
+                    counterparty_version: channel_end.version(),
+                    proofs,
+                    signer,
+                };
+                debug!("[{}] msg: {:?}", chain_name, msg);
+                let any = pallet_ibc::Any {
+                    type_url: chan_open_ack::TYPE_URL.to_string(),
+                    value: msg.encode_vec().unwrap(),
+                };
+                send_handshake(chain_name, tx, Handshake::ChanOpenAck, &end, any, store).await?;
+            }
+            (ChannelState::Open, ChannelState::TryOpen) if handshakes => {
+                if already_relayed(chain_name, store, Handshake::ChanOpenConfirm, &end)? {
+                    continue;
+                }
+                let msg = MsgChannelOpenConfirm {
+                    port_id: counterparty.port_id().clone(),
+                    channel_id: counterparty
+                        .channel_id()
+                        .cloned()
+                        .ok_or("counterparty channel id not set")?,
+                    proofs,
+                    signer,
+                };
+                debug!("[{}] msg: {:?}", chain_name, msg);
+                let any = pallet_ibc::Any {
+                    type_url: chan_open_confirm::TYPE_URL.to_string(),
+                    value: msg.encode_vec().unwrap(),
+                };
+                send_handshake(chain_name, tx, Handshake::ChanOpenConfirm, &end, any, store)
+                    .await?;
+            }
+            (ChannelState::Closed, ChannelState::Open) => {
+                let counterparty_channel_id = counterparty
+                    .channel_id()
+                    .cloned()
+                    .ok_or("counterparty channel id not set")?;
+                // Packets still in flight towards a closed channel can never be received, so
+                // they are timed out before the counterparty closes as well.
+                packet::relay_timeouts_on_close(
+                    chain_name,
+                    counterparty_name,
+                    tx,
+                    &port_id,
+                    &channel_id,
+                    &channel,
+                    channel_end.ordering(),
+                    counterparty.port_id().as_str(),
+                    counterparty_channel_id.as_str(),
+                    block_hash,
+                    block_number,
+                    client,
+                    counterparty_client,
+                    store,
+                )
+                .await?;
+                if !handshakes {
+                    continue;
+                }
+                if already_relayed(chain_name, store, Handshake::ChanCloseConfirm, &end)? {
+                    continue;
+                }
+                let msg = MsgChannelCloseConfirm {
+                    port_id: counterparty.port_id().clone(),
+                    channel_id: counterparty_channel_id,
+                    proofs,
+                    signer,
+                };
+                debug!("[{}] msg: {:?}", chain_name, msg);
+                let any = pallet_ibc::Any {
+                    type_url: chan_close_confirm::TYPE_URL.to_string(),
+                    value: msg.encode_vec().unwrap(),
+                };
+                send_handshake(
+                    chain_name,
+                    tx,
+                    Handshake::ChanCloseConfirm,
+                    &end,
+                    any,
+                    store,
+                )
+                .await?;
+            }
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
+/// Whether `handshake` was already relayed, or is in flight, for the end `end` on `chain_name`.
+fn already_relayed(
+    chain_name: &str,
+    store: &Store,
+    handshake: Handshake,
+    end: &str,
+) -> Result<bool, Box<dyn Error>> {
+    let relayed = store.is_handshake_relayed(chain_name, handshake, end)?;
+    if relayed {
+        debug!(
+            "[{}] {:?} of {} already relayed",
+            chain_name, handshake, end
+        );
+    }
+    Ok(relayed)
+}
+
+/// Send `any`, relaying `handshake` for the end `end`, with its mark in `store` in flight until
+/// the delivery task settles it.
+async fn send_handshake(
+    chain_name: &str,
+    tx: &Sender<pallet_ibc::Any>,
+    handshake: Handshake,
+    end: &str,
+    any: pallet_ibc::Any,
+    store: &Store,
+) -> Result<(), Box<dyn Error>> {
+    store.set_handshake_relayed(chain_name, handshake, end, &any)?;
+    if let Err(e) = tx.send(any.clone()).await {
+        store.settle(&any, false)?;
+        return Err(e.into());
+    }
+    Ok(())
+}
+
+/// Prove the consensus state the light client on `client` stores for its latest height, so the
+/// counterparty can check that we track it correctly.
+async fn consensus_proof(
+    client: &Client<Runtime>,
+    client_proof: &ClientProof,
+    block_hash: H256,
+) -> Result<ConsensusProof, Box<dyn Error>> {
+    let consensus_height = Height::new(0, client_proof.latest_height as u64);
+    let consensus_state = client
+        .query_consensus_state(
+            client_proof.client_id.as_str(),
+            consensus_height.encode_vec().unwrap(),
+            Some(block_hash),
+        )
+        .await?
+        .ok_or("consensus state not found")?;
+    Ok(ConsensusProof::new(
+        consensus_state.encoded_proof().into(),
+        consensus_height,
+    )?)
+}
@@ -1,38 +1,48 @@
+mod chain;
+mod clear;
+mod delivery;
+mod filter;
+mod handshake;
+mod metrics;
+mod misbehaviour;
+mod packet;
+mod store;
+mod submission;
+mod supervisor;
+mod update_client;
+
+use crate::chain::Chain;
+use crate::delivery::BatchConfig;
+use crate::filter::ChannelFilter;
+use crate::metrics::{TelemetryConfig, METRICS};
+use crate::store::Store;
+use crate::submission::RetryConfig;
+use crate::update_client::{ClientUpdate, ClientUpdateConfig, UpdateMode};
+use async_std::channel::{self, Sender};
+use async_std::task::JoinHandle;
 use calls::{
-    ibc::{
-        AcknowledgementsStore, ChannelsStore, ChannelsStoreExt, ClientStatesStoreExt,
-        ClientStatesV2StoreExt, ConnectionsStore, ConnectionsStoreExt, ConsensusStatesStore,
-        DeliverCallExt, PacketsStore, SubmitDatagramCallExt,
-    },
+    config::{self, ChainConfig, GRANDPA_CLIENT_TYPE},
+    grandpa::GrandpaRpcExt,
+    ibc::IbcRpcExt,
     NodeRuntime as Runtime,
 };
-use clap::{App, Arg, ArgMatches};
+use clap::{App, Arg, ArgMatches, SubCommand};
 use codec::Decode;
-use ibc::ics02_client::client_def::AnyHeader;
-use ibc::ics02_client::client_def::{AnyClientState, AnyConsensusState};
-use ibc::ics02_client::msgs::update_client::{self, MsgUpdateAnyClient};
-use ibc::ics10_grandpa::header::Header as GRANDPAHeader;
+use ibc::ics02_client::client_state::AnyClientState;
 use ibc::ics24_host::identifier::ClientId;
-use log::{debug, error, info};
-use pallet_ibc::{grandpa::header::Header, ChannelState, ConnectionState, Datagram, Packet};
+use log::{debug, error, info, warn};
 use serde_derive::Deserialize;
 use sp_core::{storage::StorageKey, twox_128, H256};
-use sp_finality_grandpa::GRANDPA_AUTHORITIES_KEY;
-use sp_keyring::AccountKeyring;
 use sp_runtime::generic;
 use sp_storage::StorageChangeSet;
-use sp_trie::StorageProof;
 use std::collections::HashMap;
 use std::error::Error;
 use std::fs::File;
 use std::io::Read;
+use std::path::Path;
 use std::str::FromStr;
-use std::sync::mpsc::{channel, Sender};
-use std::time::Duration;
-use substrate_subxt::{
-    system::{AccountStoreExt, System},
-    BlockNumber, Client, ClientBuilder, PairSigner, Store,
-};
+use std::time::{Duration, Instant};
+use substrate_subxt::{system::System, BlockNumber, Client};
 use tendermint::account::Id as AccountId;
 use tendermint_proto::Protobuf;
 
@@ -40,32 +50,99 @@ use tendermint_proto::Protobuf;
 struct Config {
     chains: HashMap<String, ChainConfig>,
     relay: Vec<RelayConfig>,
+    /// Where the relayer keeps its state across restarts.
+    #[serde(default = "default_data_dir")]
+    data_dir: String,
+    /// How often, in seconds, packets missed by the event-driven relaying are cleared; `0` only
+    /// clears them at startup.
+    #[serde(default = "default_clear_interval")]
+    clear_interval: u64,
+    /// Limits on the messages batched into one extrinsic.
+    #[serde(default)]
+    batch: BatchConfig,
+    /// How failed submissions are retried.
+    #[serde(default)]
+    retry: RetryConfig,
+    /// How many messages can wait for a delivery task before relaying to its chain waits.
+    #[serde(default = "default_channel_capacity")]
+    channel_capacity: usize,
+    /// How long, in seconds, a chain can go without a finalized block before the relayer
+    /// resubscribes, reconnecting if needed.
+    #[serde(default = "default_subscription_timeout")]
+    subscription_timeout: u64,
+    /// How light clients are kept up to date.
+    #[serde(default)]
+    client_update: ClientUpdateConfig,
+    /// Serves Prometheus metrics if given.
+    telemetry: Option<TelemetryConfig>,
 }
 
-#[derive(Debug, Deserialize)]
-struct ChainConfig {
-    endpoint: String,
-    client_identifier: String,
+fn default_data_dir() -> String {
+    "relayer-data".to_string()
+}
+
+fn default_clear_interval() -> u64 {
+    600
+}
+
+fn default_channel_capacity() -> usize {
+    1024
+}
+
+fn default_subscription_timeout() -> u64 {
+    60
 }
 
 #[derive(Debug, Deserialize)]
 struct RelayConfig {
     from: String,
     to: String,
+    /// Which channels of `from` are relayed, by `allow` and `deny` lists.
+    #[serde(flatten)]
+    filter: ChannelFilter,
+    /// Whether to advance connection and channel handshakes, or only relay packets.
+    #[serde(default = "default_handshakes")]
+    handshakes: bool,
+}
+
+fn default_handshakes() -> bool {
+    true
 }
 
 type EventRecords = Vec<frame_system::EventRecord<node_runtime::Event, <Runtime as System>::Hash>>;
 
-fn execute(matches: ArgMatches) {
+fn execute(matches: ArgMatches) -> Result<(), Box<dyn Error>> {
     let file_path = matches.value_of("config").unwrap();
-    let mut file = File::open(file_path).expect("config.toml not found");
+    let mut file =
+        File::open(file_path).map_err(|e| format!("can not open {}: {}", file_path, e))?;
     let mut contents = String::new();
     file.read_to_string(&mut contents)
-        .expect("can not read config.toml");
-    let config: Config = toml::from_str(&contents).expect("can not parse config.toml");
-    println!("config: {:#?}", config);
-    let result = async_std::task::block_on(run(&config));
-    println!("run: {:?}", result);
+        .map_err(|e| format!("can not read {}: {}", file_path, e))?;
+    let config: Config = config::parse(Path::new(file_path), &contents)?;
+    debug!("config: {:#?}", config);
+    match matches.subcommand() {
+        ("clear-packets", Some(matches)) => {
+            let chain = matches.value_of("chain").unwrap();
+            let port_id = matches.value_of("port").unwrap();
+            let channel_id = matches.value_of("channel").unwrap();
+            let from_height = match matches.value_of("from-height") {
+                Some(height) => Some(
+                    height
+                        .parse()
+                        .map_err(|e| format!("invalid --from-height {}: {}", height, e))?,
+                ),
+                None => None,
+            };
+            async_std::task::block_on(clear_packets(
+                &config,
+                chain,
+                port_id,
+                channel_id,
+                from_height,
+            ))
+        }
+        _ => async_std::task::block_on(run(&config)),
+    }
 }
 
 fn print_usage(matches: &ArgMatches) {
@@ -87,89 +164,453 @@ fn main() {
                 .takes_value(true)
                 .required(true),
         )
+        .subcommand(
+            SubCommand::with_name("clear-packets")
+                .about("Relays the packets and acknowledgements of a channel once, then exits")
+                .arg(
+                    Arg::with_name("chain")
+                        .long("chain")
+                        .value_name("CHAIN")
+                        .help("The chain the packets were sent on")
+                        .takes_value(true)
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("port")
+                        .long("port")
+                        .value_name("PORT")
+                        .help("The port of the channel on that chain")
+                        .takes_value(true)
+                        .default_value("transfer"),
+                )
+                .arg(
+                    Arg::with_name("channel")
+                        .long("channel")
+                        .value_name("CHANNEL")
+                        .help("The channel on that chain")
+                        .takes_value(true)
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("from-height")
+                        .long("from-height")
+                        .value_name("HEIGHT")
+                        .help(
+                            "First rescans both chains from this height for packets and \
+                             acknowledgements the relayer has not seen",
+                        )
+                        .takes_value(true),
+                ),
+        )
         .get_matches();
-    execute(matches);
+    if let Err(e) = execute(matches) {
+        eprintln!("relayer failed: {}", e);
+        std::process::exit(1);
+    }
 }
 
-async fn run(config: &Config) -> Result<(), Box<dyn Error>> {
-    async_std::task::block_on(async {
-        for task in &config.relay {
-            println!("task: {:?}", task);
-            let from = task.from.clone();
-            let from_endpoint = &config.chains[&from].endpoint;
-            let from_client_identifier = config.chains[&from].client_identifier.clone();
-
-            let to = task.to.clone();
-            let to_endpoint = &config.chains[&to].endpoint;
-            let to_client_identifier = config.chains[&to].client_identifier.clone();
-
-            let from_client = ClientBuilder::<Runtime>::new()
-                .set_url(from_endpoint)
-                .build()
-                .await?;
-            let to_client = ClientBuilder::<Runtime>::new()
-                .set_url(to_endpoint)
-                .build()
-                .await?;
+/// The connections to every chain, the senders to their delivery tasks and the tasks themselves.
+type Connections = (
+    HashMap<String, Chain>,
+    HashMap<String, Sender<pallet_ibc::Any>>,
+    Vec<JoinHandle<()>>,
+);
 
-            // subscribe_finalized_blocks is equivalent to queryHeader
-            let mut from_block_headers = from_client.subscribe_finalized_blocks().await?;
+/// Connect to every chain and spawn one supervised delivery task per chain, so that every relay
+/// path can send messages to either end.
+///
+/// A delivery task exits once all the senders to it are dropped and its queue is drained.
+async fn connect(config: &Config, store: &Store) -> Result<Connections, Box<dyn Error>> {
+    let mut chains = HashMap::new();
+    let mut senders = HashMap::new();
+    let mut handles = Vec::new();
+    for (name, chain) in &config.chains {
+        if chain.client_type != GRANDPA_CLIENT_TYPE {
+            return Err(format!("[{}] unsupported client type {}", name, chain.client_type).into());
+        }
+        let connection = Chain::connect(name.clone(), chain.endpoints.clone()).await?;
+        let (tx, rx) = channel::bounded::<pallet_ibc::Any>(config.channel_capacity);
+        let pair = chain
+            .signer
+            .pair()
+            .map_err(|e| format!("[{}] can not load signer: {}", name, e))?;
 
-            let (tx, rx) = channel();
+        let (to_chain, tmp, to_store) = (connection.clone(), chain.tmp, store.clone());
+        let (batch, retry) = (config.batch, config.retry);
+        handles.push(supervisor::spawn(
+            format!("relayer => {}", name),
+            move || {
+                delivery::deliver_messages(
+                    to_chain.clone(),
+                    pair.clone(),
+                    tmp,
+                    rx.clone(),
+                    to_store.clone(),
+                    batch,
+                    retry,
+                )
+            },
+            None,
+        ));
 
-            let from_client = from_client.clone();
-            {
-                let to_client = to_client.clone();
-                async_std::task::spawn(async move {
-                    loop {
-                        let block_header = from_block_headers.next().await;
-                        let tx = tx.clone();
-                        if let Err(e) = relay(
-                            &from,
-                            tx,
-                            block_header,
-                            &from_client,
-                            from_client_identifier.clone(),
-                            &to_client,
-                            to_client_identifier.clone(),
-                        )
-                        .await
-                        {
-                            error!("[{}] failed to relay; error = {}", from.clone(), e);
-                        }
-                    }
-                });
-            }
+        chains.insert(name.clone(), connection);
+        senders.insert(name.clone(), tx);
+    }
+    Ok((chains, senders, handles))
+}
+
+/// One `[[relay]]` path, from the chain whose events are relayed to its counterparty.
+#[derive(Clone)]
+struct Path {
+    from: String,
+    from_chain: Chain,
+    from_client_identifier: String,
+    from_tx: Sender<pallet_ibc::Any>,
+    to: String,
+    to_chain: Chain,
+    to_client_identifier: String,
+    to_tx: Sender<pallet_ibc::Any>,
+    filter: ChannelFilter,
+    handshakes: bool,
+    /// The type of the light client of `from` on `to`, as configured for `from`.
+    from_client_type: String,
+    /// The type of the light client of `to` on `from`, as configured for `to`.
+    to_client_type: String,
+    store: Store,
+}
 
-            async_std::task::spawn(async move {
-                let mut signer = PairSigner::new(AccountKeyring::Alice.pair());
-                let nonce = to_client
-                    .account(&AccountKeyring::Alice.to_account_id(), None)
-                    .await
-                    .unwrap()
-                    .nonce;
-                signer.set_nonce(nonce);
-                loop {
-                    let any = rx.recv().unwrap();
-                    debug!("[relayer => {}] msg: {:?}", to.clone(), any);
-                    if let Err(e) = to_client
-                        .deliver(&signer, vec![any], if to == "appia" { 0 } else { 1 })
-                        .await
-                    {
-                        error!(
-                            "[relayer => {}] failed to send msg; error = {}",
-                            to.clone(),
-                            e
+/// Relay the finalized blocks of `path.from` as they come.
+///
+/// A subscription that yields no block for `subscription_timeout` seconds is taken as dropped:
+/// the connection is checked, and the subscription made again. The blocks finalized in between
+/// are backfilled by `relay` from the last height processed.
+///
+/// With `on_demand` client updates, the counterparty's light client is still updated with the
+/// block relayed once every `refresh_interval` seconds.
+async fn follow(
+    path: Path,
+    subscription_timeout: u64,
+    client_update: ClientUpdateConfig,
+) -> Result<(), Box<dyn Error>> {
+    let refresh_interval = Duration::from_secs(client_update.refresh_interval);
+    let mut last_update: Option<Instant> = None;
+    loop {
+        // subscribe_finalized_blocks is equivalent to queryHeader
+        let mut from_block_headers = match path
+            .from_chain
+            .client()
+            .await
+            .subscribe_finalized_blocks()
+            .await
+        {
+            Ok(subscription) => subscription,
+            Err(e) => {
+                warn!("[{}] failed to subscribe; error = {}", path.from, e);
+                path.from_chain.ensure_connected().await?;
+                continue;
+            }
+        };
+        loop {
+            let timeout = Duration::from_secs(subscription_timeout);
+            let block_header =
+                match async_std::future::timeout(timeout, from_block_headers.next()).await {
+                    Ok(block_header) => block_header,
+                    Err(_) => {
+                        warn!(
+                            "[{}] no finalized block for {:?}, resubscribing",
+                            path.from, timeout
                         );
+                        path.from_chain.ensure_connected().await?;
+                        break;
                     }
-                    signer.increment_nonce();
+                };
+            let (from_client, to_client) =
+                (path.from_chain.client().await, path.to_chain.client().await);
+            let refresh = client_update.refresh_interval != 0
+                && last_update.map_or(true, |at| at.elapsed() >= refresh_interval);
+            match relay(
+                &path.from,
+                &path.to,
+                path.to_tx.clone(),
+                path.from_tx.clone(),
+                block_header,
+                &from_client,
+                path.from_client_identifier.clone(),
+                &to_client,
+                path.to_client_identifier.clone(),
+                &path.from_client_type,
+                &path.to_client_type,
+                &path.filter,
+                path.handshakes,
+                if client_update.on_demand && !refresh {
+                    UpdateMode::OnDemand
+                } else {
+                    UpdateMode::Eager
+                },
+                &path.store,
+            )
+            .await
+            {
+                Ok(true) => last_update = Some(Instant::now()),
+                Ok(false) => {}
+                Err(e) => {
+                    error!("[{}] failed to relay; error = {}", path.from, e);
+                    path.to_chain.ensure_connected().await?;
                 }
-            });
+            }
         }
-        loop {
-            async_std::task::sleep(Duration::from_secs(60 * 60)).await;
+    }
+}
+
+/// Clear what was missed while the relayer was offline, then keep clearing what the
+/// event-driven relaying misses every `clear_interval` seconds, if not `0`.
+async fn clear_periodically(path: Path, clear_interval: u64) -> Result<(), Box<dyn Error>> {
+    loop {
+        let (from_client, to_client) =
+            (path.from_chain.client().await, path.to_chain.client().await);
+        if let Err(e) = clear::clear_packets(
+            &path.from,
+            &path.to,
+            &path.to_tx,
+            &path.from_tx,
+            &from_client,
+            &path.from_client_identifier,
+            &to_client,
+            &path.to_client_identifier,
+            &path.from_client_type,
+            &path.to_client_type,
+            None,
+            &path.filter,
+            &path.store,
+        )
+        .await
+        {
+            error!("[{}] failed to clear packets; error = {}", path.from, e);
+            path.from_chain.ensure_connected().await?;
+            path.to_chain.ensure_connected().await?;
+        }
+        if clear_interval == 0 {
+            return Ok(());
+        }
+        async_std::task::sleep(Duration::from_secs(clear_interval)).await;
+    }
+}
+
+/// Check every header the light client of `path.from` on `path.to` accepts against the
+/// finalized chain of `path.from`, as `path.to` finalizes the updates.
+///
+/// The blocks of `path.to` finalized while the subscription was down, or the relayer stopped,
+/// are backfilled from the last height checked.
+async fn monitor_misbehaviour(path: Path, subscription_timeout: u64) -> Result<(), Box<dyn Error>> {
+    loop {
+        let mut to_block_headers = match path
+            .to_chain
+            .client()
+            .await
+            .subscribe_finalized_blocks()
+            .await
+        {
+            Ok(subscription) => subscription,
+            Err(e) => {
+                warn!("[{}] failed to subscribe; error = {}", path.to, e);
+                path.to_chain.ensure_connected().await?;
+                continue;
+            }
+        };
+        let timeout = Duration::from_secs(subscription_timeout);
+        while let Ok(block_header) =
+            async_std::future::timeout(timeout, to_block_headers.next()).await
+        {
+            let (from_client, to_client) =
+                (path.from_chain.client().await, path.to_chain.client().await);
+            if let Err(e) = check_blocks(&path, block_header, &to_client, &from_client).await {
+                error!(
+                    "[{}] failed to check client updates; error = {}",
+                    path.to, e
+                );
+                path.from_chain.ensure_connected().await?;
+            }
         }
-    })
+        warn!(
+            "[{}] no finalized block for {:?}, resubscribing",
+            path.to, timeout
+        );
+        path.to_chain.ensure_connected().await?;
+    }
+}
+
+/// Check the updates of `path.from`'s light client in the blocks of `path.to` finalized since
+/// the last one checked, up to `block_header`, so that none is missed while the subscription was
+/// down or the relayer stopped.
+async fn check_blocks(
+    path: &Path,
+    block_header: generic::Header<u32, sp_runtime::traits::BlakeTwo256>,
+    to_client: &Client<Runtime>,
+    from_client: &Client<Runtime>,
+) -> Result<(), Box<dyn Error>> {
+    let block_number = block_header.number;
+    let from_height = match path
+        .store
+        .last_checked_height(&path.to, &path.from_client_identifier)?
+    {
+        Some(last_height) => last_height + 1,
+        None => block_number,
+    };
+    for height in from_height..=block_number {
+        let hash = if height == block_number {
+            block_header.hash()
+        } else {
+            to_client
+                .block_hash(Some(BlockNumber::from(height)))
+                .await?
+                .ok_or("block not found")?
+        };
+        misbehaviour::check_block(
+            &path.to,
+            &path.to_tx,
+            hash,
+            to_client,
+            &path.from_client_identifier,
+            from_client,
+        )
+        .await?;
+        path.store
+            .set_last_checked_height(&path.to, &path.from_client_identifier, height)?;
+    }
+    Ok(())
+}
+
+async fn run(config: &Config) -> Result<(), Box<dyn Error>> {
+    let shutdown = supervisor::shutdown_signal()?;
+    let store = Store::open(&config.data_dir)
+        .map_err(|e| format!("can not open store in {}: {}", config.data_dir, e))?;
+    let (chains, senders, delivery_handles) = connect(config, &store).await?;
+
+    let mut handles = Vec::new();
+    if let Some(telemetry) = &config.telemetry {
+        let listen = telemetry.listen;
+        handles.push(supervisor::spawn(
+            "telemetry".to_string(),
+            move || metrics::serve(listen),
+            Some(shutdown.clone()),
+        ));
+    }
+    for task in &config.relay {
+        println!("task: {:?}", task);
+        let path = Path {
+            from: task.from.clone(),
+            from_chain: chains[&task.from].clone(),
+            from_client_identifier: config.chains[&task.from].client_identifier.clone(),
+            from_tx: senders[&task.from].clone(),
+            to: task.to.clone(),
+            to_chain: chains[&task.to].clone(),
+            to_client_identifier: config.chains[&task.to].client_identifier.clone(),
+            to_tx: senders[&task.to].clone(),
+            filter: task.filter.clone(),
+            handshakes: task.handshakes,
+            from_client_type: config.chains[&task.from].client_type.clone(),
+            to_client_type: config.chains[&task.to].client_type.clone(),
+            store: store.clone(),
+        };
+
+        let clear_path = path.clone();
+        let clear_interval = config.clear_interval;
+        handles.push(supervisor::spawn(
+            format!("{} clear", task.from),
+            move || clear_periodically(clear_path.clone(), clear_interval),
+            Some(shutdown.clone()),
+        ));
+        let (subscription_timeout, client_update) =
+            (config.subscription_timeout, config.client_update);
+        let monitor_path = path.clone();
+        handles.push(supervisor::spawn(
+            format!("{} misbehaviour", task.to),
+            move || monitor_misbehaviour(monitor_path.clone(), subscription_timeout),
+            Some(shutdown.clone()),
+        ));
+        handles.push(supervisor::spawn(
+            format!("{} => {}", task.from, task.to),
+            move || follow(path.clone(), subscription_timeout, client_update),
+            Some(shutdown.clone()),
+        ));
+    }
+
+    let _ = shutdown.recv().await;
+    // Stop relaying, then let the delivery tasks submit what is already queued.
+    for handle in handles {
+        handle.await;
+    }
+    drop(senders);
+    info!("submitting queued messages");
+    for handle in delivery_handles {
+        handle.await;
+    }
+    store.flush()?;
+    Ok(())
+}
+
+/// Clear the packets sent on `chain` over `(port_id, channel_id)` once, and wait until the
+/// resulting messages are submitted.
+async fn clear_packets(
+    config: &Config,
+    chain: &str,
+    port_id: &str,
+    channel_id: &str,
+    from_height: Option<u32>,
+) -> Result<(), Box<dyn Error>> {
+    let store = Store::open(&config.data_dir)
+        .map_err(|e| format!("can not open store in {}: {}", config.data_dir, e))?;
+    let (chains, senders, handles) = connect(config, &store).await?;
+    let mut clients = HashMap::new();
+    for (name, chain) in &chains {
+        clients.insert(name.clone(), chain.client().await);
+    }
+
+    let counterparties = config
+        .relay
+        .iter()
+        .filter(|task| task.from == chain)
+        .map(|task| task.to.clone())
+        .collect::<Vec<_>>();
+    if counterparties.is_empty() {
+        return Err(format!("no relay path from {}", chain).into());
+    }
+    for counterparty in &counterparties {
+        if let Some(from_height) = from_height {
+            clear::rescan(chain, counterparty, &clients[chain], from_height, &store).await?;
+            clear::rescan(
+                counterparty,
+                chain,
+                &clients[counterparty],
+                from_height,
+                &store,
+            )
+            .await?;
+        }
+        clear::clear_packets(
+            chain,
+            counterparty,
+            &senders[counterparty],
+            &senders[chain],
+            &clients[chain],
+            &config.chains[chain].client_identifier,
+            &clients[counterparty],
+            &config.chains[counterparty].client_identifier,
+            &config.chains[chain].client_type,
+            &config.chains[counterparty].client_type,
+            Some((port_id, channel_id)),
+            &ChannelFilter::default(),
+            &store,
+        )
+        .await?;
+    }
+
+    drop(senders);
+    for handle in handles {
+        handle.await;
+    }
+    store.flush()?;
+    Ok(())
 }
 
 fn get_dummy_account_id_raw() -> String {
@@ -180,19 +621,133 @@ pub fn get_dummy_account_id() -> AccountId {
     AccountId::from_str(&get_dummy_account_id_raw()).unwrap()
 }
 
+/// The latest height of a light client of `client_type`, as configured for the chain it
+/// tracks, whose state `query_client_state` gives as `value`.
+fn client_latest_height(client_type: &str, value: &[u8]) -> Result<u32, Box<dyn Error>> {
+    match client_type {
+        GRANDPA_CLIENT_TYPE => match AnyClientState::decode_vec(value)? {
+            AnyClientState::Grandpa(client_state) => {
+                Ok(client_state.latest_height.revision_height as u32)
+            }
+            _ => Err("wrong client state type".into()),
+        },
+        _ => Err(format!("unsupported client type {}", client_type).into()),
+    }
+}
+
+/// The hash of block `height` of `client`, which must be finalized, as any block a light client
+/// of the chain is at.
+async fn finalized_block_hash(
+    client: &Client<Runtime>,
+    height: u32,
+) -> Result<H256, Box<dyn Error>> {
+    let finalized_head = client.finalized_head().await?;
+    let finalized_number = client
+        .header(Some(finalized_head))
+        .await?
+        .ok_or("finalized header not found")?
+        .number;
+    if height > finalized_number {
+        return Err(format!("block {} is not finalized", height).into());
+    }
+    Ok(client
+        .block_hash(Some(BlockNumber::from(height)))
+        .await?
+        .ok_or("block not found")?)
+}
+
+/// Read the pallet-ibc events deposited in block `block_hash`.
+async fn ibc_events(
+    client: &Client<Runtime>,
+    block_hash: H256,
+) -> Result<Vec<pallet_ibc::Event<node_runtime::Runtime>>, Box<dyn Error>> {
+    let events = event_records(client, block_hash)
+        .await?
+        .into_iter()
+        .filter_map(|record| match record.event {
+            node_runtime::Event::Ibc(event) => Some(event),
+            _ => None,
+        })
+        .collect();
+    Ok(events)
+}
+
+/// The events deposited in block `block_hash` of `client`, with the phase of each.
+async fn event_records(
+    client: &Client<Runtime>,
+    block_hash: H256,
+) -> Result<EventRecords, substrate_subxt::Error> {
+    let mut storage_key = twox_128(b"System").to_vec();
+    storage_key.extend(twox_128(b"Events").to_vec());
+    let events_storage_key = StorageKey(storage_key);
+
+    let change_sets: Vec<StorageChangeSet<H256>> = client
+        .query_storage(vec![events_storage_key], block_hash, Some(block_hash))
+        .await?;
+    debug!("length of change_sets: {:?}", change_sets.len());
+    debug!("change_sets: {:?}", change_sets);
+    let events = change_sets
+        .into_iter()
+        .map(|change_set| change_set.changes)
+        .flatten()
+        .filter_map(|(_key, data)| data.as_ref().map(|data| Decode::decode(&mut &data.0[..])))
+        .filter_map(|result: Result<EventRecords, codec::Error>| result.ok())
+        .flatten()
+        .collect();
+    Ok(events)
+}
+
 async fn relay(
     chain_name: &str,
-    tx: Sender<pallet_ibc::informalsystems::Any>,
+    counterparty_name: &str,
+    tx: Sender<pallet_ibc::Any>,
+    source_tx: Sender<pallet_ibc::Any>,
     block_header: generic::Header<u32, sp_runtime::traits::BlakeTwo256>,
     client: &Client<Runtime>,
     client_identifier: String,
     counterparty_client: &Client<Runtime>,
     counterparty_client_identifier: String,
-) -> Result<(), Box<dyn Error>> {
-    let mut storage_key = twox_128(b"System").to_vec();
-    storage_key.extend(twox_128(b"Events").to_vec());
-    let events_storage_key = StorageKey(storage_key);
-
+    client_type: &str,
+    counterparty_client_type: &str,
+    filter: &ChannelFilter,
+    handshakes: bool,
+    mode: UpdateMode,
+    store: &Store,
+) -> Result<bool, Box<dyn Error>> {
+    let data = counterparty_client
+        .query_client_state(&client_identifier, None)
+        .await?
+        .ok_or("counterparty client state not found")?;
+    let latest_height = client_latest_height(client_type, &data.value)?;
+    METRICS
+        .client_update_lag
+        .with_label_values(&[chain_name])
+        .set(block_header.number.saturating_sub(latest_height) as u64);
+    // The light client only takes justified headers, so the block relayed is the latest
+    // justified one, at or after the block finalized. A client already at or past the block
+    // finalized is not updated, and the messages are proven at its height.
+    let path = client
+        .update_path(latest_height, block_header.number)
+        .await?;
+    let height = match path.last() {
+        None if latest_height >= block_header.number => latest_height,
+        Some(&(height, _)) if height >= block_header.number => height,
+        _ => {
+            debug!(
+                "[{}] no justified block at or after {} yet",
+                chain_name, block_header.number
+            );
+            return Ok(false);
+        }
+    };
+    let hash = client
+        .block_hash(Some(BlockNumber::from(height)))
+        .await?
+        .ok_or("justified block not found")?;
+    let block_header = client
+        .header(Some(hash))
+        .await?
+        .ok_or("justified header not found")?;
     let block_number = block_header.number;
     let state_root = block_header.state_root;
     let block_hash = block_header.hash();
@@ -200,357 +755,196 @@ async fn relay(
     debug!("[{}] state_root: {:?}", chain_name, state_root);
     debug!("[{}] block_hash: {:?}", chain_name, block_hash);
     // this method is equivalent to queryClientState
-    // TODO
-    let data = client
-        .client_states_v2(
-            ClientId::from_str(&counterparty_client_identifier)
-                .unwrap()
-                .as_bytes()
-                .to_vec(),
-            Some(block_hash),
-        )
-        .await?;
-    let client_state = AnyClientState::decode_vec(&*data).unwrap();
-    let client_state = match client_state {
-        AnyClientState::GRANDPA(client_state) => client_state,
-        _ => panic!("wrong client state type"),
-    };
+    let client_state_data = client
+        .query_client_state(&counterparty_client_identifier, Some(block_hash))
+        .await?
+        .ok_or("client state not found")?;
+    let counterparty_height =
+        client_latest_height(counterparty_client_type, &client_state_data.value)?;
 
-    let counterparty_block_hash = counterparty_client
-        .block_hash(Some(BlockNumber::from(client_state.latest_height as u32)))
-        .await?;
+    let counterparty_block_hash =
+        finalized_block_hash(counterparty_client, counterparty_height).await?;
+    let counterparty_timestamp =
+        packet::block_timestamp(counterparty_client, counterparty_block_hash).await?;
     info!(
         "[{}] client latest height: {}",
-        chain_name, client_state.latest_height
+        chain_name, counterparty_height
     );
-    let data = counterparty_client
-        .client_states_v2(
-            ClientId::from_str(&client_identifier)
-                .unwrap()
-                .as_bytes()
-                .to_vec(),
-            None,
+    // Every message below is proven at block_number, which the counterparty's light client of
+    // this chain is updated to first, at once or when the first such message is sent.
+    let mut client_update = ClientUpdate::start(
+        chain_name,
+        &tx,
+        client,
+        &client_identifier,
+        latest_height,
+        block_number,
+        path,
+        mode,
+    )
+    .await?;
+
+    if handshakes {
+        let client_proof = handshake::ClientProof {
+            client_id: ClientId::from_str(&counterparty_client_identifier).unwrap(),
+            client_state: AnyClientState::decode_vec(&client_state_data.value)?,
+            proof: client_state_data,
+            latest_height: counterparty_height,
+        };
+        handshake::relay_connections(
+            chain_name,
+            client_update.sender(),
+            block_hash,
+            block_number,
+            client,
+            &client_identifier,
+            &client_proof,
+            counterparty_client,
+            store,
         )
         .await?;
-    let counterparty_client_state = AnyClientState::decode_vec(&*data).unwrap();
-    let counterparty_client_state = match counterparty_client_state {
-        AnyClientState::GRANDPA(client_state) => client_state,
-        _ => panic!("wrong client state type"),
-    };
-    // For the 2 parties on inter-blockchain communication, if one chain(counterparty_client) doesn't have latest block of the other chain(client).
-    if (counterparty_client_state.latest_height as u32) < block_number {
-        for height in counterparty_client_state.latest_height as u32 + 1..=block_number {
-            let hash = client.block_hash(Some(BlockNumber::from(height))).await?;
-            let signed_block = client.block(hash).await?;
-            let authorities_proof = client
-                .read_proof(
-                    vec![StorageKey(GRANDPA_AUTHORITIES_KEY.to_vec())],
-                    Some(hash.unwrap()),
-                )
-                .await?;
-            if let Some(signed_block) = signed_block {
-                let tm_signer = get_dummy_account_id();
-                let header = AnyHeader::GRANDPA(GRANDPAHeader {
-                    height: signed_block.block.header.number.into(),
-                    commitment_root: signed_block.block.header.state_root,
-                    block_hash: signed_block.block.header.hash(),
-                    justification: signed_block.justification,
-                    authorities_proof: StorageProof::new(
-                        authorities_proof.proof.into_iter().map(|b| b.0).collect(),
-                    ),
-                });
-                let msg = MsgUpdateAnyClient::new(
-                    ClientId::from_str(&client_identifier).unwrap(),
-                    header,
-                    tm_signer,
-                );
-                let data = msg.encode_vec().unwrap();
-                let any = pallet_ibc::informalsystems::Any {
-                    type_url: update_client::TYPE_URL.to_string(),
-                    value: data,
-                };
-
-                tx.send(any).unwrap();
-            }
-        }
-    }
-    /*
-    if client_state.connections.len() > 0 {
-        info!(
-            "[{}] connections: {:?}",
-            chain_name, client_state.connections
-        );
-    }
-    for connection in client_state.connections.iter() {
-        let connection_end = client.connections(*connection, Some(block_hash)).await?;
-        debug!("[{}] connection_end: {:#?}", chain_name, connection_end);
-        let remote_connection_end = counterparty_client
-            .connections(
-                connection_end.counterparty_connection_id,
-                counterparty_block_hash,
-            )
-            .await?;
-        debug!(
-            "[{}] remote_connection_end: {:#?}",
-            chain_name, remote_connection_end
-        );
-        info!(
-            "[{}] connection state: {:?}, counterparty connection state: {:?}",
-            chain_name, connection_end.state, remote_connection_end.state
-        );
-        // TODO: remote_connection_end == None ??
-        if connection_end.state == ConnectionState::Init
-            && remote_connection_end.state == ConnectionState::None
-        {
-            // this is equivalent to queryChainConsensusState
-            let consensus_states = ConsensusStatesStore::<Runtime> {
-                key: (client_identifier, block_number),
-                _runtime: Default::default(),
-            };
-            let key = consensus_states.key(&client.metadata())?;
-            let proof_consensus = client.read_proof(vec![key], Some(block_hash)).await?;
-            let connections = ConnectionsStore::<Runtime> {
-                key: *connection,
-                _runtime: Default::default(),
-            };
-            let key = connections.key(&client.metadata())?;
-            let proof_init = client.read_proof(vec![key], Some(block_hash)).await?;
-            let datagram = Datagram::ConnOpenTry {
-                connection_id: connection_end.counterparty_connection_id,
-                counterparty_connection_id: *connection,
-                counterparty_client_id: client_identifier,
-                client_id: counterparty_client_identifier,
-                version: vec![],
-                counterparty_version: connection_end.version,
-                proof_init: StorageProof::new(proof_init.proof.into_iter().map(|b| b.0).collect()),
-                proof_consensus: StorageProof::new(
-                    proof_consensus.proof.into_iter().map(|b| b.0).collect(),
-                ),
-                proof_height: block_number,
-                consensus_height: 0, // TODO: local consensus state height
-            };
-            tx.send(datagram).unwrap();
-        } else if connection_end.state == ConnectionState::TryOpen
-            && remote_connection_end.state == ConnectionState::Init
-        {
-            let connections = ConnectionsStore::<Runtime> {
-                key: *connection,
-                _runtime: Default::default(),
-            };
-            let key = connections.key(&client.metadata())?;
-            let proof_try = client.read_proof(vec![key], Some(block_hash)).await?;
-            let datagram = Datagram::ConnOpenAck {
-                connection_id: connection_end.counterparty_connection_id,
-                counterparty_connection_id: connection_end.client_id,
-                version: 0,
-                proof_try: StorageProof::new(proof_try.proof.into_iter().map(|b| b.0).collect()),
-                proof_consensus: StorageProof::empty(),
-                proof_height: block_number,
-                consensus_height: 0,
-            };
-            tx.send(datagram).unwrap();
-        } else if connection_end.state == ConnectionState::Open
-            && remote_connection_end.state == ConnectionState::TryOpen
-        {
-            let connections = ConnectionsStore::<Runtime> {
-                key: *connection,
-                _runtime: Default::default(),
-            };
-            let key = connections.key(&client.metadata())?;
-            let proof_ack = client.read_proof(vec![key], Some(block_hash)).await?;
-            let datagram = Datagram::ConnOpenConfirm {
-                connection_id: connection_end.counterparty_connection_id,
-                proof_ack: StorageProof::new(proof_ack.proof.into_iter().map(|b| b.0).collect()),
-                proof_height: block_number,
-            };
-            tx.send(datagram).unwrap();
-        }
-    }
-    if client_state.channels.len() > 0 {
-        info!("[{}] channels: {:?}", chain_name, client_state.channels);
-    }
-    for channel in client_state.channels.iter() {
-        let channel_end = client.channels(channel.clone(), Some(block_hash)).await?;
-
-        debug!("[{}] channel_end: {:#?}", chain_name, channel_end);
-        let remote_channel_end = counterparty_client
-            .channels(
-                (
-                    channel_end.counterparty_port_id.clone(),
-                    channel_end.counterparty_channel_id,
-                ),
-                counterparty_block_hash,
-            )
-            .await?;
-        debug!(
-            "[{}] remote_channel_end: {:#?}",
-            chain_name, remote_channel_end
-        );
-        info!(
-            "[{}] channle state: {:?}, counterparty channel state: {:?}",
-            chain_name, channel_end.state, remote_channel_end.state
-        );
-        if channel_end.state == ChannelState::Init && remote_channel_end.state == ChannelState::None
-        {
-            let connection_end = client
-                .connections(channel_end.connection_hops[0], Some(block_hash))
-                .await?;
-            let channels = ChannelsStore::<Runtime> {
-                key: channel.clone(),
-                _runtime: Default::default(),
-            };
-            let key = channels.key(&client.metadata())?;
-            let proof_init = client.read_proof(vec![key], Some(block_hash)).await?;
-            let datagram = Datagram::ChanOpenTry {
-                order: channel_end.ordering,
-                // connection_hops: channel_end.connection_hops.into_iter().rev().collect(), // ??
-                connection_hops: vec![connection_end.counterparty_connection_id],
-                port_id: channel_end.counterparty_port_id,
-                channel_id: channel_end.counterparty_channel_id,
-                counterparty_port_id: channel.0.clone(),
-                counterparty_channel_id: channel.1,
-                channel_version: channel_end.version.clone(),
-                counterparty_version: channel_end.version,
-                proof_init: StorageProof::new(proof_init.proof.into_iter().map(|b| b.0).collect()),
-                proof_height: block_number,
-            };
-            tx.send(datagram).unwrap();
-        } else if channel_end.state == ChannelState::TryOpen
-            && remote_channel_end.state == ChannelState::Init
-        {
-            let channels = ChannelsStore::<Runtime> {
-                key: channel.clone(),
-                _runtime: Default::default(),
-            };
-            let key = channels.key(&client.metadata())?;
-            let proof_try = client.read_proof(vec![key], Some(block_hash)).await?;
-            let datagram = Datagram::ChanOpenAck {
-                port_id: channel_end.counterparty_port_id,
-                channel_id: channel_end.counterparty_channel_id,
-                version: remote_channel_end.version,
-                proof_try: StorageProof::new(proof_try.proof.into_iter().map(|b| b.0).collect()),
-                proof_height: block_number,
-            };
-            tx.send(datagram).unwrap();
-        } else if channel_end.state == ChannelState::Open
-            && remote_channel_end.state == ChannelState::TryOpen
-        {
-            let channels = ChannelsStore::<Runtime> {
-                key: channel.clone(),
-                _runtime: Default::default(),
-            };
-            let key = channels.key(&client.metadata())?;
-            let proof_ack = client.read_proof(vec![key], Some(block_hash)).await?;
-            let datagram = Datagram::ChanOpenConfirm {
-                port_id: channel_end.counterparty_port_id,
-                channel_id: channel_end.counterparty_channel_id,
-                proof_ack: StorageProof::new(proof_ack.proof.into_iter().map(|b| b.0).collect()),
-                proof_height: block_number,
-            };
-            tx.send(datagram).unwrap();
-        }
     }
+    handshake::relay_channels(
+        chain_name,
+        counterparty_name,
+        client_update.sender(),
+        block_hash,
+        block_number,
+        client,
+        counterparty_client,
+        filter,
+        handshakes,
+        store,
+    )
+    .await?;
+    client_update.flush().await?;
 
-    let change_sets: Vec<StorageChangeSet<H256>> = client
-        .query_storage(vec![events_storage_key], block_hash, None)
+    // Rescan the blocks finalized since the last one processed, which a restart or a dropped
+    // subscription would otherwise miss. Their messages are proven at block_number, which the
+    // light client is updated to, rather than at the blocks they were found in.
+    let from_height = match store.last_height(chain_name, counterparty_name)? {
+        Some(last_height) => last_height + 1,
+        None => block_number,
+    };
+    for height in from_height..=block_number {
+        let hash = if height == block_number {
+            block_hash
+        } else {
+            client
+                .block_hash(Some(BlockNumber::from(height)))
+                .await?
+                .ok_or("block not found")?
+        };
+        relay_packet_events(
+            chain_name,
+            counterparty_name,
+            client_update.sender(),
+            hash,
+            block_hash,
+            block_number,
+            client,
+            counterparty_height,
+            counterparty_timestamp,
+            filter,
+            store,
+        )
         .await?;
-    debug!("length of change_sets: {:?}", change_sets.len());
-    debug!("change_sets: {:?}", change_sets);
-    let events = change_sets
-        .into_iter()
-        .map(|change_set| change_set.changes)
-        .flatten()
-        .filter_map(|(_key, data)| data.as_ref().map(|data| Decode::decode(&mut &data.0[..])))
-        .filter_map(|result: Result<EventRecords, codec::Error>| result.ok())
-        .flatten()
-        .collect::<Vec<frame_system::EventRecord<node_runtime::Event, <Runtime as System>::Hash>>>(
-        );
+        client_update.flush().await?;
+        store.set_last_height(chain_name, counterparty_name, height)?;
+        METRICS
+            .latest_relayed_height
+            .with_label_values(&[chain_name])
+            .set(height as u64);
+    }
+    // The timeouts are proven on the counterparty, so need no update of its client here.
+    packet::relay_timeouts(
+        chain_name,
+        counterparty_name,
+        &source_tx,
+        block_hash,
+        client,
+        &counterparty_client_identifier,
+        counterparty_client,
+        counterparty_height,
+        counterparty_block_hash,
+        counterparty_timestamp,
+        filter,
+        store,
+    )
+    .await?;
+
+    Ok(client_update.updated())
+}
+
+/// Relay the packets sent and acknowledgements written in block `events_at` of `client`, on the
+/// channels `filter` allows, proven at block `block_hash`.
+async fn relay_packet_events(
+    chain_name: &str,
+    counterparty_name: &str,
+    tx: &Sender<pallet_ibc::Any>,
+    events_at: H256,
+    block_hash: H256,
+    block_number: u32,
+    client: &Client<Runtime>,
+    counterparty_height: u32,
+    counterparty_timestamp: u64,
+    filter: &ChannelFilter,
+    store: &Store,
+) -> Result<(), Box<dyn Error>> {
+    let events = metrics::time_rpc(
+        chain_name,
+        "state_queryStorage",
+        ibc_events(client, events_at),
+    )
+    .await?;
     for event in events.into_iter() {
-        match event.event {
-            node_runtime::Event::pallet_ibc(pallet_ibc::RawEvent::SendPacket(
-                sequence,
-                data,
-                timeout_height,
-                source_port,
-                source_channel,
-                dest_port,
-                dest_channel,
-            )) => {
-                info!("[{}] SendPacket data: {:?}", chain_name, data);
-                let packet_data = Packet {
-                    sequence,
-                    timeout_height,
-                    source_port: source_port.clone(),
-                    source_channel,
-                    dest_port,
-                    dest_channel,
-                    data,
-                };
-                let packets = PacketsStore::<Runtime> {
-                    key: (source_port, source_channel, timeout_height.into()),
-                    _runtime: Default::default(),
-                };
-                let key = packets.key(&client.metadata())?;
-                let proof = client.read_proof(vec![key], Some(block_hash)).await?;
-                let datagram = Datagram::PacketRecv {
-                    packet: packet_data,
-                    proof: StorageProof::new(proof.proof.into_iter().map(|b| b.0).collect()),
-                    proof_height: block_number,
-                };
-                tx.send(datagram).unwrap();
+        match event {
+            pallet_ibc::Event::SendPacket(_height, packet) => {
+                let packet = packet.to_ibc_packet();
+                if !filter.allows(packet.source_port.as_str(), packet.source_channel.as_str()) {
+                    continue;
+                }
+                packet::relay_send_packet(
+                    chain_name,
+                    tx,
+                    packet,
+                    block_hash,
+                    block_number,
+                    client,
+                    counterparty_height,
+                    counterparty_timestamp,
+                    store,
+                )
+                .await?;
             }
-            node_runtime::Event::pallet_ibc(pallet_ibc::RawEvent::RecvPacket(
-                sequence,
-                data,
-                timeout_height,
-                source_port,
-                source_channel,
-                dest_port,
-                dest_channel,
-                acknowledgement,
-            )) => {
-                debug!(
-                    "[{}] RecvPacket sequence: {}, data: {:?}, timeout_height: {}, \
-                             source_port: {:?}, source_channel: {:?}, dest_port: {:?}, \
-                             dest_channel: {:?}",
+            pallet_ibc::Event::WriteAcknowledgement(_height, packet, ack) => {
+                let packet = packet.to_ibc_packet();
+                if !filter.allows(
+                    packet.destination_port.as_str(),
+                    packet.destination_channel.as_str(),
+                ) {
+                    continue;
+                }
+                packet::relay_write_acknowledgement(
                     chain_name,
-                    sequence,
-                    data,
-                    timeout_height,
-                    source_port,
-                    source_channel,
-                    dest_port,
-                    dest_channel
-                );
-                info!("[{}] RecvPacket data: {:?}", chain_name, data);
-                // relay packet acknowledgement with this sequence number
-                let packet_data = Packet {
-                    sequence,
-                    timeout_height,
-                    source_port: source_port.clone(),
-                    source_channel,
-                    dest_port,
-                    dest_channel,
-                    data,
-                };
-                let acknowledgements = AcknowledgementsStore::<Runtime> {
-                    key: (source_port, source_channel, timeout_height.into()),
-                    _runtime: Default::default(),
-                };
-                let key = acknowledgements.key(&client.metadata())?;
-                let proof = client.read_proof(vec![key], Some(block_hash)).await?;
-                let datagram = Datagram::PacketAcknowledgement {
-                    packet: packet_data,
-                    acknowledgement,
-                    proof: StorageProof::new(proof.proof.into_iter().map(|b| b.0).collect()),
-                    proof_height: block_number,
-                };
-                tx.send(datagram).unwrap();
+                    tx,
+                    packet,
+                    ack,
+                    block_hash,
+                    block_number,
+                    client,
+                    store,
+                )
+                .await?;
+            }
+            pallet_ibc::Event::AcknowledgePacket(_height, packet)
+            | pallet_ibc::Event::TimeoutPacket(_height, packet)
+            | pallet_ibc::Event::TimeoutOnClosePacket(_height, packet) => {
+                store.remove_packet(chain_name, counterparty_name, &packet.to_ibc_packet())?;
             }
             _ => {}
         }
     }
-    */
 
     Ok(())
 }
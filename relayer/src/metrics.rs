@@ -0,0 +1,150 @@
+//! Prometheus metrics of the relayer, served on `/metrics` when `[telemetry]` is configured.
+//!
+//! The metrics are always recorded, and named like the node's, with a `relayer_` prefix.
+use lazy_static::lazy_static;
+use serde_derive::Deserialize;
+use std::error::Error;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::time::Instant;
+use substrate_prometheus_endpoint::{
+    exponential_buckets, init_prometheus, register, CounterVec, GaugeVec, HistogramOpts,
+    HistogramVec, Opts, PrometheusError, Registry, U64,
+};
+
+#[derive(Debug, Deserialize)]
+pub struct TelemetryConfig {
+    /// The address the `/metrics` endpoint listens on.
+    pub listen: SocketAddr,
+}
+
+pub struct Metrics {
+    /// The last finalized block of a chain relayed.
+    pub latest_relayed_height: GaugeVec<U64>,
+    /// How many blocks a chain's finalized head is ahead of its light client on the counterparty.
+    pub client_update_lag: GaugeVec<U64>,
+    /// Packet messages finalized on a chain, by kind: `recv`, `ack` or `timeout`.
+    pub packets: CounterVec<U64>,
+    /// `deliver` extrinsics to a chain that failed, by error.
+    pub extrinsic_failures: CounterVec<U64>,
+    /// Messages waiting for a chain's delivery task.
+    pub queue_depth: GaugeVec<U64>,
+    /// Latency of RPC requests to a chain, by method.
+    pub rpc_latency: HistogramVec,
+    /// Time from the submission of a `deliver` extrinsic to a chain until it is included in a
+    /// block, or finalized, by stage: `in_block` or `finalized`.
+    pub extrinsic_latency: HistogramVec,
+}
+
+impl Metrics {
+    fn register(registry: &Registry) -> Result<Self, PrometheusError> {
+        Ok(Metrics {
+            latest_relayed_height: register(
+                GaugeVec::new(
+                    Opts::new(
+                        "latest_relayed_height",
+                        "Number of the last finalized block of the chain relayed",
+                    ),
+                    &["chain"],
+                )?,
+                registry,
+            )?,
+            client_update_lag: register(
+                GaugeVec::new(
+                    Opts::new(
+                        "client_update_lag",
+                        "Number of finalized blocks of the chain its light client on the \
+                         counterparty is behind",
+                    ),
+                    &["chain"],
+                )?,
+                registry,
+            )?,
+            packets: register(
+                CounterVec::new(
+                    Opts::new(
+                        "packets_total",
+                        "Number of packet messages finalized on the chain, by kind",
+                    ),
+                    &["chain", "kind"],
+                )?,
+                registry,
+            )?,
+            extrinsic_failures: register(
+                CounterVec::new(
+                    Opts::new(
+                        "extrinsic_failures_total",
+                        "Number of deliver extrinsics to the chain that failed, by error",
+                    ),
+                    &["chain", "error"],
+                )?,
+                registry,
+            )?,
+            queue_depth: register(
+                GaugeVec::new(
+                    Opts::new(
+                        "queue_depth",
+                        "Number of messages waiting to be delivered to the chain",
+                    ),
+                    &["chain"],
+                )?,
+                registry,
+            )?,
+            rpc_latency: register(
+                HistogramVec::new(
+                    HistogramOpts::new(
+                        "rpc_latency_seconds",
+                        "Time taken by RPC requests to the chain, by method",
+                    )
+                    .buckets(exponential_buckets(0.001, 4.0, 9)?),
+                    &["chain", "method"],
+                )?,
+                registry,
+            )?,
+            extrinsic_latency: register(
+                HistogramVec::new(
+                    HistogramOpts::new(
+                        "extrinsic_latency_seconds",
+                        "Time from the submission of a deliver extrinsic to the chain until it \
+                         is included in a block or finalized, by stage",
+                    )
+                    .buckets(exponential_buckets(1.0, 2.0, 10)?),
+                    &["chain", "stage"],
+                )?,
+                registry,
+            )?,
+        })
+    }
+}
+
+lazy_static! {
+    static ref REGISTRY: Registry =
+        Registry::new_custom(Some("relayer".to_string()), None).expect("the prefix is valid");
+    pub static ref METRICS: Metrics =
+        Metrics::register(&REGISTRY).expect("the metrics are registered once");
+}
+
+/// Serve the metrics on `http://<listen>/metrics`.
+pub async fn serve(listen: SocketAddr) -> Result<(), Box<dyn Error>> {
+    init_prometheus(listen, REGISTRY.clone()).await?;
+    Ok(())
+}
+
+/// Run `future`, an RPC request `method` to `chain`, recording its latency.
+pub async fn time_rpc<F: Future>(chain: &str, method: &str, future: F) -> F::Output {
+    let timer = METRICS
+        .rpc_latency
+        .with_label_values(&[chain, method])
+        .start_timer();
+    let output = future.await;
+    timer.observe_duration();
+    output
+}
+
+/// Record that a `deliver` extrinsic `submitted` to `chain` reached `stage`.
+pub fn observe_extrinsic(chain: &str, stage: &str, submitted: Instant) {
+    METRICS
+        .extrinsic_latency
+        .with_label_values(&[chain, stage])
+        .observe(submitted.elapsed().as_secs_f64());
+}
@@ -0,0 +1,198 @@
+//! ICS-002 misbehaviour detection for the GRANDPA light clients: every header a client accepts
+//! is checked against the finalized chain it tracks, and a conflicting one is submitted with the
+//! canonical header of the same height, which freezes the client.
+use crate::get_dummy_account_id;
+use crate::update_client;
+use async_std::channel::Sender;
+use calls::{grandpa::GrandpaRpcExt, ibc::IbcRpcExt, NodeRuntime as Runtime};
+use codec::{Decode, Encode};
+use ibc::ics02_client::client_consensus::AnyConsensusState;
+use ibc::ics02_client::header::AnyHeader;
+use ibc::ics02_client::height::Height;
+use ibc::ics02_client::misbehaviour::AnyMisbehaviour;
+use ibc::ics02_client::msgs::misbehavior::{self, MsgSubmitAnyMisbehaviour};
+use ibc::ics02_client::msgs::update_client::{self as update_client_msg, MsgUpdateAnyClient};
+use ibc::ics10_grandpa::header::Header as GRANDPAHeader;
+use ibc::ics10_grandpa::misbehaviour::Misbehaviour as GRANDPAMisbehaviour;
+use ibc::ics24_host::identifier::ClientId;
+use ibc::signer::Signer;
+use log::{debug, error};
+use sp_core::H256;
+use std::error::Error;
+use substrate_subxt::{BlockNumber, Client};
+use tendermint_proto::Protobuf;
+
+/// Check the headers the light client `client_identifier` on `client` accepted in block
+/// `block_hash` against the finalized chain of `counterparty_client`, and send the misbehaviour
+/// of any conflicting one through `tx`.
+pub async fn check_block(
+    chain_name: &str,
+    tx: &Sender<pallet_ibc::Any>,
+    block_hash: H256,
+    client: &Client<Runtime>,
+    client_identifier: &str,
+    counterparty_client: &Client<Runtime>,
+) -> Result<(), Box<dyn Error>> {
+    for event in crate::ibc_events(client, block_hash).await?.into_iter() {
+        if let pallet_ibc::Event::UpdateClient(_height, client_id, _client_type, consensus_height) =
+            event
+        {
+            let client_id = client_id.to_ibc_client_id();
+            if client_id.as_str() != client_identifier {
+                continue;
+            }
+            let height = consensus_height.to_ibc_height().revision_height as u32;
+            check_header(
+                chain_name,
+                tx,
+                block_hash,
+                client,
+                &client_id,
+                counterparty_client,
+                height,
+            )
+            .await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Check the consensus state `client_id` stored for `height` against the finalized header of
+/// that height.
+async fn check_header(
+    chain_name: &str,
+    tx: &Sender<pallet_ibc::Any>,
+    block_hash: H256,
+    client: &Client<Runtime>,
+    client_id: &ClientId,
+    counterparty_client: &Client<Runtime>,
+    height: u32,
+) -> Result<(), Box<dyn Error>> {
+    let consensus_height = Height::new(0, height as u64).encode_vec().unwrap();
+    let consensus_state = client
+        .query_consensus_state(
+            client_id.as_str(),
+            consensus_height.clone(),
+            Some(block_hash),
+        )
+        .await?
+        .ok_or("consensus state not found")?
+        .consensus_state_at(&consensus_height)?
+        .ok_or("consensus state not found")?;
+    let root = match AnyConsensusState::decode_vec(&consensus_state)? {
+        AnyConsensusState::Grandpa(consensus_state) => consensus_state.root,
+        _ => return Ok(()),
+    };
+    let canonical_hash = counterparty_client
+        .block_hash(Some(BlockNumber::from(height)))
+        .await?
+        .ok_or("counterparty block not found")?;
+    let canonical = counterparty_client
+        .header(Some(canonical_hash))
+        .await?
+        .ok_or("counterparty header not found")?;
+    if root.as_bytes() == canonical.state_root.as_bytes() {
+        debug!(
+            "[{}] client {} header {} checked",
+            chain_name, client_id, height
+        );
+        return Ok(());
+    }
+
+    error!(
+        "[{}] client {} accepted a header at {} conflicting with the finalized chain",
+        chain_name, client_id, height
+    );
+    let accepted = accepted_header(client, block_hash, client_id, height)
+        .await?
+        .ok_or("accepted header not found")?;
+    let mut canonical = update_client::grandpa_header(counterparty_client, height, None).await?;
+    if canonical.justification.is_none() {
+        // Without a justification of its own, the block is proven final by the one of the last
+        // block of its authority set.
+        canonical.justification = counterparty_client
+            .prove_finality(height)
+            .await?
+            .map(|proof| proof.justification);
+    }
+    error!(
+        "[{}] conflicting headers, for `cli client misbehaviour`: {} {}",
+        chain_name,
+        hex::encode(AnyHeader::Grandpa(accepted.clone()).encode_vec().unwrap()),
+        hex::encode(AnyHeader::Grandpa(canonical.clone()).encode_vec().unwrap())
+    );
+    tx.send(misbehaviour_message(client_id.clone(), accepted, canonical))
+        .await?;
+    Ok(())
+}
+
+/// The header of `height` that an update of `client_id` in block `block_hash` of `client`
+/// carried, as decoded from the block's `deliver` calls.
+async fn accepted_header(
+    client: &Client<Runtime>,
+    block_hash: H256,
+    client_id: &ClientId,
+    height: u32,
+) -> Result<Option<GRANDPAHeader>, Box<dyn Error>> {
+    let block = client
+        .block(Some(block_hash))
+        .await?
+        .ok_or("block not found")?;
+    for extrinsic in block.block.extrinsics {
+        let extrinsic = match node_runtime::UncheckedExtrinsic::decode(&mut &extrinsic.encode()[..])
+        {
+            Ok(extrinsic) => extrinsic,
+            Err(_) => continue,
+        };
+        for message in delivered_messages(extrinsic.function) {
+            if message.type_url != update_client_msg::TYPE_URL {
+                continue;
+            }
+            let msg = MsgUpdateAnyClient::decode_vec(&message.value)?;
+            if &msg.client_id != client_id {
+                continue;
+            }
+            if let AnyHeader::Grandpa(header) = msg.header {
+                if header.height == height.into() {
+                    return Ok(Some(header));
+                }
+            }
+        }
+    }
+    Ok(None)
+}
+
+/// The messages `call` delivers, whether it is a `deliver` call itself or a `batch_all` of them,
+/// as the relayer submits.
+fn delivered_messages(call: node_runtime::Call) -> Vec<pallet_ibc::Any> {
+    match call {
+        node_runtime::Call::Ibc(pallet_ibc::Call::deliver(messages, _tmp)) => messages,
+        node_runtime::Call::Utility(node_runtime::UtilityCall::batch_all(calls)) => {
+            calls.into_iter().flat_map(delivered_messages).collect()
+        }
+        _ => vec![],
+    }
+}
+
+/// The `MsgSubmitAnyMisbehaviour` freezing `client_id` with two conflicting headers of the same
+/// height.
+fn misbehaviour_message(
+    client_id: ClientId,
+    header1: GRANDPAHeader,
+    header2: GRANDPAHeader,
+) -> pallet_ibc::Any {
+    let msg = MsgSubmitAnyMisbehaviour {
+        client_id: client_id.clone(),
+        misbehaviour: AnyMisbehaviour::Grandpa(GRANDPAMisbehaviour {
+            client_id,
+            header1,
+            header2,
+        }),
+        signer: Signer::new(get_dummy_account_id().to_string()),
+    };
+    pallet_ibc::Any {
+        type_url: misbehavior::TYPE_URL.to_string(),
+        value: msg.encode_vec().unwrap(),
+    }
+}
@@ -0,0 +1,595 @@
+//! ICS-004 packet relaying: `RecvPacket`, `Acknowledgement`, `Timeout` and `TimeoutOnClose`.
+use crate::filter::ChannelFilter;
+use crate::get_dummy_account_id;
+use crate::store::{Relayed, Store};
+use async_std::channel::Sender;
+use calls::{
+    ibc::{IbcRpcExt, StateResponse},
+    NodeRuntime as Runtime,
+};
+use codec::Decode;
+use ibc::ics02_client::height::Height;
+use ibc::ics03_connection::connection::ConnectionEnd;
+use ibc::ics04_channel::channel::{ChannelEnd, Order};
+use ibc::ics04_channel::msgs::{
+    acknowledgement::{self, MsgAcknowledgement},
+    recv_packet::{self, MsgRecvPacket},
+    timeout::{self, MsgTimeout},
+    timeout_on_close::{self, MsgTimeoutOnClose},
+};
+use ibc::ics04_channel::packet::{Packet, Sequence};
+use ibc::proofs::Proofs;
+use ibc::signer::Signer;
+use log::{debug, info};
+use sp_core::{storage::StorageKey, twox_128, H256};
+use std::collections::HashMap;
+use std::error::Error;
+use substrate_subxt::Client;
+use tendermint_proto::Protobuf;
+
+#[cfg(test)]
+mod tests;
+
+/// Relay a packet sent on `client` to `counterparty_client`.
+///
+/// A packet the counterparty has already passed the timeout height or timestamp of, as seen by
+/// the light client on `client`, is left to `relay_timeouts` instead.
+pub async fn relay_send_packet(
+    chain_name: &str,
+    tx: &Sender<pallet_ibc::Any>,
+    packet: Packet,
+    block_hash: H256,
+    block_number: u32,
+    client: &Client<Runtime>,
+    counterparty_height: u32,
+    counterparty_timestamp: u64,
+    store: &Store,
+) -> Result<(), Box<dyn Error>> {
+    info!(
+        "[{}] SendPacket sequence: {}, data: {:?}",
+        chain_name, packet.sequence, packet.data
+    );
+    store.insert_packet(chain_name, &packet)?;
+    if timed_out(&packet, counterparty_height, counterparty_timestamp) {
+        return Ok(());
+    }
+
+    let (port_id, channel_id) = (
+        packet.source_port.to_string(),
+        packet.source_channel.to_string(),
+    );
+    let sequence = u64::from(packet.sequence);
+    if store.is_relayed(chain_name, Relayed::Recv, &port_id, &channel_id, sequence)? {
+        debug!("[{}] packet {} already relayed", chain_name, sequence);
+        return Ok(());
+    }
+
+    // Nothing is sent if the packet has already been acknowledged or timed out.
+    send_recv_packet(
+        chain_name,
+        tx,
+        packet,
+        block_hash,
+        block_number,
+        client,
+        store,
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// Whether the counterparty has passed the timeout height or the timeout timestamp of `packet`
+/// at block `counterparty_height`, the timestamp of which is `counterparty_timestamp`. A timeout
+/// of zero is none.
+pub fn timed_out(packet: &Packet, counterparty_height: u32, counterparty_timestamp: u64) -> bool {
+    let timeout_height = packet.timeout_height.revision_height;
+    let timeout_timestamp = packet.timeout_timestamp;
+    (timeout_height != 0 && timeout_height <= counterparty_height as u64)
+        || (timeout_timestamp != 0 && timeout_timestamp <= counterparty_timestamp)
+}
+
+/// The timestamp of block `block_hash` of `client` in nanoseconds, as packet timeouts are, from
+/// the milliseconds of `Timestamp::Now`.
+pub async fn block_timestamp(
+    client: &Client<Runtime>,
+    block_hash: H256,
+) -> Result<u64, Box<dyn Error>> {
+    let mut storage_key = twox_128(b"Timestamp").to_vec();
+    storage_key.extend(twox_128(b"Now").to_vec());
+    let now: u64 = client
+        .fetch_unhashed(StorageKey(storage_key), Some(block_hash))
+        .await?
+        .unwrap_or_default();
+    Ok(now.saturating_mul(1_000_000))
+}
+
+/// Time out the packets sent on `client` towards the counterparty the light client
+/// `counterparty_client_identifier` tracks, once that client is at or past their timeout height
+/// or timestamp.
+///
+/// The packets are those of the store, checked at every counterparty height the relayer sees, so
+/// that a packet is timed out whenever the light client passes its timeout, not only if it had
+/// when the packet was sent.
+pub async fn relay_timeouts(
+    chain_name: &str,
+    counterparty_name: &str,
+    source_tx: &Sender<pallet_ibc::Any>,
+    block_hash: H256,
+    client: &Client<Runtime>,
+    counterparty_client_identifier: &str,
+    counterparty_client: &Client<Runtime>,
+    counterparty_height: u32,
+    counterparty_block_hash: H256,
+    counterparty_timestamp: u64,
+    filter: &ChannelFilter,
+    store: &Store,
+) -> Result<(), Box<dyn Error>> {
+    // Whether each channel seen leads to the counterparty.
+    let mut towards_counterparty = HashMap::new();
+    for packet in store.packets(chain_name)?.into_iter() {
+        if !timed_out(&packet, counterparty_height, counterparty_timestamp) {
+            continue;
+        }
+        let (port_id, channel_id) = (
+            packet.source_port.to_string(),
+            packet.source_channel.to_string(),
+        );
+        let sequence = u64::from(packet.sequence);
+        if !filter.allows(&port_id, &channel_id) {
+            continue;
+        }
+        if store.is_relayed(
+            chain_name,
+            Relayed::Timeout,
+            &port_id,
+            &channel_id,
+            sequence,
+        )? {
+            continue;
+        }
+        let key = (port_id.clone(), channel_id.clone());
+        let towards = match towards_counterparty.get(&key) {
+            Some(towards) => *towards,
+            None => {
+                let towards = channel_client_id(client, &port_id, &channel_id, block_hash).await?
+                    == counterparty_client_identifier;
+                towards_counterparty.insert(key, towards);
+                towards
+            }
+        };
+        if !towards {
+            continue;
+        }
+        let commitment = client
+            .query_packet_commitment(&port_id, &channel_id, sequence, Some(block_hash))
+            .await?;
+        if commitment.is_none() {
+            // Acknowledged or timed out already, the event of which was missed.
+            store.remove_packet(chain_name, counterparty_name, &packet)?;
+            continue;
+        }
+        relay_timeout(
+            chain_name,
+            source_tx,
+            packet,
+            block_hash,
+            client,
+            counterparty_client,
+            counterparty_height,
+            counterparty_block_hash,
+            store,
+        )
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// The identifier of the client the connection of channel `(port_id, channel_id)` on `client`
+/// is on.
+async fn channel_client_id(
+    client: &Client<Runtime>,
+    port_id: &str,
+    channel_id: &str,
+    block_hash: H256,
+) -> Result<String, Box<dyn Error>> {
+    let channel = client
+        .query_channel(port_id, channel_id, Some(block_hash))
+        .await?
+        .ok_or("channel not found")?;
+    let channel_end = ChannelEnd::decode_vec(&channel.value)?;
+    let connection_id = channel_end
+        .connection_hops()
+        .first()
+        .ok_or("channel without connection")?;
+    let connection = client
+        .query_connection(connection_id.as_str(), Some(block_hash))
+        .await?
+        .ok_or("connection not found")?;
+    Ok(ConnectionEnd::decode_vec(&connection.value)?
+        .client_id()
+        .to_string())
+}
+
+/// Relay the acknowledgement written on `client` for a packet received from the counterparty.
+pub async fn relay_write_acknowledgement(
+    chain_name: &str,
+    tx: &Sender<pallet_ibc::Any>,
+    packet: Packet,
+    ack: Vec<u8>,
+    block_hash: H256,
+    block_number: u32,
+    client: &Client<Runtime>,
+    store: &Store,
+) -> Result<(), Box<dyn Error>> {
+    info!(
+        "[{}] WriteAcknowledgement sequence: {}, ack: {:?}",
+        chain_name, packet.sequence, ack
+    );
+    store.insert_ack(chain_name, &packet, &ack)?;
+    let (port_id, channel_id) = (
+        packet.destination_port.to_string(),
+        packet.destination_channel.to_string(),
+    );
+    let sequence = u64::from(packet.sequence);
+    if store.is_relayed(chain_name, Relayed::Ack, &port_id, &channel_id, sequence)? {
+        debug!(
+            "[{}] acknowledgement {} already relayed",
+            chain_name, sequence
+        );
+        return Ok(());
+    }
+
+    send_acknowledgement(
+        chain_name,
+        tx,
+        packet,
+        ack,
+        block_hash,
+        block_number,
+        client,
+        store,
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// Send the `MsgRecvPacket` of a packet sent on `client`, proven at `block_number`, and mark it
+/// relayed.
+///
+/// Returns `false` if there is no commitment for the packet at `block_number`, either because
+/// it has been acknowledged or timed out, or because it was sent after that block.
+pub async fn send_recv_packet(
+    chain_name: &str,
+    tx: &Sender<pallet_ibc::Any>,
+    packet: Packet,
+    block_hash: H256,
+    block_number: u32,
+    client: &Client<Runtime>,
+    store: &Store,
+) -> Result<bool, Box<dyn Error>> {
+    let commitment = client
+        .query_packet_commitment(
+            packet.source_port.as_str(),
+            packet.source_channel.as_str(),
+            u64::from(packet.sequence),
+            Some(block_hash),
+        )
+        .await?;
+    let commitment = match commitment {
+        Some(commitment) => commitment,
+        None => return Ok(false),
+    };
+    let proofs = Proofs::new(
+        commitment.encoded_proof().into(),
+        None,
+        None,
+        None,
+        Height::new(0, block_number as u64),
+    )?;
+    let msg = MsgRecvPacket {
+        packet,
+        proofs,
+        signer: Signer::new(get_dummy_account_id().to_string()),
+    };
+    debug!("[{}] msg: {:?}", chain_name, msg);
+    let any = pallet_ibc::Any {
+        type_url: recv_packet::TYPE_URL.to_string(),
+        value: msg.encode_vec().unwrap(),
+    };
+    send_relayed(chain_name, tx, Relayed::Recv, &msg.packet, any, store).await?;
+
+    Ok(true)
+}
+
+/// Send the `MsgAcknowledgement` of an acknowledgement written on `client`, proven at
+/// `block_number`, and mark it relayed.
+pub async fn send_acknowledgement(
+    chain_name: &str,
+    tx: &Sender<pallet_ibc::Any>,
+    packet: Packet,
+    ack: Vec<u8>,
+    block_hash: H256,
+    block_number: u32,
+    client: &Client<Runtime>,
+    store: &Store,
+) -> Result<(), Box<dyn Error>> {
+    let acknowledgement = client
+        .query_packet_acknowledgement(
+            packet.destination_port.as_str(),
+            packet.destination_channel.as_str(),
+            u64::from(packet.sequence),
+            Some(block_hash),
+        )
+        .await?
+        .ok_or("acknowledgement not found")?;
+    let proofs = Proofs::new(
+        acknowledgement.encoded_proof().into(),
+        None,
+        None,
+        None,
+        Height::new(0, block_number as u64),
+    )?;
+    let msg = MsgAcknowledgement {
+        packet,
+        acknowledgement: ack,
+        proofs,
+        signer: Signer::new(get_dummy_account_id().to_string()),
+    };
+    debug!("[{}] msg: {:?}", chain_name, msg);
+    let any = pallet_ibc::Any {
+        type_url: acknowledgement::TYPE_URL.to_string(),
+        value: msg.encode_vec().unwrap(),
+    };
+    send_relayed(chain_name, tx, Relayed::Ack, &msg.packet, any, store).await?;
+
+    Ok(())
+}
+
+/// Time out a packet sent on `client` by proving it was not received on the counterparty at
+/// `counterparty_height`, and mark it relayed.
+///
+/// Returns `false` if the packet was received before it timed out.
+pub async fn relay_timeout(
+    chain_name: &str,
+    source_tx: &Sender<pallet_ibc::Any>,
+    packet: Packet,
+    block_hash: H256,
+    client: &Client<Runtime>,
+    counterparty_client: &Client<Runtime>,
+    counterparty_height: u32,
+    counterparty_block_hash: H256,
+    store: &Store,
+) -> Result<bool, Box<dyn Error>> {
+    info!(
+        "[{}] packet {} timed out at height {} or timestamp {}",
+        chain_name, packet.sequence, packet.timeout_height, packet.timeout_timestamp
+    );
+    let port_id = packet.source_port.to_string();
+    let channel_id = packet.source_channel.to_string();
+    let channel = client
+        .query_channel(&port_id, &channel_id, Some(block_hash))
+        .await?
+        .ok_or("channel not found")?;
+    let channel_end = ChannelEnd::decode_vec(&channel.value)?;
+
+    let destination_port = packet.destination_port.to_string();
+    let destination_channel = packet.destination_channel.to_string();
+    let (proof, next_sequence_recv) = if channel_end.ordering() == &Order::Ordered {
+        let next_sequence_recv = counterparty_client
+            .query_next_sequence_recv(
+                &destination_port,
+                &destination_channel,
+                Some(counterparty_block_hash),
+            )
+            .await?
+            .ok_or("next sequence recv not found")?;
+        let sequence = u64::decode(&mut &next_sequence_recv.value[..])?;
+        if sequence > u64::from(packet.sequence) {
+            // The packet has been received before it timed out.
+            return Ok(false);
+        }
+        (next_sequence_recv, Sequence::from(sequence))
+    } else {
+        let receipt = counterparty_client
+            .query_packet_receipt(
+                &destination_port,
+                &destination_channel,
+                u64::from(packet.sequence),
+                Some(counterparty_block_hash),
+            )
+            .await?;
+        if !receipt.value.is_empty() {
+            // The packet has been received before it timed out.
+            return Ok(false);
+        }
+        (receipt, packet.sequence)
+    };
+
+    let proofs = Proofs::new(
+        proof.encoded_proof().into(),
+        None,
+        None,
+        None,
+        Height::new(0, counterparty_height as u64),
+    )?;
+    let msg = MsgTimeout {
+        packet,
+        next_sequence_recv,
+        proofs,
+        signer: Signer::new(get_dummy_account_id().to_string()),
+    };
+    debug!("[{}] msg: {:?}", chain_name, msg);
+    let any = pallet_ibc::Any {
+        type_url: timeout::TYPE_URL.to_string(),
+        value: msg.encode_vec().unwrap(),
+    };
+    send_relayed(
+        chain_name,
+        source_tx,
+        Relayed::Timeout,
+        &msg.packet,
+        any,
+        store,
+    )
+    .await?;
+
+    Ok(true)
+}
+
+/// Send `any`, relaying `relayed` for `packet`, with its mark in `store` in flight until the
+/// delivery task settles it.
+async fn send_relayed(
+    chain_name: &str,
+    tx: &Sender<pallet_ibc::Any>,
+    relayed: Relayed,
+    packet: &Packet,
+    any: pallet_ibc::Any,
+    store: &Store,
+) -> Result<(), Box<dyn Error>> {
+    let (port_id, channel_id) = match relayed {
+        Relayed::Recv | Relayed::Timeout => (&packet.source_port, &packet.source_channel),
+        Relayed::Ack => (&packet.destination_port, &packet.destination_channel),
+    };
+    store.set_relayed(
+        chain_name,
+        relayed,
+        port_id.as_str(),
+        channel_id.as_str(),
+        u64::from(packet.sequence),
+        &any,
+    )?;
+    if let Err(e) = tx.send(any.clone()).await {
+        store.settle(&any, false)?;
+        return Err(e.into());
+    }
+    Ok(())
+}
+
+/// Time out the packets sent from the counterparty on a channel that has been closed on
+/// `client` before they were received.
+///
+/// `channel` is the proof of the closed channel end `(port_id, channel_id)` on `client`. On an
+/// ordered channel, the packets not received are those from its next sequence to receive on; on
+/// an unordered channel, each packet is checked for, and proven to lack, a receipt. Each packet
+/// is timed out once, with the same mark in `store` as by `MsgTimeout`.
+pub async fn relay_timeouts_on_close(
+    chain_name: &str,
+    counterparty_name: &str,
+    tx: &Sender<pallet_ibc::Any>,
+    port_id: &str,
+    channel_id: &str,
+    channel: &StateResponse<H256>,
+    order: &Order,
+    counterparty_port_id: &str,
+    counterparty_channel_id: &str,
+    block_hash: H256,
+    block_number: u32,
+    client: &Client<Runtime>,
+    counterparty_client: &Client<Runtime>,
+    store: &Store,
+) -> Result<(), Box<dyn Error>> {
+    let commitments = counterparty_client
+        .query_packet_commitments(counterparty_port_id, counterparty_channel_id, None)
+        .await?;
+    if commitments.is_empty() {
+        return Ok(());
+    }
+    let next_sequence_recv = if order == &Order::Ordered {
+        let next_sequence_recv = client
+            .query_next_sequence_recv(port_id, channel_id, Some(block_hash))
+            .await?
+            .ok_or("next sequence recv not found")?;
+        let sequence = u64::decode(&mut &next_sequence_recv.value[..])?;
+        Some((next_sequence_recv, sequence))
+    } else {
+        None
+    };
+
+    for (commitment_sequence, _) in commitments.into_iter() {
+        // Marked on the chain the packet was sent on, as `MsgTimeout` is.
+        if store.is_relayed(
+            counterparty_name,
+            Relayed::Timeout,
+            counterparty_port_id,
+            counterparty_channel_id,
+            commitment_sequence,
+        )? {
+            continue;
+        }
+        let (proof, sequence) = match &next_sequence_recv {
+            Some((next_sequence_recv, sequence)) => {
+                if commitment_sequence < *sequence {
+                    // Received, only its acknowledgement is still to be relayed.
+                    continue;
+                }
+                (next_sequence_recv.clone(), Sequence::from(*sequence))
+            }
+            None => {
+                let receipt = client
+                    .query_packet_receipt(
+                        port_id,
+                        channel_id,
+                        commitment_sequence,
+                        Some(block_hash),
+                    )
+                    .await?;
+                if !receipt.value.is_empty() {
+                    // Received, only its acknowledgement is still to be relayed.
+                    continue;
+                }
+                (receipt, Sequence::from(commitment_sequence))
+            }
+        };
+        let packet = store.packet(
+            counterparty_name,
+            counterparty_port_id,
+            counterparty_channel_id,
+            commitment_sequence,
+        )?;
+        let packet = match packet {
+            Some(packet) => packet,
+            None => {
+                info!(
+                    "[{}] can not time out unknown packet {}",
+                    chain_name, commitment_sequence
+                );
+                continue;
+            }
+        };
+        info!(
+            "[{}] packet {} timed out on closed channel",
+            chain_name, packet.sequence
+        );
+        let msg = MsgTimeoutOnClose {
+            packet,
+            next_sequence_recv: sequence,
+            proofs: Proofs::new(
+                proof.encoded_proof().into(),
+                None,
+                None,
+                Some(channel.encoded_proof().into()),
+                Height::new(0, block_number as u64),
+            )?,
+            signer: Signer::new(get_dummy_account_id().to_string()),
+        };
+        debug!("[{}] msg: {:?}", chain_name, msg);
+        let any = pallet_ibc::Any {
+            type_url: timeout_on_close::TYPE_URL.to_string(),
+            value: msg.encode_vec().unwrap(),
+        };
+        send_relayed(
+            counterparty_name,
+            tx,
+            Relayed::Timeout,
+            &msg.packet,
+            any,
+            store,
+        )
+        .await?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,47 @@
+use super::timed_out;
+use ibc::ics02_client::height::Height;
+use ibc::ics04_channel::packet::{Packet, Sequence};
+use ibc::ics24_host::identifier::{ChannelId, PortId};
+use std::str::FromStr;
+
+fn packet(timeout_height: u64, timeout_timestamp: u64) -> Packet {
+    Packet {
+        sequence: Sequence::from(1),
+        source_port: PortId::from_str("transfer").unwrap(),
+        source_channel: ChannelId::new(0),
+        destination_port: PortId::from_str("transfer").unwrap(),
+        destination_channel: ChannelId::new(1),
+        data: vec![],
+        timeout_height: Height::new(0, timeout_height),
+        timeout_timestamp,
+    }
+}
+
+#[test]
+fn times_out_at_the_timeout_height() {
+    let packet = packet(100, 0);
+    assert!(!timed_out(&packet, 99, u64::MAX));
+    assert!(timed_out(&packet, 100, 0));
+    assert!(timed_out(&packet, 101, 0));
+}
+
+#[test]
+fn times_out_a_timestamp_only_packet_at_the_timeout_timestamp() {
+    let packet = packet(0, 5_000_000_000);
+    assert!(!timed_out(&packet, u32::MAX, 4_999_999_999));
+    assert!(timed_out(&packet, 0, 5_000_000_000));
+    assert!(timed_out(&packet, 0, 6_000_000_000));
+}
+
+#[test]
+fn times_out_at_whichever_timeout_comes_first() {
+    let packet = packet(100, 5_000_000_000);
+    assert!(!timed_out(&packet, 99, 4_999_999_999));
+    assert!(timed_out(&packet, 100, 4_999_999_999));
+    assert!(timed_out(&packet, 99, 5_000_000_000));
+}
+
+#[test]
+fn never_times_out_without_a_timeout() {
+    assert!(!timed_out(&packet(0, 0), u32::MAX, u64::MAX));
+}
@@ -0,0 +1,394 @@
+//! The relayer's persistent state, so that a restarted relayer resumes where it stopped.
+//!
+//! Everything lives in one sled database under the configured data dir:
+//! - `heights`: the last finalized block of a chain processed for each relay path, and checked
+//! for misbehaviour of each light client on it,
+//! - `pending`: messages handed to a delivery task but not yet finalized on chain,
+//! - `failed`: messages given up on, with the reason,
+//! - `packets`: packets sent but not yet acknowledged or timed out,
+//! - `acks`: acknowledgements written, which are needed again if relaying them failed, until
+//! their packet is acknowledged or timed out,
+//! - `relayed`: the packets, acknowledgements and timeouts, and the connection and channel
+//! handshake steps, already relayed, or in flight,
+//! - `in_flight`: the mark in `relayed` of each message in flight, by the hash of the message.
+use codec::{Decode, Encode};
+use ibc::ics04_channel::packet::Packet;
+use sp_core::hashing::blake2_256;
+use std::error::Error;
+use std::path::Path;
+use tendermint_proto::Protobuf;
+
+/// What has been relayed for a packet.
+#[derive(Clone, Copy, Debug)]
+pub enum Relayed {
+    /// The `MsgRecvPacket` of a packet sent on the chain.
+    Recv,
+    /// The `MsgAcknowledgement` of an acknowledgement written on the chain.
+    Ack,
+    /// The `MsgTimeout` of a packet sent on the chain.
+    Timeout,
+}
+
+/// A connection or channel handshake message relayed for an end on a chain.
+#[derive(Clone, Copy, Debug)]
+pub enum Handshake {
+    ConnOpenTry,
+    ConnOpenAck,
+    ConnOpenConfirm,
+    ChanOpenTry,
+    ChanOpenAck,
+    ChanOpenConfirm,
+    ChanCloseConfirm,
+}
+
+#[derive(Clone)]
+pub struct Store {
+    db: sled::Db,
+    heights: sled::Tree,
+    pending: sled::Tree,
+    failed: sled::Tree,
+    packets: sled::Tree,
+    acks: sled::Tree,
+    relayed: sled::Tree,
+    in_flight: sled::Tree,
+}
+
+/// `chain/port/channel/` followed by the big endian sequence, so that keys sort by sequence.
+fn packet_key(chain: &str, port_id: &str, channel_id: &str, sequence: u64) -> Vec<u8> {
+    let mut key = format!("{}/{}/{}/", chain, port_id, channel_id).into_bytes();
+    key.extend(&sequence.to_be_bytes());
+    key
+}
+
+fn relayed_key(
+    chain: &str,
+    relayed: Relayed,
+    port_id: &str,
+    channel_id: &str,
+    sequence: u64,
+) -> Vec<u8> {
+    let kind = match relayed {
+        Relayed::Recv => "recv",
+        Relayed::Ack => "ack",
+        Relayed::Timeout => "timeout",
+    };
+    packet_key(
+        &format!("{}/{}", chain, kind),
+        port_id,
+        channel_id,
+        sequence,
+    )
+}
+
+/// `chain/handshake/step/end`, where `end` is the connection id, or the port and channel ids, of
+/// the end on `chain` the message is relayed for.
+fn handshake_key(chain: &str, handshake: Handshake, end: &str) -> Vec<u8> {
+    let step = match handshake {
+        Handshake::ConnOpenTry => "conn_open_try",
+        Handshake::ConnOpenAck => "conn_open_ack",
+        Handshake::ConnOpenConfirm => "conn_open_confirm",
+        Handshake::ChanOpenTry => "chan_open_try",
+        Handshake::ChanOpenAck => "chan_open_ack",
+        Handshake::ChanOpenConfirm => "chan_open_confirm",
+        Handshake::ChanCloseConfirm => "chan_close_confirm",
+    };
+    format!("{}/handshake/{}/{}", chain, step, end).into_bytes()
+}
+
+fn pending_key(chain: &str, id: u64) -> Vec<u8> {
+    let mut key = format!("{}/", chain).into_bytes();
+    key.extend(&id.to_be_bytes());
+    key
+}
+
+impl Store {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, Box<dyn Error>> {
+        let db = sled::open(path)?;
+        Ok(Store {
+            heights: db.open_tree("heights")?,
+            pending: db.open_tree("pending")?,
+            failed: db.open_tree("failed")?,
+            packets: db.open_tree("packets")?,
+            acks: db.open_tree("acks")?,
+            relayed: db.open_tree("relayed")?,
+            in_flight: db.open_tree("in_flight")?,
+            db,
+        })
+    }
+
+    /// Write everything to disk, before the relayer exits.
+    pub fn flush(&self) -> Result<(), Box<dyn Error>> {
+        self.db.flush()?;
+        Ok(())
+    }
+
+    /// The last finalized block of `chain` processed for the relay path to `counterparty`.
+    pub fn last_height(
+        &self,
+        chain: &str,
+        counterparty: &str,
+    ) -> Result<Option<u32>, Box<dyn Error>> {
+        let key = format!("{}/{}", chain, counterparty);
+        match self.heights.get(key)? {
+            Some(value) => Ok(Some(u32::decode(&mut &value[..])?)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn set_last_height(
+        &self,
+        chain: &str,
+        counterparty: &str,
+        height: u32,
+    ) -> Result<(), Box<dyn Error>> {
+        let key = format!("{}/{}", chain, counterparty);
+        self.heights.insert(key, height.encode())?;
+        Ok(())
+    }
+
+    /// The last finalized block of `chain` whose updates of `client_id` were checked for
+    /// misbehaviour.
+    pub fn last_checked_height(
+        &self,
+        chain: &str,
+        client_id: &str,
+    ) -> Result<Option<u32>, Box<dyn Error>> {
+        let key = format!("misbehaviour/{}/{}", chain, client_id);
+        match self.heights.get(key)? {
+            Some(value) => Ok(Some(u32::decode(&mut &value[..])?)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn set_last_checked_height(
+        &self,
+        chain: &str,
+        client_id: &str,
+        height: u32,
+    ) -> Result<(), Box<dyn Error>> {
+        let key = format!("misbehaviour/{}/{}", chain, client_id);
+        self.heights.insert(key, height.encode())?;
+        Ok(())
+    }
+
+    /// Record a message about to be submitted to `chain`, returning the id to remove it by.
+    pub fn push_pending(&self, chain: &str, any: &pallet_ibc::Any) -> Result<u64, Box<dyn Error>> {
+        let id = self.db.generate_id()?;
+        self.pending.insert(pending_key(chain, id), any.encode())?;
+        Ok(id)
+    }
+
+    pub fn remove_pending(&self, chain: &str, id: u64) -> Result<(), Box<dyn Error>> {
+        self.pending.remove(pending_key(chain, id))?;
+        Ok(())
+    }
+
+    /// Remove and return the messages left pending for `chain`, oldest first.
+    pub fn take_pending(&self, chain: &str) -> Result<Vec<pallet_ibc::Any>, Box<dyn Error>> {
+        let mut messages = Vec::new();
+        for entry in self.pending.scan_prefix(format!("{}/", chain)) {
+            let (key, value) = entry?;
+            messages.push(pallet_ibc::Any::decode(&mut &value[..])?);
+            self.pending.remove(key)?;
+        }
+        Ok(messages)
+    }
+
+    /// Record a message to `chain` given up on, for an operator to look into.
+    pub fn push_failed(
+        &self,
+        chain: &str,
+        any: &pallet_ibc::Any,
+        reason: &str,
+    ) -> Result<(), Box<dyn Error>> {
+        let id = self.db.generate_id()?;
+        self.failed
+            .insert(pending_key(chain, id), (any, reason).encode())?;
+        Ok(())
+    }
+
+    /// Remember a packet sent on `chain` until it is acknowledged or timed out, as commitments
+    /// on chain only hold its hash.
+    pub fn insert_packet(&self, chain: &str, packet: &Packet) -> Result<(), Box<dyn Error>> {
+        let key = packet_key(
+            chain,
+            packet.source_port.as_str(),
+            packet.source_channel.as_str(),
+            u64::from(packet.sequence),
+        );
+        self.packets.insert(key, packet.encode_vec()?)?;
+        Ok(())
+    }
+
+    pub fn packet(
+        &self,
+        chain: &str,
+        port_id: &str,
+        channel_id: &str,
+        sequence: u64,
+    ) -> Result<Option<Packet>, Box<dyn Error>> {
+        match self
+            .packets
+            .get(packet_key(chain, port_id, channel_id, sequence))?
+        {
+            Some(value) => Ok(Some(Packet::decode_vec(&value)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// The packets sent on `chain` not yet acknowledged or timed out.
+    pub fn packets(&self, chain: &str) -> Result<Vec<Packet>, Box<dyn Error>> {
+        let mut packets = Vec::new();
+        for entry in self.packets.scan_prefix(format!("{}/", chain)) {
+            let (_key, value) = entry?;
+            packets.push(Packet::decode_vec(&value)?);
+        }
+        Ok(packets)
+    }
+
+    /// Forget a packet sent on `chain` to `counterparty` once it has been acknowledged or timed
+    /// out, with its acknowledgement and what has been relayed for it on either end.
+    pub fn remove_packet(
+        &self,
+        chain: &str,
+        counterparty: &str,
+        packet: &Packet,
+    ) -> Result<(), Box<dyn Error>> {
+        let (port_id, channel_id) = (packet.source_port.as_str(), packet.source_channel.as_str());
+        let sequence = u64::from(packet.sequence);
+        self.packets
+            .remove(packet_key(chain, port_id, channel_id, sequence))?;
+        for relayed in [Relayed::Recv, Relayed::Timeout].iter() {
+            self.relayed
+                .remove(relayed_key(chain, *relayed, port_id, channel_id, sequence))?;
+        }
+        let (destination_port, destination_channel) = (
+            packet.destination_port.as_str(),
+            packet.destination_channel.as_str(),
+        );
+        self.acks.remove(packet_key(
+            counterparty,
+            destination_port,
+            destination_channel,
+            sequence,
+        ))?;
+        self.relayed.remove(relayed_key(
+            counterparty,
+            Relayed::Ack,
+            destination_port,
+            destination_channel,
+            sequence,
+        ))?;
+        Ok(())
+    }
+
+    /// Remember the acknowledgement written on `chain` for a received packet, as acknowledgements
+    /// on chain only hold its hash.
+    pub fn insert_ack(
+        &self,
+        chain: &str,
+        packet: &Packet,
+        ack: &[u8],
+    ) -> Result<(), Box<dyn Error>> {
+        let key = packet_key(
+            chain,
+            packet.destination_port.as_str(),
+            packet.destination_channel.as_str(),
+            u64::from(packet.sequence),
+        );
+        self.acks.insert(key, ack)?;
+        Ok(())
+    }
+
+    /// The acknowledgement written on `chain` for `(port_id, channel_id, sequence)`, keyed by the
+    /// packet's destination end.
+    pub fn ack(
+        &self,
+        chain: &str,
+        port_id: &str,
+        channel_id: &str,
+        sequence: u64,
+    ) -> Result<Option<Vec<u8>>, Box<dyn Error>> {
+        Ok(self
+            .acks
+            .get(packet_key(chain, port_id, channel_id, sequence))?
+            .map(|value| value.to_vec()))
+    }
+
+    /// Whether `relayed` was already done, or is in flight, for `(port_id, channel_id, sequence)`
+    /// on `chain`.
+    ///
+    /// Packets are keyed by their source end for `Relayed::Recv` and `Relayed::Timeout`, and by
+    /// their destination end for `Relayed::Ack`.
+    pub fn is_relayed(
+        &self,
+        chain: &str,
+        relayed: Relayed,
+        port_id: &str,
+        channel_id: &str,
+        sequence: u64,
+    ) -> Result<bool, Box<dyn Error>> {
+        let key = relayed_key(chain, relayed, port_id, channel_id, sequence);
+        Ok(self.relayed.contains_key(key)?)
+    }
+
+    /// Mark `relayed` as in flight with the message `any`, until `settle` keeps or drops the
+    /// mark once the message is finalized or given up on.
+    pub fn set_relayed(
+        &self,
+        chain: &str,
+        relayed: Relayed,
+        port_id: &str,
+        channel_id: &str,
+        sequence: u64,
+        any: &pallet_ibc::Any,
+    ) -> Result<(), Box<dyn Error>> {
+        let key = relayed_key(chain, relayed, port_id, channel_id, sequence);
+        self.set_in_flight(key, any)
+    }
+
+    /// Whether `handshake` was already relayed, or is in flight, for the end `end` on `chain`.
+    ///
+    /// Marks are kept once finalized, as the end on `chain` may stay in the same state after,
+    /// e.g. in `Init` until the counterparty's `TryOpen` is acknowledged.
+    pub fn is_handshake_relayed(
+        &self,
+        chain: &str,
+        handshake: Handshake,
+        end: &str,
+    ) -> Result<bool, Box<dyn Error>> {
+        Ok(self
+            .relayed
+            .contains_key(handshake_key(chain, handshake, end))?)
+    }
+
+    /// Mark `handshake` for the end `end` on `chain` as in flight with the message `any`, until
+    /// `settle` keeps or drops the mark.
+    pub fn set_handshake_relayed(
+        &self,
+        chain: &str,
+        handshake: Handshake,
+        end: &str,
+        any: &pallet_ibc::Any,
+    ) -> Result<(), Box<dyn Error>> {
+        self.set_in_flight(handshake_key(chain, handshake, end), any)
+    }
+
+    fn set_in_flight(&self, key: Vec<u8>, any: &pallet_ibc::Any) -> Result<(), Box<dyn Error>> {
+        self.in_flight
+            .insert(blake2_256(&any.encode()), key.as_slice())?;
+        self.relayed.insert(key, &[])?;
+        Ok(())
+    }
+
+    /// Keep the mark of the message `any` if it was `finalized`, and drop it otherwise so that
+    /// the message is relayed again. Nothing is done for a message without a mark.
+    pub fn settle(&self, any: &pallet_ibc::Any, finalized: bool) -> Result<(), Box<dyn Error>> {
+        if let Some(key) = self.in_flight.remove(blake2_256(&any.encode()))? {
+            if !finalized {
+                self.relayed.remove(key)?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,534 @@
+//! Submission of `deliver` extrinsics to a chain, each watched until it is finalized.
+//!
+//! `deliver` does not revert the messages dispatched before one that fails, so it is wrapped in
+//! `Utility::batch_all`: an extrinsic that fails to dispatch changes nothing, and its messages
+//! can be delivered again in smaller batches down to the one that fails.
+//!
+//! Up to `max_in_flight` extrinsics are submitted with consecutive nonces before the first is
+//! finalized. Once one fails, the others in flight are waited for, the signer's nonce is
+//! resynced from the chain, and the failed batches are delivered again one at a time, so that
+//! one failure does not leave a nonce gap every later extrinsic is rejected for.
+use crate::chain::Chain;
+use crate::metrics::{self, METRICS};
+use crate::store::Store;
+use async_std::task::{self, JoinHandle};
+use calls::{
+    author::AuthorRpcExt, ibc::DeliverCall, utility::BatchAllCall, NodeRuntime as Runtime,
+};
+use core::marker::PhantomData;
+use frame_system::Phase;
+use ibc::ics04_channel::msgs::{acknowledgement, recv_packet, timeout, timeout_on_close};
+use log::{debug, error, info, warn};
+use serde_derive::Deserialize;
+use sp_core::{sr25519, H256};
+use sp_runtime::traits::{BlakeTwo256, Hash};
+use sp_transaction_pool::TransactionStatus;
+use std::cmp;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+use substrate_subxt::{system::AccountStoreExt, Client, PairSigner, RuntimeError, Signer as _};
+
+/// How failed submissions are retried.
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub struct RetryConfig {
+    /// Retries of a batch before its messages are given up on.
+    #[serde(default = "default_max_retries")]
+    pub max_retries: u32,
+    /// The delay before the first retry, in seconds, doubled on every retry.
+    #[serde(default = "default_initial_backoff")]
+    pub initial_backoff: u64,
+    /// The longest delay between retries, in seconds.
+    #[serde(default = "default_max_backoff")]
+    pub max_backoff: u64,
+}
+
+fn default_max_retries() -> u32 {
+    5
+}
+
+fn default_initial_backoff() -> u64 {
+    2
+}
+
+fn default_max_backoff() -> u64 {
+    60
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        RetryConfig {
+            max_retries: default_max_retries(),
+            initial_backoff: default_initial_backoff(),
+            max_backoff: default_max_backoff(),
+        }
+    }
+}
+
+/// Why a batch could not be delivered as is.
+enum Failure {
+    /// The pool rejected it for exceeding the block weight or length; its nonce is still unused.
+    ExhaustsResources(substrate_subxt::Error),
+    /// It was included, but one of its messages failed to dispatch, which reverted the others.
+    Dispatch(substrate_subxt::Error),
+    /// It was still not finalized after every retry.
+    GaveUp(substrate_subxt::Error),
+}
+
+/// The hash of the block an extrinsic was finalized in, or why it was not.
+type Finalized = Result<H256, substrate_subxt::Error>;
+
+/// An extrinsic submitted and not yet finalized.
+struct InFlight {
+    batch: Vec<pallet_ibc::Any>,
+    ids: Vec<u64>,
+    handle: JoinHandle<Finalized>,
+}
+
+pub struct Submitter {
+    chain_name: String,
+    chain: Chain,
+    signer: PairSigner<Runtime, sr25519::Pair>,
+    tmp: u8,
+    store: Store,
+    retry_config: RetryConfig,
+    max_in_flight: usize,
+    in_flight: VecDeque<InFlight>,
+}
+
+impl Submitter {
+    pub async fn new(
+        chain: Chain,
+        pair: sr25519::Pair,
+        tmp: u8,
+        store: Store,
+        retry_config: RetryConfig,
+        max_in_flight: usize,
+    ) -> Result<Self, substrate_subxt::Error> {
+        let mut submitter = Submitter {
+            chain_name: chain.name.clone(),
+            chain,
+            signer: PairSigner::new(pair),
+            tmp,
+            store,
+            retry_config,
+            max_in_flight: cmp::max(max_in_flight, 1),
+            in_flight: VecDeque::new(),
+        };
+        submitter.sync_nonce().await?;
+        Ok(submitter)
+    }
+
+    async fn sync_nonce(&mut self) -> Result<(), substrate_subxt::Error> {
+        let account_id = self.signer.account_id().clone();
+        let nonce = self
+            .chain
+            .client()
+            .await
+            .account(&account_id, None)
+            .await?
+            .nonce;
+        debug!("[relayer => {}] nonce: {}", self.chain_name, nonce);
+        self.signer.set_nonce(nonce);
+        Ok(())
+    }
+
+    /// Whether extrinsics submitted are still waiting to be finalized.
+    pub fn has_in_flight(&self) -> bool {
+        !self.in_flight.is_empty()
+    }
+
+    /// Submit `messages` in one extrinsic, without waiting for it to be finalized unless
+    /// `max_in_flight` extrinsics already are; the oldest of those is waited for first.
+    pub async fn submit(&mut self, messages: Vec<pallet_ibc::Any>) {
+        if self.in_flight.len() >= self.max_in_flight {
+            self.finish_oldest().await;
+        }
+        debug!(
+            "[relayer => {}] {} msgs: {:?}",
+            self.chain_name,
+            messages.len(),
+            messages
+        );
+        let ids = self.push_pending(&messages);
+        let signer = self.signer.clone();
+        self.signer.increment_nonce();
+        let handle = task::spawn(deliver_and_watch(
+            self.chain.clone(),
+            signer,
+            messages.clone(),
+            self.tmp,
+        ));
+        self.in_flight.push_back(InFlight {
+            batch: messages,
+            ids,
+            handle,
+        });
+    }
+
+    /// Wait for every extrinsic in flight to be finalized or given up on.
+    pub async fn flush(&mut self) {
+        while self.has_in_flight() {
+            self.finish_oldest().await;
+        }
+    }
+
+    /// Wait for the oldest extrinsic in flight. If it failed, wait for the others too, then
+    /// deliver the batches that failed again one at a time.
+    pub async fn finish_oldest(&mut self) {
+        let in_flight = match self.in_flight.pop_front() {
+            Some(in_flight) => in_flight,
+            None => return,
+        };
+        let (mut failed, nonce_used) = match in_flight.handle.await {
+            Ok(block_hash) => {
+                self.finalized(in_flight.batch, in_flight.ids, block_hash);
+                return;
+            }
+            Err(e) => {
+                self.count_failure(&e);
+                let nonce_used = matches!(e, substrate_subxt::Error::Runtime(_));
+                (vec![(in_flight.batch, in_flight.ids, e)], nonce_used)
+            }
+        };
+        // The extrinsics after a failed one may fail to dispatch for the messages of the failed
+        // one they depend on. If its nonce was not used, they wait on it forever, so they are
+        // not waited for unless already done, and delivered again with the failed one.
+        while let Some(in_flight) = self.in_flight.pop_front() {
+            let result = if nonce_used {
+                Some(in_flight.handle.await)
+            } else {
+                in_flight.handle.cancel().await
+            };
+            match result {
+                Some(Ok(block_hash)) => self.finalized(in_flight.batch, in_flight.ids, block_hash),
+                Some(Err(e)) => {
+                    self.count_failure(&e);
+                    failed.push((in_flight.batch, in_flight.ids, e));
+                }
+                None => failed.push((
+                    in_flight.batch,
+                    in_flight.ids,
+                    substrate_subxt::Error::Other("submitted after a failed extrinsic".into()),
+                )),
+            }
+        }
+        self.reconnect().await;
+        if let Err(e) = self.sync_nonce().await {
+            error!(
+                "[relayer => {}] failed to resync nonce; error = {}",
+                self.chain_name, e
+            );
+        }
+        for (batch, ids, e) in failed {
+            info!(
+                "[relayer => {}] delivering {} msgs again; error = {}",
+                self.chain_name,
+                batch.len(),
+                e
+            );
+            self.remove_pending(ids);
+            self.deliver(batch).await;
+        }
+    }
+
+    /// Deliver `messages` in one extrinsic and wait for it to be finalized.
+    ///
+    /// A batch the chain rejects for exceeding its block limits, or that fails to dispatch, is
+    /// split in halves and the halves are delivered separately, down to the message that fails
+    /// on its own. Messages given up on are logged and moved from the pending messages of the
+    /// store to its failed ones, and their relayed marks dropped, so that they are relayed again.
+    async fn deliver(&mut self, messages: Vec<pallet_ibc::Any>) {
+        let mut batches = vec![messages];
+        while let Some(mut batch) = batches.pop() {
+            debug!(
+                "[relayer => {}] {} msgs: {:?}",
+                self.chain_name,
+                batch.len(),
+                batch
+            );
+            let ids = self.push_pending(&batch);
+
+            let e = match self.submit_batch(&batch).await {
+                Ok(block_hash) => {
+                    self.finalized(batch, ids, block_hash);
+                    continue;
+                }
+                Err(Failure::ExhaustsResources(e)) | Err(Failure::Dispatch(e))
+                    if batch.len() > 1 =>
+                {
+                    info!(
+                        "[relayer => {}] splitting batch of {} msgs; error = {}",
+                        self.chain_name,
+                        batch.len(),
+                        e
+                    );
+                    self.remove_pending(ids);
+                    let second_half = batch.split_off(batch.len() / 2);
+                    batches.push(second_half);
+                    batches.push(batch);
+                    continue;
+                }
+                Err(Failure::ExhaustsResources(e))
+                | Err(Failure::Dispatch(e))
+                | Err(Failure::GaveUp(e)) => e,
+            };
+            self.remove_pending(ids);
+            self.settle(&batch, false);
+            for any in batch.iter() {
+                error!(
+                    "[relayer => {}] gave up on {} msg; error = {}",
+                    self.chain_name, any.type_url, e
+                );
+                if let Err(e) = self
+                    .store
+                    .push_failed(&self.chain_name, any, &e.to_string())
+                {
+                    error!(
+                        "[relayer => {}] failed to store msg; error = {}",
+                        self.chain_name, e
+                    );
+                }
+            }
+        }
+    }
+
+    async fn submit_batch(&mut self, batch: &[pallet_ibc::Any]) -> Result<H256, Failure> {
+        let mut backoff = Duration::from_secs(self.retry_config.initial_backoff);
+        let mut retries = 0;
+        loop {
+            let result = deliver_and_watch(
+                self.chain.clone(),
+                self.signer.clone(),
+                batch.to_vec(),
+                self.tmp,
+            )
+            .await;
+            if let Err(e) = &result {
+                self.count_failure(e);
+            }
+            let e = match result {
+                Ok(block_hash) => {
+                    self.signer.increment_nonce();
+                    return Ok(block_hash);
+                }
+                Err(e @ substrate_subxt::Error::Runtime(_)) => {
+                    self.signer.increment_nonce();
+                    return Err(Failure::Dispatch(e));
+                }
+                Err(e) if exhausts_resources(&e) => return Err(Failure::ExhaustsResources(e)),
+                Err(e) => e,
+            };
+            if retries == self.retry_config.max_retries {
+                return Err(Failure::GaveUp(e));
+            }
+            retries += 1;
+            warn!(
+                "[relayer => {}] failed to deliver msgs, retry {} in {:?}; error = {}",
+                self.chain_name, retries, backoff, e
+            );
+            async_std::task::sleep(backoff).await;
+            backoff = cmp::min(
+                backoff * 2,
+                Duration::from_secs(self.retry_config.max_backoff),
+            );
+            // The connection may have dropped, and the extrinsic with it, or it may have been
+            // included with a nonce another one used.
+            self.reconnect().await;
+            if let Err(e) = self.sync_nonce().await {
+                error!(
+                    "[relayer => {}] failed to resync nonce; error = {}",
+                    self.chain_name, e
+                );
+            }
+        }
+    }
+
+    /// Reconnect if the connection dropped. A chain still unreachable is left to the next
+    /// submission to fail on.
+    async fn reconnect(&self) {
+        if let Err(e) = self.chain.ensure_connected().await {
+            error!("[relayer => {}] {}", self.chain_name, e);
+        }
+    }
+
+    /// Record the messages of `batch` as delivered in the finalized block `block_hash`.
+    fn finalized(&self, batch: Vec<pallet_ibc::Any>, ids: Vec<u64>, block_hash: H256) {
+        for any in batch.iter() {
+            if let Some(kind) = packet_kind(&any.type_url) {
+                METRICS
+                    .packets
+                    .with_label_values(&[&self.chain_name, kind])
+                    .inc();
+            }
+        }
+        info!(
+            "[relayer => {}] {} msgs finalized in block {:?}",
+            self.chain_name,
+            batch.len(),
+            block_hash
+        );
+        self.remove_pending(ids);
+        self.settle(&batch, true);
+    }
+
+    fn count_failure(&self, e: &substrate_subxt::Error) {
+        METRICS
+            .extrinsic_failures
+            .with_label_values(&[&self.chain_name, error_kind(e)])
+            .inc();
+    }
+
+    /// Keep the relayed marks of the messages of `batch` if it was `finalized`, drop them
+    /// otherwise.
+    fn settle(&self, batch: &[pallet_ibc::Any], finalized: bool) {
+        for any in batch {
+            if let Err(e) = self.store.settle(any, finalized) {
+                error!(
+                    "[relayer => {}] failed to store relayed mark; error = {}",
+                    self.chain_name, e
+                );
+            }
+        }
+    }
+
+    fn push_pending(&self, batch: &[pallet_ibc::Any]) -> Vec<u64> {
+        batch
+            .iter()
+            .filter_map(|any| match self.store.push_pending(&self.chain_name, any) {
+                Ok(id) => Some(id),
+                Err(e) => {
+                    error!(
+                        "[relayer => {}] failed to store msg; error = {}",
+                        self.chain_name, e
+                    );
+                    None
+                }
+            })
+            .collect()
+    }
+
+    fn remove_pending(&self, ids: Vec<u64>) {
+        for id in ids {
+            if let Err(e) = self.store.remove_pending(&self.chain_name, id) {
+                error!(
+                    "[relayer => {}] failed to store msg; error = {}",
+                    self.chain_name, e
+                );
+            }
+        }
+    }
+}
+
+/// Deliver `messages` to `chain` in one `batch_all` extrinsic signed by `signer`, and wait for
+/// it to be finalized.
+///
+/// Only the submission is timed as an RPC request; the time the extrinsic then takes to be
+/// included in a block and finalized is recorded in the extrinsic latency metric.
+async fn deliver_and_watch(
+    chain: Chain,
+    signer: PairSigner<Runtime, sr25519::Pair>,
+    messages: Vec<pallet_ibc::Any>,
+    tmp: u8,
+) -> Finalized {
+    let client = chain.client().await;
+    let deliver = client.encode(DeliverCall::<Runtime> {
+        _runtime: PhantomData,
+        messages,
+        tmp,
+    })?;
+    let extrinsic = client
+        .create_signed(
+            BatchAllCall::<Runtime> {
+                _runtime: PhantomData,
+                calls: vec![deliver],
+            },
+            &signer,
+        )
+        .await?;
+    let submitted = Instant::now();
+    let mut statuses = metrics::time_rpc(
+        &chain.name,
+        "author_submitAndWatchExtrinsic",
+        client.submit_and_watch(&extrinsic),
+    )
+    .await?;
+    loop {
+        match statuses.next().await {
+            TransactionStatus::InBlock(_) => {
+                metrics::observe_extrinsic(&chain.name, "in_block", submitted)
+            }
+            TransactionStatus::Finalized(block_hash) => {
+                metrics::observe_extrinsic(&chain.name, "finalized", submitted);
+                dispatch_result(&client, block_hash, BlakeTwo256::hash_of(&extrinsic)).await?;
+                return Ok(block_hash);
+            }
+            TransactionStatus::Future
+            | TransactionStatus::Ready
+            | TransactionStatus::Broadcast(_)
+            | TransactionStatus::Retracted(_) => {}
+            status => {
+                return Err(substrate_subxt::Error::Other(format!(
+                    "extrinsic left the pool: {:?}",
+                    status
+                )))
+            }
+        }
+    }
+}
+
+/// Fail with the error extrinsic `extrinsic_hash` failed to dispatch with in block
+/// `block_hash` of `client`, if it did.
+async fn dispatch_result(
+    client: &Client<Runtime>,
+    block_hash: H256,
+    extrinsic_hash: H256,
+) -> Result<(), substrate_subxt::Error> {
+    let block = client
+        .block(Some(block_hash))
+        .await?
+        .ok_or_else(|| substrate_subxt::Error::Other("finalized block not found".into()))?;
+    let index = block
+        .block
+        .extrinsics
+        .iter()
+        .position(|extrinsic| BlakeTwo256::hash_of(extrinsic) == extrinsic_hash)
+        .ok_or_else(|| substrate_subxt::Error::Other("extrinsic not in its block".into()))?;
+    for record in crate::event_records(client, block_hash).await? {
+        if record.phase != Phase::ApplyExtrinsic(index as u32) {
+            continue;
+        }
+        if let node_runtime::Event::System(node_runtime::SystemEvent::ExtrinsicFailed(error, _)) =
+            record.event
+        {
+            return Err(substrate_subxt::Error::Runtime(RuntimeError::Other(
+                format!("{:?}", error),
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// The `kind` label of a packet message in the packet metrics.
+fn packet_kind(type_url: &str) -> Option<&'static str> {
+    match type_url {
+        recv_packet::TYPE_URL => Some("recv"),
+        acknowledgement::TYPE_URL => Some("ack"),
+        timeout::TYPE_URL | timeout_on_close::TYPE_URL => Some("timeout"),
+        _ => None,
+    }
+}
+
+/// The `error` label of a failed extrinsic in the failure metrics.
+fn error_kind(e: &substrate_subxt::Error) -> &'static str {
+    match e {
+        substrate_subxt::Error::Runtime(_) => "dispatch",
+        _ if exhausts_resources(e) => "exhausts_resources",
+        substrate_subxt::Error::Rpc(_) => "rpc",
+        _ => "other",
+    }
+}
+
+/// Whether the transaction pool rejected an extrinsic for exceeding the block weight or length.
+fn exhausts_resources(e: &substrate_subxt::Error) -> bool {
+    e.to_string().contains("exhaust the block limits")
+}
@@ -0,0 +1,62 @@
+//! Supervision of the relayer's long-running tasks, and its shutdown on SIGINT/SIGTERM.
+use async_std::channel::{self, Receiver};
+use async_std::task::JoinHandle;
+use futures::future::{self, FutureExt};
+use log::{error, info, warn};
+use std::error::Error;
+use std::future::Future;
+use std::panic::AssertUnwindSafe;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+/// How long a failed task waits before it is started again.
+const RESTART_DELAY: Duration = Duration::from_secs(5);
+
+/// Install the SIGINT/SIGTERM handler. `recv` on the returned receiver, or any clone of it,
+/// returns once the relayer is asked to shut down.
+///
+/// A second signal exits at once, without waiting for the pending messages.
+pub fn shutdown_signal() -> Result<Receiver<()>, Box<dyn Error>> {
+    let (tx, rx) = channel::bounded(1);
+    let signalled = AtomicBool::new(false);
+    ctrlc::set_handler(move || {
+        if signalled.swap(true, Ordering::SeqCst) {
+            warn!("forced shutdown");
+            std::process::exit(1);
+        }
+        info!("shutting down, signal again to force it");
+        tx.close();
+    })?;
+    Ok(rx)
+}
+
+/// Spawn a task running the future `make_task` returns, and a new one whenever that fails or
+/// panics, until one returns `Ok` or `shutdown` fires.
+pub fn spawn<F, Fut>(
+    name: String,
+    mut make_task: F,
+    shutdown: Option<Receiver<()>>,
+) -> JoinHandle<()>
+where
+    F: FnMut() -> Fut + Send + 'static,
+    Fut: Future<Output = Result<(), Box<dyn Error>>> + Send + 'static,
+{
+    async_std::task::spawn(async move {
+        let supervised = async move {
+            loop {
+                match AssertUnwindSafe(make_task()).catch_unwind().await {
+                    Ok(Ok(())) => return,
+                    Ok(Err(e)) => error!("[{}] task failed, restarting; error = {}", name, e),
+                    Err(_) => error!("[{}] task panicked, restarting", name),
+                }
+                async_std::task::sleep(RESTART_DELAY).await;
+            }
+        };
+        match shutdown {
+            Some(shutdown) => {
+                future::select(Box::pin(supervised), Box::pin(shutdown.recv())).await;
+            }
+            None => supervised.await,
+        }
+    })
+}
@@ -0,0 +1,231 @@
+//! ICS-002 client updates of the GRANDPA light client of a chain, skipping to the blocks that
+//! change its authority set instead of replaying every block.
+//!
+//! The light client checks a header's justification against the authority set it tracks, and
+//! takes the next set from the header's authorities proof. Only the last block of each set the
+//! client has not seen yet, which the next set's justifications can not be checked without, and
+//! the block relayed are therefore submitted, each with its `grandpa_proveFinality`
+//! justification. The block relayed is the latest justified one, as the path of
+//! `GrandpaRpcExt::update_path` ends with.
+//!
+//! With `on_demand` client updates, the update to the block relayed is held back until a message
+//! proven at that block is sent, and goes out right before it.
+use crate::get_dummy_account_id;
+use async_std::channel::{self, Receiver, Sender};
+use calls::NodeRuntime as Runtime;
+use ibc::ics02_client::header::AnyHeader;
+use ibc::ics02_client::msgs::update_client::{self, MsgUpdateAnyClient};
+use ibc::ics10_grandpa::header::Header as GRANDPAHeader;
+use ibc::ics24_host::identifier::ClientId;
+use ibc::signer::Signer;
+use log::info;
+use serde_derive::Deserialize;
+use sp_core::{storage::StorageKey, H256};
+use sp_finality_grandpa::{GRANDPA_AUTHORITIES_KEY, GRANDPA_ENGINE_ID};
+use sp_trie::StorageProof;
+use std::error::Error;
+use std::str::FromStr;
+use substrate_subxt::{BlockNumber, Client};
+use tendermint_proto::Protobuf;
+
+/// How the light clients are kept up to date.
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub struct ClientUpdateConfig {
+    /// Update a client only when a message proven at the block relayed needs it, instead of with
+    /// every finalized block.
+    #[serde(default)]
+    pub on_demand: bool,
+    /// How often, in seconds, an `on_demand` client is updated while no message needs it, so it
+    /// does not fall too far behind; `0` never.
+    #[serde(default = "default_refresh_interval")]
+    pub refresh_interval: u64,
+}
+
+fn default_refresh_interval() -> u64 {
+    3600
+}
+
+impl Default for ClientUpdateConfig {
+    fn default() -> Self {
+        ClientUpdateConfig {
+            on_demand: false,
+            refresh_interval: default_refresh_interval(),
+        }
+    }
+}
+
+/// When the light client is updated to the block relayed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UpdateMode {
+    /// At once.
+    Eager,
+    /// Right before the first message proven at the block relayed is sent.
+    OnDemand,
+}
+
+/// The update of the light client of a chain, on its counterparty, to the block relayed.
+pub struct ClientUpdate<'a> {
+    chain_name: &'a str,
+    tx: &'a Sender<pallet_ibc::Any>,
+    client: &'a Client<Runtime>,
+    client_identifier: &'a str,
+    latest_height: u32,
+    block_number: u32,
+    /// The justified blocks the client is updated through, ending with the block relayed.
+    path: Vec<(u32, Vec<u8>)>,
+    /// The messages proven at the block relayed, held back until `flush`, if on demand.
+    held: Option<(Sender<pallet_ibc::Any>, Receiver<pallet_ibc::Any>)>,
+    updated: bool,
+}
+
+impl<'a> ClientUpdate<'a> {
+    /// Start relaying block `block_number` of `client` to a light client at `latest_height`,
+    /// which is updated through `path` as `mode` says.
+    pub async fn start(
+        chain_name: &'a str,
+        tx: &'a Sender<pallet_ibc::Any>,
+        client: &'a Client<Runtime>,
+        client_identifier: &'a str,
+        latest_height: u32,
+        block_number: u32,
+        path: Vec<(u32, Vec<u8>)>,
+        mode: UpdateMode,
+    ) -> Result<ClientUpdate<'a>, Box<dyn Error>> {
+        let mut update = ClientUpdate {
+            chain_name,
+            tx,
+            client,
+            client_identifier,
+            latest_height,
+            block_number,
+            path,
+            held: None,
+            updated: false,
+        };
+        match mode {
+            UpdateMode::Eager => update.ensure_updated().await?,
+            UpdateMode::OnDemand => update.held = Some(channel::unbounded()),
+        }
+        Ok(update)
+    }
+
+    /// The sender of the messages proven at the block relayed.
+    pub fn sender(&self) -> &Sender<pallet_ibc::Any> {
+        match &self.held {
+            Some((tx, _)) => tx,
+            None => self.tx,
+        }
+    }
+
+    /// Send the messages held back, after the update they need.
+    pub async fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        let mut messages = vec![];
+        if let Some((_, rx)) = &self.held {
+            while let Ok(message) = rx.try_recv() {
+                messages.push(message);
+            }
+        }
+        if messages.is_empty() {
+            return Ok(());
+        }
+        self.ensure_updated().await?;
+        for message in messages {
+            self.tx.send(message).await?;
+        }
+        Ok(())
+    }
+
+    /// Whether the client was updated to the block relayed.
+    pub fn updated(&self) -> bool {
+        self.updated
+    }
+
+    async fn ensure_updated(&mut self) -> Result<(), Box<dyn Error>> {
+        if self.latest_height < self.block_number {
+            info!(
+                "[{}] updating client from {} to {} through {:?}",
+                self.chain_name,
+                self.latest_height,
+                self.block_number,
+                self.path
+                    .iter()
+                    .map(|(height, _)| height)
+                    .collect::<Vec<_>>()
+            );
+            for (height, justification) in self.path.drain(..) {
+                send_header(
+                    self.tx,
+                    self.client,
+                    self.client_identifier,
+                    height,
+                    justification,
+                )
+                .await?;
+            }
+            self.latest_height = self.block_number;
+            self.updated = true;
+        }
+        Ok(())
+    }
+}
+
+async fn block_hash(client: &Client<Runtime>, height: u32) -> Result<H256, Box<dyn Error>> {
+    Ok(client
+        .block_hash(Some(BlockNumber::from(height)))
+        .await?
+        .ok_or("block not found")?)
+}
+
+/// Send the update of `client_identifier` to the header of block `height`, with `justification`.
+async fn send_header(
+    tx: &Sender<pallet_ibc::Any>,
+    client: &Client<Runtime>,
+    client_identifier: &str,
+    height: u32,
+    justification: Vec<u8>,
+) -> Result<(), Box<dyn Error>> {
+    let header = grandpa_header(client, height, Some(justification)).await?;
+    let msg = MsgUpdateAnyClient::new(
+        ClientId::from_str(client_identifier).unwrap(),
+        AnyHeader::Grandpa(header),
+        Signer::new(get_dummy_account_id().to_string()),
+    );
+    tx.send(pallet_ibc::Any {
+        type_url: update_client::TYPE_URL.to_string(),
+        value: msg.encode_vec().unwrap(),
+    })
+    .await?;
+    Ok(())
+}
+
+/// The GRANDPA light client header of block `height` of `client`, with `justification` or else
+/// the one the node stored for the block, if any.
+pub async fn grandpa_header(
+    client: &Client<Runtime>,
+    height: u32,
+    justification: Option<Vec<u8>>,
+) -> Result<GRANDPAHeader, Box<dyn Error>> {
+    let hash = block_hash(client, height).await?;
+    let signed_block = client.block(Some(hash)).await?.ok_or("block not found")?;
+    let authorities_proof = client
+        .read_proof(
+            vec![StorageKey(GRANDPA_AUTHORITIES_KEY.to_vec())],
+            Some(hash),
+        )
+        .await?;
+    let justification = match justification {
+        Some(justification) => Some(justification),
+        None => signed_block
+            .justifications
+            .and_then(|j| j.into_justification(GRANDPA_ENGINE_ID)),
+    };
+    Ok(GRANDPAHeader {
+        height: signed_block.block.header.number.into(),
+        commitment_root: signed_block.block.header.state_root,
+        block_hash: signed_block.block.header.hash(),
+        justification,
+        authorities_proof: StorageProof::new(
+            authorities_proof.proof.into_iter().map(|b| b.0).collect(),
+        ),
+    })
+}
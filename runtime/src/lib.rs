@@ -6,6 +6,9 @@
 #[cfg(feature = "std")]
 include!(concat!(env!("OUT_DIR"), "/wasm_binary.rs"));
 
+#[cfg(test)]
+mod tests;
+
 use pallet_grandpa::fg_primitives;
 use pallet_grandpa::{AuthorityId as GrandpaId, AuthorityList as GrandpaAuthorityList};
 use sp_api::impl_runtime_apis;
@@ -33,9 +36,11 @@ pub use frame_support::{
 	},
 	StorageValue,
 };
+pub use frame_system::Event as SystemEvent;
 pub use pallet_balances::Call as BalancesCall;
 pub use pallet_timestamp::Call as TimestampCall;
 use pallet_transaction_payment::CurrencyAdapter;
+pub use pallet_utility::Call as UtilityCall;
 #[cfg(any(feature = "std", test))]
 pub use sp_runtime::BuildStorage;
 pub use sp_runtime::{Perbill, Permill};
@@ -56,6 +61,12 @@ use sp_runtime::transaction_validity::TransactionPriority;
 use beefy_primitives::{crypto::AuthorityId as BeefyId, ValidatorSet};
 use sp_runtime::traits::Keccak256;
 
+// `::` because the `ibc` module below holds the runtime API helpers.
+use ::ibc::ics04_channel::{channel::Order, packet::Packet};
+use ::ibc::ics24_host::identifier::{ChannelId, PortId};
+use frame_support::PalletId;
+use sp_runtime::{DispatchError, DispatchResult};
+
 /// Import the template pallet.
 pub use pallet_template;
 
@@ -547,9 +558,18 @@ impl pallet_mmr::Config for Runtime {
 	const INDEXING_PREFIX: &'static [u8] = b"mmr";
 	type Hashing = Keccak256;
 	type Hash = MmrHash;
-	type LeafData = frame_system::Pallet<Self>;
+	type LeafData = MmrLeaf;
 	type OnNewRoot = DepositLog;
-	type WeightInfo = ();
+	type WeightInfo = MmrWeightInfo;
+}
+
+/// The weight of appending a leaf, with the storage pallet-mmr-leaf reads and writes to build it.
+pub struct MmrWeightInfo;
+impl pallet_mmr::WeightInfo for MmrWeightInfo {
+	fn on_initialize(peaks: u64) -> Weight {
+		<() as pallet_mmr::WeightInfo>::on_initialize(peaks)
+			.saturating_add(MmrLeaf::leaf_data_weight())
+	}
 }
 
 parameter_types! {
@@ -580,12 +600,127 @@ impl pallet_beefy::Config for Runtime {
 	type BeefyId = BeefyId;
 }
 
+impl pallet_mmr_leaf::Config for Runtime {}
+
+/// The application modules pallet-ibc routes channel handshakes and packets to.
+enum IbcModule {
+	Transfer,
+	Template,
+}
+
+impl IbcModule {
+	fn bound_to(port_id: &PortId) -> Result<Self, DispatchError> {
+		if port_id.as_str() == pallet_ibc_transfer::PORT_ID {
+			Ok(IbcModule::Transfer)
+		} else if TemplateModule::is_bound(port_id) {
+			Ok(IbcModule::Template)
+		} else {
+			Err(DispatchError::Other("no module bound to port"))
+		}
+	}
+}
+
+/// Routes the channel handshakes and packets of pallet-ibc to application modules by port.
+pub struct IbcModules;
+
+impl pallet_ibc::ModuleCallbacks for IbcModules {
+	fn on_chan_open_init(port_id: &PortId, order: &Order, version: &str) -> DispatchResult {
+		match IbcModule::bound_to(port_id)? {
+			IbcModule::Transfer => IbcTransfer::on_chan_open(order, version),
+			IbcModule::Template => TemplateModule::on_chan_open_init(order, version),
+		}
+	}
+
+	fn on_chan_open_try(
+		port_id: &PortId,
+		order: &Order,
+		counterparty_version: &str,
+	) -> DispatchResult {
+		match IbcModule::bound_to(port_id)? {
+			IbcModule::Transfer => IbcTransfer::on_chan_open(order, counterparty_version),
+			IbcModule::Template => TemplateModule::on_chan_open_try(order, counterparty_version),
+		}
+	}
+
+	fn on_chan_open_ack(
+		port_id: &PortId,
+		channel_id: &ChannelId,
+		counterparty_version: &str,
+	) -> DispatchResult {
+		match IbcModule::bound_to(port_id)? {
+			IbcModule::Transfer => IbcTransfer::on_chan_open_ack(counterparty_version),
+			IbcModule::Template =>
+				TemplateModule::on_chan_open_ack(port_id, channel_id, counterparty_version),
+		}
+	}
+
+	fn on_chan_open_confirm(port_id: &PortId, channel_id: &ChannelId) -> DispatchResult {
+		match IbcModule::bound_to(port_id)? {
+			IbcModule::Transfer => Ok(()),
+			IbcModule::Template => TemplateModule::on_chan_open_confirm(port_id, channel_id),
+		}
+	}
+
+	fn on_recv_packet(packet: &Packet) -> Vec<u8> {
+		match IbcModule::bound_to(&packet.destination_port) {
+			Ok(IbcModule::Transfer) => IbcTransfer::on_recv_packet(packet),
+			Ok(IbcModule::Template) => TemplateModule::on_recv_packet(packet),
+			// Acknowledge in the ICS-20 format, so that a sending transfer module refunds.
+			Err(_) => pallet_ibc_transfer::packet::Acknowledgement::error("unknown port").encode(),
+		}
+	}
+
+	fn on_acknowledgement_packet(packet: &Packet, acknowledgement: &[u8]) -> DispatchResult {
+		match IbcModule::bound_to(&packet.source_port)? {
+			IbcModule::Transfer => IbcTransfer::on_acknowledgement_packet(packet, acknowledgement),
+			IbcModule::Template =>
+				TemplateModule::on_acknowledgement_packet(packet, acknowledgement),
+		}
+	}
+
+	fn on_timeout_packet(packet: &Packet) -> DispatchResult {
+		match IbcModule::bound_to(&packet.source_port)? {
+			IbcModule::Transfer => IbcTransfer::on_timeout_packet(packet),
+			IbcModule::Template => TemplateModule::on_timeout_packet(packet),
+		}
+	}
+}
+
+/// Configure pallet-ibc.
+impl pallet_ibc::Config for Runtime {
+	type Event = Event;
+	type ModuleCallbacks = IbcModules;
+}
+
+parameter_types! {
+	pub const IbcTransferPalletId: PalletId = PalletId(*b"ibc/tran");
+	pub const NativeDenom: &'static str = "UNIT";
+}
+
+/// Configure the ICS-20 transfer module. Vouchers are minted in `Assets`.
+impl pallet_ibc_transfer::Config for Runtime {
+	type Event = Event;
+	type Currency = Balances;
+	type NativeDenom = NativeDenom;
+	type AssetId = u32;
+	type Assets = Assets;
+	type PalletId = IbcTransferPalletId;
+}
+
 impl pallet_sudo::Config for Runtime {
 	type Event = Event;
 	type Call = Call;
 }
 
-/// Configure the pallet-template in pallets/template.
+/// The relayer wraps its `deliver` calls in `batch_all`, so that messages failing to dispatch
+/// change nothing.
+impl pallet_utility::Config for Runtime {
+	type Event = Event;
+	type Call = Call;
+	type WeightInfo = pallet_utility::weights::SubstrateWeight<Runtime>;
+}
+
+/// Configure the pallet-template in pallets/template, an IBC module on the ports it binds.
 impl pallet_template::Config for Runtime {
 	type Event = Event;
 }
@@ -613,8 +748,12 @@ construct_runtime!(
 		Assets: pallet_assets::{Pallet, Call, Storage, Event<T>},
 		Mmr: pallet_mmr::{Pallet, Storage},
 		Beefy: pallet_beefy::{Pallet, Config<T>},
+		MmrLeaf: pallet_mmr_leaf::{Pallet, Call, Storage, Inherent},
+		Ibc: pallet_ibc::{Pallet, Call, Storage, Config, Event<T>},
+		IbcTransfer: pallet_ibc_transfer::{Pallet, Call, Storage, Event<T>},
 		// Include the custom logic from the pallet-template in the runtime.
 		TemplateModule: pallet_template::{Pallet, Call, Storage, Event<T>},
+		Utility: pallet_utility::{Pallet, Call, Event},
 	}
 );
 
@@ -657,6 +796,18 @@ mod mmr {
 	pub type Hashing = <Runtime as pallet_mmr::Config>::Hashing;
 }
 
+/// IBC runtime API helper types.
+mod ibc {
+	use sp_std::prelude::*;
+
+	pub use pallet_ibc_rpc_runtime_api::IbcStorageEntry;
+
+	/// Pair a value read from pallet-ibc storage with the key it lives under.
+	pub fn entry(key: Vec<u8>, value: Option<Vec<u8>>) -> Option<IbcStorageEntry> {
+		value.map(|value| IbcStorageEntry { key, value })
+	}
+}
+
 impl_runtime_apis! {
 	impl sp_api::Core<Block> for Runtime {
 		fn version() -> RuntimeVersion {
@@ -864,6 +1015,116 @@ impl_runtime_apis! {
 		}
 	}
 
+	impl pallet_ibc_rpc_runtime_api::IbcApi<Block> for Runtime {
+		fn client_state(client_id: Vec<u8>) -> Option<ibc::IbcStorageEntry> {
+			ibc::entry(
+				pallet_ibc::ClientStates::<Runtime>::hashed_key_for(&client_id),
+				pallet_ibc::ClientStates::<Runtime>::try_get(&client_id).ok(),
+			)
+		}
+
+		fn consensus_state(client_id: Vec<u8>, height: Vec<u8>) -> Option<ibc::IbcStorageEntry> {
+			// All consensus states of a client are stored under a single key, so the value is the
+			// whole stored list, which the proof covers, and the verifier picks the one at `height`.
+			let consensus_states = pallet_ibc::ConsensusStates::<Runtime>::get(&client_id);
+			let found =
+				consensus_states.iter().any(|(consensus_height, _)| *consensus_height == height);
+			ibc::entry(
+				pallet_ibc::ConsensusStates::<Runtime>::hashed_key_for(&client_id),
+				found.then(|| consensus_states.encode()),
+			)
+		}
+
+		fn connection(connection_id: Vec<u8>) -> Option<ibc::IbcStorageEntry> {
+			ibc::entry(
+				pallet_ibc::Connections::<Runtime>::hashed_key_for(&connection_id),
+				pallet_ibc::Connections::<Runtime>::try_get(&connection_id).ok(),
+			)
+		}
+
+		fn connections() -> Vec<(Vec<u8>, ibc::IbcStorageEntry)> {
+			pallet_ibc::Connections::<Runtime>::iter()
+				.map(|(connection_id, value)| {
+					let key = pallet_ibc::Connections::<Runtime>::hashed_key_for(&connection_id);
+					(connection_id, ibc::IbcStorageEntry { key, value })
+				})
+				.collect()
+		}
+
+		fn channel(port_id: Vec<u8>, channel_id: Vec<u8>) -> Option<ibc::IbcStorageEntry> {
+			ibc::entry(
+				pallet_ibc::Channels::<Runtime>::hashed_key_for(&port_id, &channel_id),
+				pallet_ibc::Channels::<Runtime>::try_get(&port_id, &channel_id).ok(),
+			)
+		}
+
+		fn channels() -> Vec<(Vec<u8>, Vec<u8>, ibc::IbcStorageEntry)> {
+			pallet_ibc::Channels::<Runtime>::iter()
+				.map(|(port_id, channel_id, value)| {
+					let key = pallet_ibc::Channels::<Runtime>::hashed_key_for(&port_id, &channel_id);
+					(port_id, channel_id, ibc::IbcStorageEntry { key, value })
+				})
+				.collect()
+		}
+
+		fn packet_commitment(
+			port_id: Vec<u8>,
+			channel_id: Vec<u8>,
+			sequence: u64,
+		) -> Option<ibc::IbcStorageEntry> {
+			let path = (port_id, channel_id);
+			ibc::entry(
+				pallet_ibc::PacketCommitment::<Runtime>::hashed_key_for(&path, sequence),
+				pallet_ibc::PacketCommitment::<Runtime>::try_get(&path, sequence).ok(),
+			)
+		}
+
+		fn packet_commitments(
+			port_id: Vec<u8>,
+			channel_id: Vec<u8>,
+		) -> Vec<(u64, ibc::IbcStorageEntry)> {
+			let path = (port_id, channel_id);
+			pallet_ibc::PacketCommitment::<Runtime>::iter_prefix(&path)
+				.map(|(sequence, value)| {
+					let key = pallet_ibc::PacketCommitment::<Runtime>::hashed_key_for(&path, sequence);
+					(sequence, ibc::IbcStorageEntry { key, value })
+				})
+				.collect()
+		}
+
+		fn packet_acknowledgement(
+			port_id: Vec<u8>,
+			channel_id: Vec<u8>,
+			sequence: u64,
+		) -> Option<ibc::IbcStorageEntry> {
+			let path = (port_id, channel_id);
+			ibc::entry(
+				pallet_ibc::Acknowledgements::<Runtime>::hashed_key_for(&path, sequence),
+				pallet_ibc::Acknowledgements::<Runtime>::try_get(&path, sequence).ok(),
+			)
+		}
+
+		fn packet_receipt(
+			port_id: Vec<u8>,
+			channel_id: Vec<u8>,
+			sequence: u64,
+		) -> ibc::IbcStorageEntry {
+			let path = (port_id, channel_id);
+			ibc::IbcStorageEntry {
+				key: pallet_ibc::PacketReceipt::<Runtime>::hashed_key_for(&path, sequence),
+				value: pallet_ibc::PacketReceipt::<Runtime>::try_get(&path, sequence)
+					.unwrap_or_default(),
+			}
+		}
+
+		fn next_sequence_recv(port_id: Vec<u8>, channel_id: Vec<u8>) -> Option<ibc::IbcStorageEntry> {
+			ibc::entry(
+				pallet_ibc::NextSequenceRecv::<Runtime>::hashed_key_for(&port_id, &channel_id),
+				pallet_ibc::NextSequenceRecv::<Runtime>::try_get(&port_id, &channel_id).ok(),
+			)
+		}
+	}
+
 	impl beefy_primitives::BeefyApi<Block> for Runtime {
 		fn validator_set() -> ValidatorSet<BeefyId> {
 			Beefy::validator_set()
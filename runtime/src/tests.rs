@@ -0,0 +1,78 @@
+use crate::{mmr, Hash, Mmr, MmrLeaf, Origin, Runtime, System};
+use frame_support::{assert_ok, traits::OnInitialize};
+use pallet_mmr::primitives::runtime_decl_for_MmrApi::MmrApi;
+use sp_core::offchain::{testing::TestOffchainExt, OffchainDbExt, OffchainWorkerExt};
+
+/// Start block `number` with parent hash `[number; 32]`, appending its leaf, and set its parent
+/// state root to `[0xa0 + number; 32]`.
+fn new_block() {
+	let number = System::block_number() + 1;
+	System::initialize(
+		&number,
+		&Hash::repeat_byte(number as u8),
+		&Default::default(),
+		frame_system::InitKind::Full,
+	);
+	Mmr::on_initialize(number);
+	assert_ok!(MmrLeaf::set_parent_state_root(
+		Origin::none(),
+		Hash::repeat_byte(0xa0 + number as u8)
+	));
+}
+
+/// Externalities with the offchain database the MMR nodes are indexed in, after `blocks` blocks.
+fn ext_with_blocks(blocks: u32) -> sp_io::TestExternalities {
+	let mut ext: sp_io::TestExternalities = frame_system::GenesisConfig::default()
+		.build_storage::<Runtime>()
+		.unwrap()
+		.into();
+	ext.execute_with(|| {
+		for _ in 0..blocks {
+			new_block();
+		}
+	});
+	ext.persist_offchain_overlay();
+	let (offchain, _offchain_state) = TestOffchainExt::with_offchain_db(ext.offchain_db());
+	ext.register_extension(OffchainDbExt::new(offchain.clone()));
+	ext.register_extension(OffchainWorkerExt::new(offchain));
+	ext
+}
+
+#[test]
+fn generated_proofs_verify_through_the_runtime_api() {
+	ext_with_blocks(7).execute_with(|| {
+		let root = Mmr::mmr_root_hash();
+		for leaf_index in 0..7 {
+			let (leaf, proof) = Runtime::generate_proof(leaf_index).unwrap();
+			let decoded: mmr::Leaf = leaf.clone().into_opaque_leaf().try_decode().unwrap();
+			// The leaf of block `n`, at index `n`, is appended in block `n + 1`, and commits the
+			// state root of block `n - 1`, which block `n` set.
+			assert_eq!(
+				decoded.parent_number_and_hash,
+				(leaf_index as u32, Hash::repeat_byte(leaf_index as u8 + 1))
+			);
+			let parent_state_root = match leaf_index {
+				0 => Hash::zero(),
+				n => Hash::repeat_byte(0xa0 + n as u8),
+			};
+			assert_eq!(decoded.parent_state_root, parent_state_root);
+
+			assert_eq!(Runtime::verify_proof(leaf.clone(), proof.clone()), Ok(()));
+			assert_eq!(Runtime::verify_proof_stateless(root, leaf, proof), Ok(()));
+		}
+	});
+}
+
+#[test]
+fn tampered_leaves_do_not_verify_through_the_runtime_api() {
+	ext_with_blocks(7).execute_with(|| {
+		let root = Mmr::mmr_root_hash();
+		let (leaf, proof) = Runtime::generate_proof(3).unwrap();
+		let mut decoded: mmr::Leaf = leaf.into_opaque_leaf().try_decode().unwrap();
+		decoded.parent_state_root = Hash::zero();
+		let leaf = mmr::EncodableOpaqueLeaf::from_leaf(&decoded);
+
+		assert!(Runtime::verify_proof(leaf.clone(), proof.clone()).is_err());
+		assert!(Runtime::verify_proof_stateless(root, leaf, proof).is_err());
+	});
+}